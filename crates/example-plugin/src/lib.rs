@@ -0,0 +1,45 @@
+//! Example component plugin implementing the `atrmnl:plugin` world.
+//!
+//! Build it with `cargo build -p example-plugin --target wasm32-wasip2` and
+//! reference `target/wasm32-wasip2/debug/example_plugin.wasm` from a `wasm`
+//! plugin entry.
+
+use serde_json::{Value, json};
+
+mod bindings {
+    #![allow(clippy::too_many_arguments, reason = "generated code")]
+    wit_bindgen::generate!({ path: "../server/wit", world: "plugin" });
+}
+
+use bindings::{
+    Error, Guest, Output, RenderContext,
+    atrmnl::plugin::types::{Content, Templated},
+};
+
+struct Greeter;
+
+impl Guest for Greeter {
+    fn generate(config: String, ctx: RenderContext) -> Result<Output, Error> {
+        let config: Value =
+            serde_json::from_str(&config).map_err(|e| Error::Misconfigured(e.to_string()))?;
+        let name = config["name"]
+            .as_str()
+            .ok_or_else(|| Error::Misconfigured("`name` is required".into()))?;
+        let content = match config["template"].as_str() {
+            Some(template) => Content::Templated(Templated {
+                template: template.into(),
+                data: json!({ "name": name, "device": ctx.device }).to_string(),
+            }),
+            None => Content::Html(format!(
+                "<p>Hello {name} from {} ({}x{})</p>",
+                ctx.device, ctx.width, ctx.height
+            )),
+        };
+        Ok(Output {
+            content,
+            refresh_interval: Some(900),
+        })
+    }
+}
+
+bindings::export!(Greeter with_types_in bindings);
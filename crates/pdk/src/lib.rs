@@ -0,0 +1,296 @@
+//! Typed access to the host functions the atrmnl server provides to WASM plugins.
+//!
+//! ```ignore
+//! use atrmnl_pdk::{kv, log, prelude::*};
+//!
+//! #[plugin_fn]
+//! pub fn generate(_config: String) -> FnResult<String> {
+//!     let ctx = atrmnl_pdk::context()?;
+//!     let renders = kv::get("renders")?.map_or(0, |n| n.parse().unwrap_or(0)) + 1;
+//!     kv::set("renders", &renders.to_string())?;
+//!     log::info(&format!("rendering for {}", ctx.device));
+//!     Ok(format!("<p>{renders} renders at {}x{}</p>", ctx.width, ctx.height))
+//! }
+//! ```
+//!
+//! Every call runs in a fresh instance of the plugin, so statics start over
+//! each time; anything that should persist belongs in [`kv`].
+
+use std::{collections::HashMap, time::Duration};
+
+use extism_pdk::{Error, Json};
+use serde::{Deserialize, Serialize};
+
+pub use extism_pdk;
+
+pub mod prelude {
+    pub use extism_pdk::{FnResult, Json, plugin_fn};
+}
+
+mod host {
+    use extism_pdk::{Json, host_fn};
+
+    use super::{Chart, Context, FetchRequest, FetchResponse, KvEntry, LogRecord, Qr};
+
+    #[host_fn]
+    extern "ExtismHost" {
+        pub fn atrmnl_kv_get(key: String) -> Json<Option<String>>;
+        pub fn atrmnl_kv_set(entry: Json<KvEntry>);
+        pub fn atrmnl_kv_delete(key: String);
+        pub fn atrmnl_context() -> Json<Context>;
+        pub fn atrmnl_log(record: Json<LogRecord>);
+        pub fn atrmnl_fetch(request: Json<FetchRequest>) -> Json<FetchResponse>;
+        pub fn atrmnl_secret(name: String) -> Json<Option<String>>;
+        pub fn atrmnl_chart(chart: Json<Chart>) -> String;
+        pub fn atrmnl_qr(qr: Json<Qr>) -> String;
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Context {
+    pub device: String,
+    pub view: String,
+    pub width: u32,
+    pub height: u32,
+    pub timezone: String,
+    pub locale: String,
+}
+
+/// Returns the device and view the plugin is currently rendering for.
+pub fn context() -> Result<Context, Error> {
+    unsafe { host::atrmnl_context() }.map(|Json(ctx)| ctx)
+}
+
+/// Looks up `ATRMNL_SECRET_<PLUGIN>__<NAME>` in the server's environment, where
+/// `<PLUGIN>` is the plugin's configured name. Both names may only contain
+/// letters, digits and single underscores between them; other names fail.
+pub fn secret(name: &str) -> Result<Option<String>, Error> {
+    unsafe { host::atrmnl_secret(name.to_owned()) }.map(|Json(secret)| secret)
+}
+
+#[derive(Serialize)]
+struct KvEntry {
+    key: String,
+    value: String,
+}
+
+/// Key-value store shared by all renders of the plugin.
+pub mod kv {
+    use extism_pdk::{Error, Json};
+
+    use super::{KvEntry, host};
+
+    pub fn get(key: &str) -> Result<Option<String>, Error> {
+        unsafe { host::atrmnl_kv_get(key.to_owned()) }.map(|Json(value)| value)
+    }
+
+    pub fn set(key: &str, value: &str) -> Result<(), Error> {
+        unsafe {
+            host::atrmnl_kv_set(Json(KvEntry {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }))
+        }
+    }
+
+    pub fn delete(key: &str) -> Result<(), Error> {
+        unsafe { host::atrmnl_kv_delete(key.to_owned()) }
+    }
+}
+
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    level: &'a str,
+    message: &'a str,
+}
+
+/// Writes to the server log, tagged with the plugin's name.
+pub mod log {
+    use extism_pdk::Json;
+
+    use super::{LogRecord, host};
+
+    fn log(level: &str, message: &str) {
+        // Logging is best effort; a failure here should never fail a render.
+        let _ = unsafe { host::atrmnl_log(Json(LogRecord { level, message })) };
+    }
+
+    pub fn debug(message: &str) {
+        log("debug", message);
+    }
+
+    pub fn info(message: &str) {
+        log("info", message);
+    }
+
+    pub fn warn(message: &str) {
+        log("warn", message);
+    }
+
+    pub fn error(message: &str) {
+        log("error", message);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FetchRequest {
+    url: String,
+    headers: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FetchResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl FetchResponse {
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+/// Starts a GET request that goes through the server's HTTP client and
+/// response cache.
+pub fn fetch(url: impl Into<String>) -> FetchRequest {
+    FetchRequest {
+        url: url.into(),
+        ..FetchRequest::default()
+    }
+}
+
+impl FetchRequest {
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// How long a successful response may be served from the cache.
+    #[must_use]
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl_secs = Some(ttl.as_secs());
+        self
+    }
+
+    pub fn send(self) -> Result<FetchResponse, Error> {
+        unsafe { host::atrmnl_fetch(Json(self)) }.map(|Json(resp)| resp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartKind {
+    #[default]
+    Line,
+    Area,
+    Bar,
+    Sparkline,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Chart {
+    kind: ChartKind,
+    width: u32,
+    height: u32,
+    series: Vec<Vec<f64>>,
+    labels: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<f64>,
+    unit: String,
+}
+
+/// Starts a chart the server renders as inline SVG, drawn in black with
+/// crisp edges and hatch patterns so it stays sharp on e-ink.
+pub fn chart(kind: ChartKind, width: u32, height: u32) -> Chart {
+    Chart {
+        kind,
+        width,
+        height,
+        ..Chart::default()
+    }
+}
+
+impl Chart {
+    /// Adds a series of values. Further series are drawn dashed or with a
+    /// different hatch.
+    #[must_use]
+    pub fn series(mut self, values: impl IntoIterator<Item = f64>) -> Self {
+        self.series.push(values.into_iter().collect());
+        self
+    }
+
+    /// Labels along the x axis, one per value.
+    #[must_use]
+    pub fn labels<S: Into<String>>(mut self, labels: impl IntoIterator<Item = S>) -> Self {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Fixes the y axis instead of fitting it to the values.
+    #[must_use]
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    /// Appended to the values on the y axis, such as `"°"`.
+    #[must_use]
+    pub fn unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = unit.into();
+        self
+    }
+
+    pub fn svg(self) -> Result<String, Error> {
+        unsafe { host::atrmnl_chart(Json(self)) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QrCorrection {
+    Low,
+    #[default]
+    Medium,
+    Quartile,
+    High,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Qr {
+    data: String,
+    size: u32,
+    correction: QrCorrection,
+}
+
+/// Starts a QR code of `data` the server renders as inline SVG, at most
+/// `size` pixels wide. Every module covers whole pixels so the code stays
+/// sharp on e-ink.
+pub fn qr(data: impl Into<String>, size: u32) -> Qr {
+    Qr {
+        data: data.into(),
+        size,
+        correction: QrCorrection::default(),
+    }
+}
+
+impl Qr {
+    /// How much of the code may be damaged or covered and still scan.
+    /// Higher levels make the code denser.
+    #[must_use]
+    pub const fn correction(mut self, correction: QrCorrection) -> Self {
+        self.correction = correction;
+        self
+    }
+
+    /// Fails if the data does not fit in a QR code, or the code does not
+    /// fit in `size` pixels.
+    pub fn svg(self) -> Result<String, Error> {
+        unsafe { host::atrmnl_qr(Json(self)) }
+    }
+}
@@ -0,0 +1,47 @@
+//! Compares compiling a WASM plugin for every render with instantiating a
+//! module compiled once, as `plugins::wasm` does.
+//!
+//! Run with `cargo bench -p server --bench wasm_instances`.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const RUNS: u32 = 200;
+const CONFIG: &str = r#"{"city":"Zurich"}"#;
+
+fn time(mut render: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        render();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "plugins", "echo.wat"]
+        .iter()
+        .collect();
+    let manifest = extism::Manifest::new([extism::Wasm::file(path)]);
+
+    let compiling = time(|| {
+        extism::Plugin::new(&manifest, [], true)
+            .expect("Sample plugin instantiates")
+            .call::<&str, String>("generate", CONFIG)
+            .expect("Sample plugin runs");
+    });
+
+    let compiled = extism::PluginBuilder::new(manifest.clone())
+        .with_wasi(true)
+        .compile()
+        .expect("Sample plugin compiles");
+    let instantiated = time(|| {
+        extism::Plugin::new_from_compiled(&compiled)
+            .expect("Sample plugin instantiates")
+            .call::<&str, String>("generate", CONFIG)
+            .expect("Sample plugin runs");
+    });
+
+    println!("compiling: {compiling:?}/render, instantiated: {instantiated:?}/render");
+}
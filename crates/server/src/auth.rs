@@ -0,0 +1,56 @@
+use base64::Engine;
+use subtle::ConstantTimeEq;
+
+// Compares secrets without leaking how much of them matched through timing.
+fn matches(given: &str, expected: &str) -> bool {
+    given.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+// Whether an `Authorization` header is `Bearer <token>`.
+pub fn bearer(authorization: Option<&str>, token: &str) -> bool {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| matches(given, token))
+}
+
+// Whether an `Authorization` header is HTTP Basic auth with `password` and any
+// user name.
+pub fn basic(authorization: Option<&str>, password: &str) -> bool {
+    authorization
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .is_some_and(|credentials| {
+            credentials
+                .split_once(':')
+                .is_some_and(|(_, given)| matches(given, password))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_requires_the_exact_token() {
+        assert!(bearer(Some("Bearer s3cret"), "s3cret"));
+        assert!(!bearer(Some("Bearer s3cre"), "s3cret"));
+        assert!(!bearer(Some("Bearer s3crets"), "s3cret"));
+        assert!(!bearer(Some("s3cret"), "s3cret"));
+        assert!(!bearer(None, "s3cret"));
+    }
+
+    #[test]
+    fn basic_checks_only_the_password() {
+        // "mum:fridge", "dad:fridge" and "mum:oven".
+        assert!(basic(Some("Basic bXVtOmZyaWRnZQ=="), "fridge"));
+        assert!(basic(Some("Basic ZGFkOmZyaWRnZQ=="), "fridge"));
+        assert!(!basic(Some("Basic bXVtOm92ZW4="), "fridge"));
+        assert!(!basic(Some("Basic not base64"), "fridge"));
+        assert!(!basic(Some("Bearer fridge"), "fridge"));
+    }
+}
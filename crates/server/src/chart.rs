@@ -0,0 +1,510 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sailfish::runtime::{Buffer, Render, RenderError};
+use serde::Deserialize;
+
+const FONT_SIZE: f64 = 14.0;
+// Rough advance of a digit at `FONT_SIZE`, used to reserve room for labels.
+const CHAR_WIDTH: f64 = 8.5;
+// Lines of further series are told apart by their dashes.
+const DASHES: [&str; 3] = ["", "6 4", "2 3"];
+const PATTERNS: usize = 4;
+// The scale aims for three to five ticks; the cap only matters where huge
+// values swallow the step, so that adding it no longer moves the tick.
+const MAX_TICKS: u8 = 10;
+
+// Keeps pattern ids unique when a page embeds several charts.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    #[default]
+    Line,
+    Area,
+    Bar,
+    // A bare line without axes or labels, sized to sit next to a value.
+    Sparkline,
+}
+
+// A chart rendered as inline SVG for e-ink panels: black on white, crisp
+// edges and hatch patterns instead of colors or shades of grey.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chart {
+    #[serde(default)]
+    pub kind: Kind,
+    pub width: u32,
+    pub height: u32,
+    pub series: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    // Appended to the values on the y axis.
+    #[serde(default)]
+    pub unit: String,
+}
+
+struct Plot {
+    left: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+    lo: f64,
+    hi: f64,
+}
+
+impl Plot {
+    fn y(&self, value: f64) -> f64 {
+        ((self.hi - value.clamp(self.lo, self.hi)) / (self.hi - self.lo))
+            .mul_add(self.height, self.top)
+    }
+
+    fn bottom(&self) -> f64 {
+        self.top + self.height
+    }
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    reason = "charts hold far fewer points than f64 represents exactly"
+)]
+const fn float(n: usize) -> f64 {
+    n as f64
+}
+
+// 1, 2 or 5 times a power of ten, at least `raw`.
+fn nice_step(raw: f64) -> f64 {
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = match raw / magnitude {
+        n if n <= 1.0 => 1.0,
+        n if n <= 2.0 => 2.0,
+        n if n <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+    step * magnitude
+}
+
+fn format_value(value: f64) -> String {
+    let text = format!("{value:.2}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    // Rounding can leave "-0" behind.
+    if text == "-0" { "0" } else { text }.to_owned()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn pattern(svg: &mut String, id: &str, index: usize) {
+    let (size, marks) = match index % PATTERNS {
+        0 => (
+            6,
+            r#"<path d="M0,6 L6,0 M-1,1 L1,-1 M5,7 L7,5" stroke="black"/>"#,
+        ),
+        1 => (6, r#"<path d="M0,0 L6,6 M6,0 L0,6" stroke="black"/>"#),
+        2 => (4, r#"<rect width="2" height="2" fill="black"/>"#),
+        _ => (4, r#"<rect width="4" height="1" fill="black"/>"#),
+    };
+    let _ = write!(
+        svg,
+        r#"<pattern id="{id}-{index}" width="{size}" height="{size}" patternUnits="userSpaceOnUse">{marks}</pattern>"#
+    );
+}
+
+impl Chart {
+    pub const fn new(kind: Kind, width: u32, height: u32) -> Self {
+        Self {
+            kind,
+            width,
+            height,
+            series: Vec::new(),
+            labels: Vec::new(),
+            min: None,
+            max: None,
+            unit: String::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_series(mut self, values: impl IntoIterator<Item = f64>) -> Self {
+        self.series.push(values.into_iter().collect());
+        self
+    }
+
+    // Labels along the x axis, one per value. They are thinned out when
+    // they would overlap.
+    #[must_use]
+    pub fn with_labels<S: Into<String>>(mut self, labels: impl IntoIterator<Item = S>) -> Self {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    #[must_use]
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = unit.into();
+        self
+    }
+
+    // The value range to plot and the ticks on the y axis. Bounds that are
+    // not configured are rounded outwards to the nearest tick.
+    fn scale(&self) -> Option<(f64, f64, Vec<f64>)> {
+        let (data_lo, data_hi) = self.series.iter().flatten().filter(|v| v.is_finite()).fold(
+            (None, None),
+            |(lo, hi): (Option<f64>, Option<f64>), &v| {
+                (
+                    Some(lo.map_or(v, |lo| lo.min(v))),
+                    Some(hi.map_or(v, |hi| hi.max(v))),
+                )
+            },
+        );
+        let lo = self.min.or(data_lo)?;
+        let mut hi = self.max.or(data_hi)?;
+        if hi <= lo {
+            // At huge magnitudes adding one is lost to rounding.
+            hi = (lo + 1.0).max(lo.next_up());
+        }
+        if self.kind == Kind::Sparkline {
+            return Some((lo, hi, Vec::new()));
+        }
+        let step = nice_step((hi - lo) / 3.0);
+        let lo = self.min.unwrap_or_else(|| (lo / step).floor() * step);
+        let hi = self.max.unwrap_or_else(|| (hi / step).ceil() * step);
+        let first = (lo / step).ceil() * step;
+        let mut ticks: Vec<_> = (0..MAX_TICKS)
+            .map(|i| f64::from(i).mul_add(step, first))
+            .take_while(|t| *t <= hi + step / 1000.0)
+            .collect();
+        ticks.dedup();
+        Some((lo, hi, ticks))
+    }
+
+    fn points(&self) -> usize {
+        self.series.iter().map(Vec::len).max().unwrap_or_default()
+    }
+
+    fn x(&self, plot: &Plot, index: usize) -> f64 {
+        let n = self.points();
+        if self.kind == Kind::Bar {
+            plot.left + (float(index) + 0.5) * plot.width / float(n)
+        } else if n <= 1 {
+            plot.left + plot.width / 2.0
+        } else {
+            plot.left + float(index) * plot.width / float(n - 1)
+        }
+    }
+
+    fn polyline(&self, plot: &Plot, values: &[f64]) -> String {
+        values
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_finite())
+            .map(|(i, &v)| format!("{:.1},{:.1}", self.x(plot, i), plot.y(v)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn plot(&self, lo: f64, hi: f64, ticks: &[f64]) -> Plot {
+        let (width, height) = (f64::from(self.width), f64::from(self.height));
+        if self.kind == Kind::Sparkline {
+            // Leaves room for the dot on the last value.
+            return Plot {
+                left: 3.0,
+                top: 3.0,
+                width: (width - 6.0).max(1.0),
+                height: (height - 6.0).max(1.0),
+                lo,
+                hi,
+            };
+        }
+        let tick_chars = ticks
+            .iter()
+            .map(|t| format_value(*t).chars().count() + self.unit.chars().count())
+            .max()
+            .unwrap_or_default();
+        let label_chars = self
+            .labels
+            .iter()
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or_default();
+        let left = float(tick_chars).mul_add(CHAR_WIDTH, 6.0);
+        // Labels are centred on the outermost points and need room to spill.
+        let right = if self.kind == Kind::Bar {
+            2.0
+        } else {
+            (float(label_chars) * CHAR_WIDTH / 2.0).max(2.0)
+        };
+        let top = FONT_SIZE / 2.0;
+        let bottom = if self.labels.is_empty() {
+            2.0
+        } else {
+            FONT_SIZE + 6.0
+        };
+        Plot {
+            left,
+            top,
+            width: (width - left - right).max(1.0),
+            height: (height - top - bottom).max(1.0),
+            lo,
+            hi,
+        }
+    }
+
+    fn axes(&self, svg: &mut String, plot: &Plot, ticks: &[f64]) {
+        let right = plot.left + plot.width;
+        for tick in ticks {
+            let y = plot.y(*tick);
+            let _ = write!(
+                svg,
+                r#"<line x1="{:.1}" y1="{y:.1}" x2="{right:.1}" y2="{y:.1}" stroke="black" stroke-dasharray="1 3"/><text x="{:.1}" y="{y:.1}" text-anchor="end" dominant-baseline="middle">{}{}</text>"#,
+                plot.left,
+                plot.left - 6.0,
+                format_value(*tick),
+                escape(&self.unit),
+            );
+        }
+        let _ = write!(
+            svg,
+            r#"<line x1="{:.1}" y1="{bottom:.1}" x2="{right:.1}" y2="{bottom:.1}" stroke="black" stroke-width="2"/>"#,
+            plot.left,
+            bottom = plot.bottom(),
+        );
+        if self.labels.is_empty() {
+            return;
+        }
+        let label_chars = self
+            .labels
+            .iter()
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or_default();
+        let slot = float(label_chars).mul_add(CHAR_WIDTH, CHAR_WIDTH);
+        let fit = (plot.width / slot).floor().max(1.0);
+        let every = (float(self.labels.len()) / fit).ceil().max(1.0);
+        for (i, label) in self.labels.iter().enumerate().take(self.points()) {
+            if float(i) % every != 0.0 {
+                continue;
+            }
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+                self.x(plot, i),
+                plot.bottom() + FONT_SIZE + 2.0,
+                escape(label),
+            );
+        }
+    }
+
+    fn bars(&self, svg: &mut String, plot: &Plot, id: &str) {
+        let slot = plot.width / float(self.points());
+        let width = slot * 0.7 / float(self.series.len());
+        let base = plot.y(0.0);
+        for (s, values) in self.series.iter().enumerate() {
+            for (i, v) in values.iter().enumerate().filter(|(_, v)| v.is_finite()) {
+                let x = float(s).mul_add(width, slot.mul_add(-0.35, self.x(plot, i)));
+                let y = plot.y(*v);
+                let _ = write!(
+                    svg,
+                    r#"<rect x="{x:.1}" y="{:.1}" width="{width:.1}" height="{:.1}" fill="url(#{id}-{s})" stroke="black"/>"#,
+                    y.min(base),
+                    (y - base).abs(),
+                );
+            }
+        }
+    }
+
+    fn lines(&self, svg: &mut String, plot: &Plot, id: &str) {
+        for (s, values) in self.series.iter().enumerate() {
+            let points = self.polyline(plot, values);
+            if points.is_empty() {
+                continue;
+            }
+            if self.kind == Kind::Area {
+                let first = values
+                    .iter()
+                    .position(|v| v.is_finite())
+                    .unwrap_or_default();
+                let last = values
+                    .iter()
+                    .rposition(|v| v.is_finite())
+                    .unwrap_or_default();
+                let _ = write!(
+                    svg,
+                    r#"<polygon points="{points} {:.1},{bottom:.1} {:.1},{bottom:.1}" fill="url(#{id}-{s})"/>"#,
+                    self.x(plot, last),
+                    self.x(plot, first),
+                    bottom = plot.bottom(),
+                );
+            }
+            let dashes = match DASHES[s % DASHES.len()] {
+                "" => String::new(),
+                dashes => format!(r#" stroke-dasharray="{dashes}""#),
+            };
+            let _ = write!(
+                svg,
+                r#"<polyline points="{points}" fill="none" stroke="black" stroke-width="2"{dashes}/>"#
+            );
+        }
+        if self.kind == Kind::Sparkline {
+            let last = self.series.first().and_then(|values| {
+                values
+                    .iter()
+                    .enumerate()
+                    .rfind(|(_, v)| v.is_finite())
+                    .map(|(i, v)| (self.x(plot, i), plot.y(*v)))
+            });
+            if let Some((x, y)) = last {
+                let _ = write!(
+                    svg,
+                    r#"<circle cx="{x:.1}" cy="{y:.1}" r="3" fill="black"/>"#
+                );
+            }
+        }
+    }
+
+    pub fn svg(&self) -> String {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" shape-rendering="crispEdges" font-size="{FONT_SIZE}">"#,
+            w = self.width,
+            h = self.height,
+        );
+        if let Some((lo, hi, ticks)) = self.scale() {
+            let plot = self.plot(lo, hi, &ticks);
+            let id = format!("chart{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+            if matches!(self.kind, Kind::Area | Kind::Bar) {
+                svg.push_str("<defs>");
+                for s in 0..self.series.len() {
+                    pattern(&mut svg, &id, s);
+                }
+                svg.push_str("</defs>");
+            }
+            if self.kind != Kind::Sparkline {
+                self.axes(&mut svg, &plot, &ticks);
+            }
+            if self.kind == Kind::Bar {
+                self.bars(&mut svg, &plot, &id);
+            } else {
+                self.lines(&mut svg, &plot, &id);
+            }
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+// Lets templates embed a chart with `<%- chart %>`.
+impl Render for Chart {
+    fn render(&self, b: &mut Buffer) -> Result<(), RenderError> {
+        b.push_str(&self.svg());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_rounds_to_ticks() {
+        let chart = Chart::new(Kind::Line, 400, 100).with_series([12.3, 18.9, 15.0]);
+        let (lo, hi, ticks) = chart.scale().expect("Chart has data");
+        assert_eq!((lo, hi), (10.0, 20.0));
+        assert_eq!(ticks, [10.0, 15.0, 20.0]);
+
+        let mut chart = chart;
+        chart.min = Some(0.0);
+        let (lo, hi, ticks) = chart.scale().expect("Chart has data");
+        assert_eq!((lo, hi), (0.0, 20.0));
+        assert_eq!(ticks, [0.0, 10.0, 20.0]);
+    }
+
+    #[test]
+    fn scale_handles_flat_and_missing_data() {
+        assert!(Chart::new(Kind::Line, 400, 100).scale().is_none());
+        let chart = Chart::new(Kind::Sparkline, 100, 20).with_series([f64::NAN, 4.0, 4.0]);
+        let (lo, hi, ticks) = chart.scale().expect("Chart has data");
+        assert_eq!((lo, hi), (4.0, 5.0));
+        assert!(ticks.is_empty());
+    }
+
+    #[test]
+    fn scale_ends_for_huge_and_nearly_equal_values() {
+        let lo = 2f64.powi(59);
+        for series in [[lo, lo + 128.0], [lo, lo], [f64::MAX, f64::MAX / 2.0]] {
+            let chart = Chart::new(Kind::Line, 400, 100).with_series(series);
+            let (_, _, ticks) = chart.scale().expect("Chart has data");
+            assert!(ticks.len() <= usize::from(MAX_TICKS));
+            assert!(chart.svg().starts_with("<svg"));
+        }
+    }
+
+    #[test]
+    fn format_value_trims_zeros() {
+        assert_eq!(format_value(20.0), "20");
+        assert_eq!(format_value(2.5), "2.5");
+        assert_eq!(format_value(0.25), "0.25");
+        assert_eq!(format_value(-0.001), "0");
+    }
+
+    #[test]
+    fn svg_avoids_grey() {
+        let chart = Chart::new(Kind::Area, 400, 100)
+            .with_series([1.0, 3.0, 2.0])
+            .with_labels(["a", "b", "<c>"])
+            .with_unit("°");
+        let svg = chart.svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"shape-rendering="crispEdges""#));
+        assert!(svg.contains("<pattern"));
+        assert!(svg.contains("&lt;c&gt;"));
+        assert!(svg.contains("3°"));
+        assert!(!svg.contains("opacity"));
+        assert!(!svg.contains("gray") && !svg.contains("grey"));
+    }
+
+    #[test]
+    fn svg_draws_each_kind() {
+        let chart = |kind| {
+            Chart::new(kind, 200, 80)
+                .with_series([1.0, -2.0, 3.0])
+                .with_series([2.0, 2.0, 2.0])
+                .svg()
+        };
+        let line = chart(Kind::Line);
+        assert_eq!(line.matches("<polyline").count(), 2);
+        assert!(line.contains("stroke-dasharray=\"6 4\""));
+        assert_eq!(chart(Kind::Bar).matches("<rect").count(), 6);
+        assert_eq!(chart(Kind::Area).matches("<polygon").count(), 2);
+        let sparkline = chart(Kind::Sparkline);
+        assert!(sparkline.contains("<circle"));
+        assert!(!sparkline.contains("<text"));
+    }
+
+    #[test]
+    fn labels_are_thinned_out() {
+        let hours: Vec<_> = (0..24).map(|h| format!("{h:02}")).collect();
+        let svg = Chart::new(Kind::Line, 200, 80)
+            .with_series((0..24).map(f64::from))
+            .with_labels(hours)
+            .svg();
+        let labels = svg.matches(r#"text-anchor="middle""#).count();
+        assert!((2..24).contains(&labels));
+    }
+
+    #[test]
+    fn deserializes_from_json() {
+        let chart: Chart = serde_json::from_str(
+            r#"{"kind": "bar", "width": 300, "height": 120, "series": [[1, 2, 3]]}"#,
+        )
+        .expect("Valid chart JSON");
+        assert_eq!(chart.kind, Kind::Bar);
+        assert!(chart.labels.is_empty());
+    }
+}
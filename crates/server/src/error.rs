@@ -14,7 +14,9 @@ pub trait IntoCanonical {
 pub enum Canonical {
     AlreadyExists,
     NotFound,
+    Unauthenticated,
     PermissionDenied,
+    ResourceExhausted,
     InvalidArgument,
     FailedPrecondition,
     DeadlineExceeded,
@@ -42,10 +44,18 @@ impl IntoResponse for Canonical {
                 StatusCode::NOT_FOUND,
                 pages::not_found("The requested resource does not exist."),
             ),
+            Self::Unauthenticated => (
+                StatusCode::UNAUTHORIZED,
+                pages::error("Who goes there?", "The request lacks valid credentials."),
+            ),
             Self::PermissionDenied => (
                 StatusCode::FORBIDDEN,
                 pages::error("Nope. Can't do", "The resource is inaccessible."),
             ),
+            Self::ResourceExhausted => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                pages::error("That's a lot", "The request is too large."),
+            ),
             Self::InvalidArgument => (
                 StatusCode::BAD_REQUEST,
                 pages::bad_request("Well be better next time."),
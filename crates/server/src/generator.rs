@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use axum::response::IntoResponse;
 use futures::future::BoxFuture;
 use http::StatusCode;
@@ -28,6 +30,14 @@ pub enum Error {
     Misconfigured,
     #[error("WASM plugin error: {0}")]
     Wasm(String),
+    #[error("the WASM plugin exceeded its time limit")]
+    WasmTimeout,
+    #[error("the WASM plugin exceeded its memory limit")]
+    WasmOutOfMemory,
+    #[error("the WASM plugin is not allowed to access {0}")]
+    WasmHostDenied(String),
+    #[error("template error: {0}")]
+    Template(String),
     #[error("an unknown error occurred")]
     Unknown,
 }
@@ -98,6 +108,31 @@ impl IntoResponse for Error {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 pages::error("WASM plugin error", msg.as_str()),
             ),
+            Self::WasmTimeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                pages::error(
+                    "WASM plugin took too long",
+                    "The plugin was stopped because it exceeded its time limit.",
+                ),
+            ),
+            Self::WasmOutOfMemory => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                pages::error(
+                    "WASM plugin ran out of memory",
+                    "The plugin was stopped because it exceeded its memory limit.",
+                ),
+            ),
+            Self::WasmHostDenied(target) => (
+                StatusCode::FORBIDDEN,
+                pages::error(
+                    "WASM plugin request blocked",
+                    &format!("The plugin is not allowed to access {target}."),
+                ),
+            ),
+            Self::Template(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                pages::error("Template error", msg.as_str()),
+            ),
             Self::Unknown => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 pages::internal_error("It's unclear what happened, but it was not good."),
@@ -128,8 +163,77 @@ impl IntoResponse for SetupError {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Full,
+    HalfVertical,
+    Quadrant,
+}
+
+impl View {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::HalfVertical => "half_vertical",
+            Self::Quadrant => "quadrant",
+        }
+    }
+
+    pub const fn size(self) -> (u32, u32) {
+        match self {
+            Self::Full => (800, 480),
+            Self::HalfVertical => (400, 480),
+            Self::Quadrant => (400, 240),
+        }
+    }
+
+    // How many rows `row_height` pixels high fit above each other in the view.
+    pub const fn rows(self, row_height: u32) -> usize {
+        (self.size().1 / row_height) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderContext {
+    pub device: String,
+    pub view: View,
+    pub timezone: String,
+    pub locale: String,
+}
+
+impl RenderContext {
+    pub fn new(device: impl Into<String>) -> Self {
+        Self {
+            device: device.into(),
+            view: View::Full,
+            timezone: "UTC".into(),
+            locale: "en".into(),
+        }
+    }
+
+    pub fn with_region(self, timezone: Option<&str>, locale: Option<&str>) -> Self {
+        Self {
+            timezone: timezone.map_or(self.timezone, Into::into),
+            locale: locale.map_or(self.locale, Into::into),
+            ..self
+        }
+    }
+
+    pub fn with_view(&self, view: View) -> Self {
+        Self {
+            view,
+            ..self.clone()
+        }
+    }
+}
+
 pub trait Content {
-    fn generate(&self) -> BoxFuture<'_, Result<String, Error>>;
+    fn generate<'a>(&'a self, ctx: &'a RenderContext) -> BoxFuture<'a, Result<String, Error>>;
+
+    // How soon the device should come back, as last requested by the content.
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -188,6 +292,55 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
 
+    #[test]
+    fn error_wasm_timeout_into_response() {
+        let resp = Error::WasmTimeout.into_response();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn error_wasm_out_of_memory_into_response() {
+        let resp = Error::WasmOutOfMemory.into_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn error_wasm_host_denied_into_response() {
+        let resp = Error::WasmHostDenied("http://192.0.2.1/".into()).into_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn error_template_into_response() {
+        let resp = Error::Template("unknown variable".into()).into_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn render_context_defaults_to_full_view() {
+        let ctx = RenderContext::new("device1");
+        assert_eq!(ctx.device, "device1");
+        assert_eq!(ctx.view, View::Full);
+        assert_eq!(ctx.with_view(View::Quadrant).view, View::Quadrant);
+    }
+
+    #[test]
+    fn render_context_region_overrides_defaults() {
+        let ctx = RenderContext::new("device1").with_region(Some("Europe/Zurich"), None);
+        assert_eq!(ctx.timezone, "Europe/Zurich");
+        assert_eq!(ctx.locale, "en");
+        let quadrant = ctx.with_view(View::Quadrant);
+        assert_eq!(quadrant.timezone, "Europe/Zurich");
+        assert_eq!(quadrant.view.size(), (400, 240));
+    }
+
+    #[test]
+    fn view_rows_fit_the_height() {
+        assert_eq!(View::Full.rows(80), 6);
+        assert_eq!(View::HalfVertical.rows(36), 13);
+        assert_eq!(View::Quadrant.rows(48), 5);
+    }
+
     #[test]
     fn error_unknown_into_response() {
         let resp = Error::Unknown.into_response();
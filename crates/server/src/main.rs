@@ -12,18 +12,23 @@ use clap::Parser;
 use eyre::{WrapErr, eyre};
 use log::info;
 
+mod auth;
+mod chart;
 mod device;
 mod error;
 mod generator;
 mod net;
 mod pages;
 mod plugins;
+mod qr;
 mod resource;
 mod serve;
 mod storage;
+#[cfg(test)]
+mod testing;
 
 #[derive(Parser)]
-#[command(rename_all = "snake_case")]
+#[command(rename_all = "snake_case", subcommand_negates_reqs = true)]
 struct Args {
     #[arg(short, long, default_value_t = 8223, help = "Port to listen on.")]
     port: u16,
@@ -42,14 +47,72 @@ struct Args {
     )]
     show_request_details: bool,
 
+    #[arg(
+        long,
+        help = "Directory with one folder per installed plugin, each holding a plugin.toml."
+    )]
+    plugins_dir: Option<PathBuf>,
+
     #[arg(
         long,
         help = "Override default chromium based browser profile directory."
     )]
     user_dir: Option<PathBuf>,
 
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Makes /preview/{id} reload whenever a WASM plugin module changes."
+    )]
+    dev: bool,
+
     #[command(flatten)]
     tls: TlsArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+#[command(rename_all = "snake_case")]
+enum Command {
+    #[command(about = "Prints a devices.toml entry for an exported TRMNL private plugin.")]
+    ImportTrmnl {
+        #[arg(help = "Path to the exported plugin archive (.zip).")]
+        archive: PathBuf,
+
+        #[arg(
+            long,
+            default_value = "my-device",
+            help = "Device to add the plugin to."
+        )]
+        device: String,
+
+        #[arg(
+            long,
+            help = "Name to reference the plugin by. Derived from the plugin's name by default."
+        )]
+        name: Option<String>,
+    },
+}
+
+impl Command {
+    fn run(self) -> color_eyre::Result<()> {
+        match self {
+            Self::ImportTrmnl {
+                archive,
+                device,
+                name,
+            } => {
+                let path = archive.canonicalize().unwrap_or(archive);
+                let plugin = plugins::template::archive::Archive::open(&path)
+                    .wrap_err_with(|| format!("Failed to import {}", path.display()))?;
+                let name = name.unwrap_or_else(|| plugin.default_name());
+                println!("{}", plugin.config_entry(&device, &name, &path));
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(clap::Args)]
@@ -75,6 +138,9 @@ async fn main() -> color_eyre::Result<()> {
         .init();
 
     let args = Args::parse();
+    if let Some(command) = args.command {
+        return command.run();
+    }
     resource::init_self(args.port, !args.tls.nouse_tls);
 
     let tls = if args.tls.nouse_tls {
@@ -93,17 +159,27 @@ async fn main() -> color_eyre::Result<()> {
         )
     };
 
+    let catalog = args
+        .plugins_dir
+        .map(|dir| plugins::catalog::Catalog::discover(&dir))
+        .transpose()
+        .wrap_err("While trying to discover installed plugins")?
+        .unwrap_or_default();
+    let storage = Arc::new(
+        storage::Storage::load(args.devices_file, catalog)
+            .await
+            .wrap_err("While trying to load local device file")?,
+    );
+    let reloads = plugins::reload::watch(storage.plugins());
+    plugins::refresh::schedule(storage.device_plugins());
     let state = serve::ServerState {
-        storage: Arc::new(
-            storage::Storage::load(args.devices_file)
-                .await
-                .wrap_err("While trying to load local device file")?,
-        ),
+        storage,
         renderer: Arc::new(
             blender::Instance::new(args.user_dir)
                 .await
                 .wrap_err("Failed to initialize browser renderer")?,
         ),
+        reloads: args.dev.then_some(reloads),
     };
 
     let addr = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), args.port);
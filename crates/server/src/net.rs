@@ -1,4 +1,7 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
 
 pub trait Retryable {
     fn should_retry(&self) -> bool;
@@ -10,6 +13,12 @@ impl Retryable for reqwest::Error {
     }
 }
 
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+pub fn client() -> &'static reqwest::Client {
+    &CLIENT
+}
+
 pub async fn retry<F, Fut, R, E>(
     factory: F,
     initial_backoff: Duration,
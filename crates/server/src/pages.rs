@@ -1,6 +1,9 @@
 use axum::response::Html;
+use chrono::{DateTime, Utc};
 use sailfish::TemplateOnce;
 
+use crate::plugins::{board::Message, format::age};
+
 #[derive(TemplateOnce)]
 #[template(path = "pages/index.stpl")]
 struct IndexTemplate<'a> {
@@ -24,6 +27,14 @@ struct ErrorTemplate<'a> {
 #[template(path = "pages/test_screen.stpl")]
 struct TestScreenTemplate;
 
+#[derive(TemplateOnce)]
+#[template(path = "pages/board.stpl")]
+struct BoardTemplate<'a> {
+    name: &'a str,
+    messages: &'a [Message],
+    now: DateTime<Utc>,
+}
+
 pub fn index(inner: &str) -> Html<String> {
     Html(
         IndexTemplate { inner }
@@ -60,6 +71,18 @@ pub fn internal_error(details: &str) -> Html<String> {
     error("I'm terribly sorry, but something happened", details)
 }
 
+pub fn board(name: &str, messages: &[Message], now: DateTime<Utc>) -> Html<String> {
+    index(
+        &BoardTemplate {
+            name,
+            messages,
+            now,
+        }
+        .render_once()
+        .expect("board template render failed"),
+    )
+}
+
 pub fn test_screen() -> String {
     TestScreenTemplate
         .render_once()
@@ -71,6 +94,7 @@ pub fn home() -> Html<String> {
         "<h1>Welcome to Awesome TRMNL.</h1>",
         "<p>Do you have a TRMNL device? Point it at me.</p>",
         "<p>Or see a <a href=\"/preview/test\">test preview</a>.</p>",
+        "<p>Installed plugins are listed <a href=\"/plugins\">here</a>.</p>",
     ))
 }
 
@@ -1,12 +1,35 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
 
 use futures::future::BoxFuture;
 use weather::Detail;
 
-use crate::{generator, pages, storage};
+use crate::{
+    generator::{self, RenderContext},
+    pages, storage,
+};
 
+pub mod astronomy;
+pub mod board;
+pub mod calendar;
+pub mod catalog;
+pub mod clock;
+pub mod feed;
+pub mod file;
+pub mod format;
+pub mod home_assistant;
 pub mod mashup;
+pub mod note;
+pub mod photos;
+pub mod push;
+pub mod qr;
+pub mod refresh;
+pub mod reload;
+pub mod sensor;
+pub mod state;
+pub mod template;
 pub mod ticktick;
+pub mod transit;
+pub mod uptime;
 pub mod wasm;
 pub mod weather;
 
@@ -23,11 +46,152 @@ pub enum PluginConfig {
         detail: Detail,
     },
     TestScreen,
+    Calendar {
+        name: Option<String>,
+        sources: Vec<calendar::Source>,
+        #[serde(default)]
+        layout: calendar::Layout,
+        days: Option<u32>,
+    },
+    Tasks {
+        name: Option<String>,
+        sources: Vec<calendar::Source>,
+    },
+    Feed {
+        name: Option<String>,
+        urls: Vec<url::Url>,
+        limit: Option<usize>,
+    },
+    File {
+        name: Option<String>,
+        path: std::path::PathBuf,
+        format: Option<file::Format>,
+        layout: Option<file::Layout>,
+        #[serde(default)]
+        columns: Vec<String>,
+        title: Option<String>,
+        markup: Option<template::Markup>,
+        stale_after_mins: Option<u64>,
+        limit: Option<usize>,
+    },
+    Astronomy {
+        name: Option<String>,
+        latitude: f64,
+        longitude: f64,
+    },
+    Clock {
+        name: Option<String>,
+        #[serde(default)]
+        zones: Vec<clock::ZoneConfig>,
+        interval_mins: Option<u32>,
+        lead_secs: Option<u64>,
+        #[serde(default)]
+        hour12: bool,
+    },
+    HomeAssistant {
+        name: Option<String>,
+        url: url::Url,
+        token: String,
+        entities: Vec<home_assistant::EntityConfig>,
+    },
+    Board {
+        name: Option<String>,
+        password: String,
+        limit: Option<usize>,
+        state: Option<std::path::PathBuf>,
+    },
+    Note {
+        name: Option<String>,
+        path: std::path::PathBuf,
+        title: Option<String>,
+    },
+    Photos {
+        name: Option<String>,
+        dir: std::path::PathBuf,
+        #[serde(default)]
+        order: photos::Order,
+        #[serde(default)]
+        fit: photos::Fit,
+        levels: Option<u8>,
+        recent: Option<usize>,
+        state: Option<std::path::PathBuf>,
+    },
+    Push {
+        name: Option<String>,
+        token: String,
+        markup: template::Markup,
+        #[serde(default)]
+        fields: serde_json::Map<String, serde_json::Value>,
+        history: Option<usize>,
+        max_bytes: Option<usize>,
+        state: Option<std::path::PathBuf>,
+    },
+    Qr {
+        name: Option<String>,
+        data: Option<String>,
+        wifi: Option<crate::qr::Wifi>,
+        title: Option<String>,
+        #[serde(default)]
+        correction: crate::qr::Correction,
+    },
+    Sensor {
+        name: Option<String>,
+        label: Option<String>,
+        #[serde(default)]
+        unit: String,
+        token: Option<String>,
+        path: Option<std::path::PathBuf>,
+        window_hours: Option<u64>,
+        limit: Option<usize>,
+        state: Option<std::path::PathBuf>,
+    },
+    Transit {
+        name: Option<String>,
+        feed: std::path::PathBuf,
+        stops: Vec<transit::StopConfig>,
+        realtime: Option<url::Url>,
+        limit: Option<usize>,
+        refresh_mins: Option<u64>,
+    },
+    Uptime {
+        name: Option<String>,
+        targets: Vec<uptime::TargetConfig>,
+        interval_secs: Option<u64>,
+        timeout_secs: Option<u64>,
+        history: Option<usize>,
+    },
     Wasm {
         name: String,
         path: std::path::PathBuf,
         #[serde(default)]
         config: serde_json::Value,
+        #[serde(default)]
+        limits: wasm::Limits,
+        state: Option<std::path::PathBuf>,
+        #[serde(default)]
+        templates: HashMap<String, std::path::PathBuf>,
+        refresh_secs: Option<u64>,
+    },
+    Template {
+        name: String,
+        #[serde(default)]
+        polling: Vec<template::Polling>,
+        markup: template::Markup,
+        #[serde(default)]
+        fields: serde_json::Map<String, serde_json::Value>,
+    },
+    TrmnlArchive {
+        name: String,
+        path: std::path::PathBuf,
+        #[serde(default)]
+        fields: serde_json::Map<String, serde_json::Value>,
+    },
+    Installed {
+        plugin: String,
+        name: Option<String>,
+        #[serde(default)]
+        config: serde_json::Value,
+        state: Option<std::path::PathBuf>,
     },
 }
 
@@ -37,7 +201,27 @@ impl PluginConfig {
             Self::Ticktick { .. } => String::from("ticktick"),
             Self::TestScreen => String::from("test"),
             Self::Weather { .. } => String::from("weather"),
-            Self::Wasm { name, .. } => name.clone(),
+            Self::Calendar { name, .. } => name.clone().unwrap_or_else(|| "calendar".into()),
+            Self::Tasks { name, .. } => name.clone().unwrap_or_else(|| "tasks".into()),
+            Self::Feed { name, .. } => name.clone().unwrap_or_else(|| "feed".into()),
+            Self::File { name, .. } => name.clone().unwrap_or_else(|| "file".into()),
+            Self::Note { name, .. } => name.clone().unwrap_or_else(|| "note".into()),
+            Self::Photos { name, .. } => name.clone().unwrap_or_else(|| "photos".into()),
+            Self::Clock { name, .. } => name.clone().unwrap_or_else(|| "clock".into()),
+            Self::Astronomy { name, .. } => name.clone().unwrap_or_else(|| "astronomy".into()),
+            Self::HomeAssistant { name, .. } => {
+                name.clone().unwrap_or_else(|| "home_assistant".into())
+            }
+            Self::Board { name, .. } => name.clone().unwrap_or_else(|| "board".into()),
+            Self::Push { name, .. } => name.clone().unwrap_or_else(|| "push".into()),
+            Self::Qr { name, .. } => name.clone().unwrap_or_else(|| "qr".into()),
+            Self::Sensor { name, .. } => name.clone().unwrap_or_else(|| "sensor".into()),
+            Self::Transit { name, .. } => name.clone().unwrap_or_else(|| "transit".into()),
+            Self::Uptime { name, .. } => name.clone().unwrap_or_else(|| "uptime".into()),
+            Self::Wasm { name, .. }
+            | Self::Template { name, .. }
+            | Self::TrmnlArchive { name, .. } => name.clone(),
+            Self::Installed { plugin, name, .. } => name.as_ref().unwrap_or(plugin).clone(),
         }
     }
 }
@@ -51,10 +235,28 @@ pub enum Plugin {
         client: weather::Client,
     },
     TestScreen,
+    Calendar(calendar::CalendarPlugin),
+    Tasks(calendar::tasks::TasksPlugin),
+    Feed(feed::FeedPlugin),
+    File(file::FilePlugin),
+    Note(note::NotePlugin),
+    Photos(photos::PhotosPlugin),
+    Clock(clock::ClockPlugin),
+    Astronomy(astronomy::AstronomyPlugin),
+    HomeAssistant(home_assistant::HomeAssistantPlugin),
+    Push(push::PushPlugin),
+    Qr(qr::QrPlugin),
+    Sensor(sensor::SensorPlugin),
+    Transit(transit::TransitPlugin),
+    Uptime(uptime::UptimePlugin),
+    Board(board::BoardPlugin),
     Wasm(wasm::WasmPlugin),
+    Component(wasm::component::ComponentPlugin),
+    Template(template::TemplatePlugin),
 }
 
 impl Plugin {
+    #[allow(clippy::too_many_lines, reason = "one arm per plugin")]
     pub async fn new(value: PluginConfig) -> Result<Self, storage::LoadError> {
         match value {
             PluginConfig::Ticktick { project_id, auth } => Ok(Self::Ticktick {
@@ -63,16 +265,267 @@ impl Plugin {
                 project: project_id.into(),
             }),
             PluginConfig::TestScreen => Ok(Self::TestScreen),
-            PluginConfig::Wasm {
+            PluginConfig::Calendar {
+                name: _,
+                sources,
+                layout,
+                days,
+            } => Ok(Self::Calendar(calendar::CalendarPlugin::new(
+                sources, layout, days,
+            )?)),
+            PluginConfig::Tasks { name: _, sources } => {
+                Ok(Self::Tasks(calendar::tasks::TasksPlugin::new(sources)?))
+            }
+            PluginConfig::Feed {
                 name: _,
+                urls,
+                limit,
+            } => Ok(Self::Feed(feed::FeedPlugin::new(urls, limit)?)),
+            PluginConfig::File {
+                name: _,
+                path,
+                format,
+                layout,
+                columns,
+                title,
+                markup,
+                stale_after_mins,
+                limit,
+            } => Ok(Self::File(file::FilePlugin::new(
+                path,
+                format,
+                layout,
+                columns,
+                title,
+                markup.as_ref(),
+                stale_after_mins.map(Duration::from_mins),
+                limit,
+            )?)),
+            PluginConfig::Clock {
+                name: _,
+                zones,
+                interval_mins,
+                lead_secs,
+                hour12,
+            } => Ok(Self::Clock(clock::ClockPlugin::new(
+                zones,
+                interval_mins,
+                lead_secs,
+                hour12,
+            )?)),
+            PluginConfig::Astronomy {
+                name: _,
+                latitude,
+                longitude,
+            } => Ok(Self::Astronomy(astronomy::AstronomyPlugin::new(
+                latitude, longitude,
+            )?)),
+            PluginConfig::HomeAssistant {
+                name: _,
+                url,
+                token,
+                entities,
+            } => Ok(Self::HomeAssistant(
+                home_assistant::HomeAssistantPlugin::new(url, token, entities)?,
+            )),
+            PluginConfig::Note {
+                name: _,
+                path,
+                title,
+            } => Ok(Self::Note(note::NotePlugin::new(path, title)?)),
+            PluginConfig::Photos {
+                name: _,
+                dir,
+                order,
+                fit,
+                levels,
+                recent,
+                state,
+            } => Ok(Self::Photos(photos::PhotosPlugin::new(
+                dir, order, fit, levels, recent, state,
+            )?)),
+            PluginConfig::Push {
+                name: _,
+                token,
+                markup,
+                fields,
+                history,
+                max_bytes,
+                state,
+            } => Ok(Self::Push(push::PushPlugin::new(
+                token, &markup, fields, history, max_bytes, state,
+            )?)),
+            PluginConfig::Qr {
+                name: _,
+                data,
+                wifi,
+                title,
+                correction,
+            } => Ok(Self::Qr(qr::QrPlugin::new(data, wifi, title, correction)?)),
+            PluginConfig::Sensor {
+                name: _,
+                label,
+                unit,
+                token,
+                path,
+                window_hours,
+                limit,
+                state,
+            } => Ok(Self::Sensor(sensor::SensorPlugin::new(
+                label,
+                unit,
+                token,
+                path,
+                window_hours.map(Duration::from_hours),
+                limit,
+                state,
+            )?)),
+            PluginConfig::Transit {
+                name: _,
+                feed,
+                stops,
+                realtime,
+                limit,
+                refresh_mins,
+            } => Ok(Self::Transit(transit::TransitPlugin::new(
+                &feed,
+                stops,
+                realtime,
+                limit,
+                refresh_mins,
+            )?)),
+            PluginConfig::Board {
+                name: _,
+                password,
+                limit,
+                state,
+            } => Ok(Self::Board(board::BoardPlugin::new(
+                password, limit, state,
+            )?)),
+            PluginConfig::Uptime {
+                name: _,
+                targets,
+                interval_secs,
+                timeout_secs,
+                history,
+            } => Ok(Self::Uptime(uptime::UptimePlugin::new(
+                targets,
+                interval_secs,
+                timeout_secs,
+                history,
+            )?)),
+            PluginConfig::Wasm {
+                name,
                 path,
                 config,
-            } => Ok(Self::Wasm(wasm::WasmPlugin::new(path, config)?)),
+                limits,
+                state,
+                templates,
+                refresh_secs,
+            } => {
+                if wasm::component::is_component(&path) {
+                    Ok(Self::Component(wasm::component::ComponentPlugin::new(
+                        &path, &config, limits, templates,
+                    )?))
+                } else {
+                    let plugin = wasm::WasmPlugin::new(&name, &path, config, limits, state)?;
+                    Ok(Self::Wasm(match refresh_secs {
+                        Some(secs) => plugin.with_refresh_interval(Duration::from_secs(secs)),
+                        None => plugin,
+                    }))
+                }
+            }
+            PluginConfig::Template {
+                name: _,
+                polling,
+                markup,
+                fields,
+            } => Ok(Self::Template(template::TemplatePlugin::new(
+                polling, &markup, fields,
+            )?)),
+            PluginConfig::TrmnlArchive {
+                name: _,
+                path,
+                fields,
+            } => Ok(Self::Template(
+                template::archive::Archive::open(&path)
+                    .inspect_err(|e| log::error!("Importing {}: {e}", path.display()))?
+                    .into_plugin(fields)?,
+            )),
             PluginConfig::Weather { location, detail } => Ok(Self::Weather {
                 client: weather::Client::new(location, detail)
                     .await
                     .map_err(|_| storage::LoadError::InvalidConfig)?,
             }),
+            // Resolved against the catalog by `storage` before plugins are created.
+            PluginConfig::Installed { plugin, .. } => {
+                Err(catalog::Error::NotInstalled(plugin).into())
+            }
+        }
+    }
+
+    pub fn module_path(&self) -> Option<&Path> {
+        match self {
+            Self::Wasm(plugin) => Some(plugin.path()),
+            Self::Component(plugin) => Some(plugin.path()),
+            _ => None,
+        }
+    }
+
+    pub const fn as_push(&self) -> Option<&push::PushPlugin> {
+        match self {
+            Self::Push(plugin) => Some(plugin),
+            _ => None,
+        }
+    }
+
+    pub const fn as_sensor(&self) -> Option<&sensor::SensorPlugin> {
+        match self {
+            Self::Sensor(plugin) => Some(plugin),
+            _ => None,
+        }
+    }
+
+    pub const fn as_board(&self) -> Option<&board::BoardPlugin> {
+        match self {
+            Self::Board(plugin) => Some(plugin),
+            _ => None,
+        }
+    }
+
+    // Names the plugins with background work in the log, `None` for the
+    // others.
+    pub fn refresh_label(&self) -> Option<String> {
+        match self {
+            Self::Uptime(_) => Some("uptime checks".into()),
+            _ => self.module_path().map(|path| path.display().to_string()),
+        }
+    }
+
+    pub fn refresh_schedule(&self) -> Option<Duration> {
+        match self {
+            Self::Wasm(plugin) => plugin.refresh_schedule(),
+            Self::Uptime(plugin) => Some(plugin.interval()),
+            _ => None,
+        }
+    }
+
+    pub async fn refresh(&self, ctx: &RenderContext) -> Result<(), generator::Error> {
+        match self {
+            Self::Wasm(plugin) => plugin.refresh(ctx).await,
+            Self::Uptime(plugin) => {
+                plugin.check(chrono::Utc::now()).await;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn reload(&self) -> Result<(), storage::LoadError> {
+        match self {
+            Self::Wasm(plugin) => plugin.reload(),
+            Self::Component(plugin) => plugin.reload(),
+            _ => Ok(()),
         }
     }
 }
@@ -80,7 +533,10 @@ impl Plugin {
 pub type PluginsMap = HashMap<String, Arc<Plugin>>;
 
 impl generator::Content for Plugin {
-    fn generate(&self) -> BoxFuture<'_, Result<String, generator::Error>> {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
         match self {
             Self::TestScreen => Box::pin(async { Ok(pages::test_screen()) }),
             Self::Ticktick { client, project } => Box::pin(async {
@@ -90,7 +546,33 @@ impl generator::Content for Plugin {
                     .map_err(Into::into)
             }),
             Self::Weather { client } => Box::pin(async { client.fetch_and_display().await }),
-            Self::Wasm(plugin) => plugin.generate(),
+            Self::Calendar(plugin) => plugin.generate(ctx),
+            Self::Tasks(plugin) => plugin.generate(ctx),
+            Self::Feed(plugin) => plugin.generate(ctx),
+            Self::File(plugin) => plugin.generate(ctx),
+            Self::Note(plugin) => plugin.generate(ctx),
+            Self::Photos(plugin) => plugin.generate(ctx),
+            Self::Clock(plugin) => plugin.generate(ctx),
+            Self::Astronomy(plugin) => plugin.generate(ctx),
+            Self::HomeAssistant(plugin) => plugin.generate(ctx),
+            Self::Push(plugin) => plugin.generate(ctx),
+            Self::Qr(plugin) => plugin.generate(ctx),
+            Self::Sensor(plugin) => plugin.generate(ctx),
+            Self::Transit(plugin) => plugin.generate(ctx),
+            Self::Uptime(plugin) => plugin.generate(ctx),
+            Self::Board(plugin) => plugin.generate(ctx),
+            Self::Wasm(plugin) => plugin.generate(ctx),
+            Self::Component(plugin) => plugin.generate(ctx),
+            Self::Template(plugin) => plugin.generate(ctx),
+        }
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        match self {
+            Self::Component(plugin) => plugin.refresh_interval(),
+            Self::Clock(plugin) => plugin.refresh_interval(),
+            Self::Transit(plugin) => plugin.refresh_interval(),
+            _ => None,
         }
     }
 }
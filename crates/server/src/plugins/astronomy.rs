@@ -0,0 +1,201 @@
+use chrono::{DateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use log::{error, warn};
+use sailfish::TemplateOnce;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    storage,
+};
+
+pub mod moon;
+pub mod sun;
+
+pub use moon::{Moon, Phase};
+pub use sun::Sun;
+
+const J2000: f64 = 2_451_545.0;
+
+const ICON_SUNRISE: &str = iconify::svg!("wi:sunrise", width = "32px");
+const ICON_SUNSET: &str = iconify::svg!("wi:sunset", width = "32px");
+const ICON_MOONRISE: &str = iconify::svg!("wi:moonrise", width = "32px");
+const ICON_MOONSET: &str = iconify::svg!("wi:moonset", width = "32px");
+
+fn sin(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+fn tan(degrees: f64) -> f64 {
+    degrees.to_radians().tan()
+}
+
+fn asin(x: f64) -> f64 {
+    x.asin().to_degrees()
+}
+
+fn acos(x: f64) -> f64 {
+    x.acos().to_degrees()
+}
+
+fn atan2(y: f64, x: f64) -> f64 {
+    y.atan2(x).to_degrees()
+}
+
+fn julian_day(time: DateTime<Utc>) -> f64 {
+    #[allow(clippy::cast_precision_loss, reason = "millisecond timestamps fit")]
+    let millis = time.timestamp_millis() as f64;
+    millis / 86_400_000.0 + 2_440_587.5
+}
+
+// Julian centuries since J2000.0.
+fn centuries(jd: f64) -> f64 {
+    (jd - J2000) / 36_525.0
+}
+
+// A place on Earth in degrees, north and east being positive.
+#[derive(Debug, Clone, Copy)]
+pub struct Observer {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Observer {
+    pub const fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+// When a body passes a given altitude, going up and coming down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crossings {
+    pub rise: Option<DateTime<Utc>>,
+    pub set: Option<DateTime<Utc>>,
+}
+
+struct Times {
+    rise: String,
+    set: String,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "astronomy.stpl")]
+struct AstronomyTemplate {
+    date: String,
+    daylight: Times,
+    civil: Times,
+    nautical: Times,
+    noon: String,
+    day_length: String,
+    phase: Phase,
+    illumination: u8,
+    moon: Times,
+    compact: bool,
+}
+
+pub struct AstronomyPlugin {
+    observer: Observer,
+}
+
+fn format_length(length: TimeDelta) -> String {
+    format!("{}h {:02}m", length.num_hours(), length.num_minutes() % 60)
+}
+
+impl AstronomyPlugin {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, storage::LoadError> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            error!("Coordinates {latitude}, {longitude} are out of range");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self {
+            observer: Observer::new(latitude, longitude),
+        })
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let tz = ctx.timezone.parse::<Tz>().unwrap_or_else(|_| {
+            warn!("Unknown timezone {}, showing times in UTC", ctx.timezone);
+            Tz::UTC
+        });
+        let today = now.with_timezone(&tz).date_naive();
+        let midnight = tz
+            .from_local_datetime(&today.and_time(NaiveTime::MIN))
+            .earliest()
+            .map_or(now, |t| t.with_timezone(&Utc));
+        let sun = Sun::on(self.observer, today);
+        let moon = Moon::on(self.observer, midnight, now);
+        let local = |time: Option<DateTime<Utc>>| {
+            time.map_or_else(
+                || "—".into(),
+                |t| t.with_timezone(&tz).format("%H:%M").to_string(),
+            )
+        };
+        let times = |c: Crossings| Times {
+            rise: local(c.rise),
+            set: local(c.set),
+        };
+        AstronomyTemplate {
+            date: today.format("%A, %-d %B").to_string(),
+            daylight: times(sun.daylight),
+            civil: times(sun.civil),
+            nautical: times(sun.nautical),
+            noon: local(Some(sun.noon)),
+            day_length: format_length(sun.day_length),
+            phase: moon.phase,
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                reason = "a percentage"
+            )]
+            illumination: (moon.illumination * 100.0).round() as u8,
+            moon: times(moon.horizon),
+            compact: ctx.view == View::Quadrant,
+        }
+        .render_once()
+        .expect("astronomy template render failed")
+    }
+}
+
+impl generator::Content for AstronomyPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { Ok(self.render(ctx, Utc::now())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_shows_local_times() {
+        let plugin = AstronomyPlugin::new(52.52, 13.405).expect("Valid coordinates");
+        let ctx = RenderContext::new("d").with_region(Some("Europe/Berlin"), None);
+        let now = Utc
+            .with_ymd_and_hms(2024, 6, 21, 10, 0, 0)
+            .single()
+            .expect("Valid time");
+        let html = plugin.render(&ctx, now);
+        assert!(html.contains("Friday, 21 June"));
+        assert!(html.contains("04:4"), "Sunrise around 04:43");
+        assert!(html.contains("21:3"), "Sunset around 21:33");
+        assert!(html.contains("16h 5"), "Day length around 16h 50m");
+        assert!(html.contains("Full moon") || html.contains("Waxing gibbous"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_coordinates() {
+        assert!(AstronomyPlugin::new(91.0, 0.0).is_err());
+        assert!(AstronomyPlugin::new(0.0, -181.0).is_err());
+        assert_eq!(format_length(TimeDelta::minutes(16 * 60 + 5)), "16h 05m");
+    }
+}
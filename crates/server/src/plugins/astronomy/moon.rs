@@ -0,0 +1,276 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::{Crossings, Observer, asin, atan2, centuries, cos, julian_day, sin, tan};
+
+// Moonrise and moonset are searched for at this step, then interpolated.
+const STEP: TimeDelta = TimeDelta::minutes(10);
+
+const ICON_NEW: &str = iconify::svg!("wi:moon-alt-new", width = "64px");
+const ICON_WAXING_CRESCENT: &str = iconify::svg!("wi:moon-alt-waxing-crescent-3", width = "64px");
+const ICON_FIRST_QUARTER: &str = iconify::svg!("wi:moon-alt-first-quarter", width = "64px");
+const ICON_WAXING_GIBBOUS: &str = iconify::svg!("wi:moon-alt-waxing-gibbous-3", width = "64px");
+const ICON_FULL: &str = iconify::svg!("wi:moon-alt-full", width = "64px");
+const ICON_WANING_GIBBOUS: &str = iconify::svg!("wi:moon-alt-waning-gibbous-3", width = "64px");
+const ICON_LAST_QUARTER: &str = iconify::svg!("wi:moon-alt-third-quarter", width = "64px");
+const ICON_WANING_CRESCENT: &str = iconify::svg!("wi:moon-alt-waning-crescent-3", width = "64px");
+
+// The low precision series of the Astronomical Almanac, good to a few tenths
+// of a degree. Each term is an amplitude, a phase and a rate per century.
+const LONGITUDE: [(f64, f64, f64); 6] = [
+    (6.29, 135.0, 477_198.87),
+    (-1.27, 259.3, -413_335.36),
+    (0.66, 235.7, 890_534.22),
+    (0.21, 269.9, 954_397.74),
+    (-0.19, 357.5, 35_999.05),
+    (-0.11, 186.5, 966_404.03),
+];
+const LATITUDE: [(f64, f64, f64); 4] = [
+    (5.13, 93.3, 483_202.02),
+    (0.28, 228.2, 960_400.89),
+    (-0.28, 318.3, 6003.15),
+    (-0.17, 217.6, -407_332.21),
+];
+const PARALLAX: [(f64, f64, f64); 4] = [
+    (0.0518, 135.0, 477_198.87),
+    (0.0095, 259.3, -413_335.36),
+    (0.0078, 235.7, 890_534.22),
+    (0.0028, 269.9, 954_397.74),
+];
+
+fn series(terms: &[(f64, f64, f64)], t: f64, f: fn(f64) -> f64) -> f64 {
+    terms
+        .iter()
+        .map(|&(amplitude, phase, rate)| amplitude * f(rate.mul_add(t, phase)))
+        .sum()
+}
+
+// Ecliptic longitude and latitude in degrees.
+struct Ecliptic {
+    longitude: f64,
+    latitude: f64,
+}
+
+// The moon's position and horizontal parallax at `t` centuries since J2000.
+fn position(t: f64) -> (Ecliptic, f64) {
+    let longitude = 481_267.881f64.mul_add(t, 218.32) + series(&LONGITUDE, t, sin);
+    (
+        Ecliptic {
+            longitude: longitude.rem_euclid(360.0),
+            latitude: series(&LATITUDE, t, sin),
+        },
+        0.9508 + series(&PARALLAX, t, cos),
+    )
+}
+
+// The sun's ecliptic longitude, precise enough for the phase.
+fn sun_longitude(t: f64) -> f64 {
+    let anomaly = 35_999.050f64.mul_add(t, 357.529);
+    let centre = 1.915f64.mul_add(sin(anomaly), 0.020 * sin(2.0 * anomaly));
+    (36_000.770f64.mul_add(t, 280.466) + centre).rem_euclid(360.0)
+}
+
+// Altitude of the moon's centre above the altitude at which its upper limb
+// touches the horizon.
+fn altitude(observer: Observer, at: DateTime<Utc>) -> f64 {
+    let jd = julian_day(at);
+    let t = centuries(jd);
+    let (moon, parallax) = position(t);
+    let obliquity = (-0.013f64).mul_add(t, 23.4393);
+    let ascension = atan2(
+        sin(moon.longitude).mul_add(cos(obliquity), -tan(moon.latitude) * sin(obliquity)),
+        cos(moon.longitude),
+    );
+    let declination = asin(sin(moon.latitude).mul_add(
+        cos(obliquity),
+        cos(moon.latitude) * sin(obliquity) * sin(moon.longitude),
+    ));
+    let sidereal = 360.985_647_366_29f64.mul_add(jd - super::J2000, 280.460_618_37);
+    let hour_angle = sidereal + observer.longitude - ascension;
+    let altitude = asin(sin(observer.latitude).mul_add(
+        sin(declination),
+        cos(observer.latitude) * cos(declination) * cos(hour_angle),
+    ));
+    altitude - 0.7275f64.mul_add(parallax, -0.5667)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl Phase {
+    // The named phases each span 45° of elongation, centred on the exact
+    // quarters.
+    fn from_elongation(degrees: f64) -> Self {
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            reason = "the elongation lies within [0, 360)"
+        )]
+        match ((degrees + 22.5) / 45.0) as u8 % 8 {
+            0 => Self::New,
+            1 => Self::WaxingCrescent,
+            2 => Self::FirstQuarter,
+            3 => Self::WaxingGibbous,
+            4 => Self::Full,
+            5 => Self::WaningGibbous,
+            6 => Self::LastQuarter,
+            _ => Self::WaningCrescent,
+        }
+    }
+
+    pub const fn svg(self) -> &'static str {
+        match self {
+            Self::New => ICON_NEW,
+            Self::WaxingCrescent => ICON_WAXING_CRESCENT,
+            Self::FirstQuarter => ICON_FIRST_QUARTER,
+            Self::WaxingGibbous => ICON_WAXING_GIBBOUS,
+            Self::Full => ICON_FULL,
+            Self::WaningGibbous => ICON_WANING_GIBBOUS,
+            Self::LastQuarter => ICON_LAST_QUARTER,
+            Self::WaningCrescent => ICON_WANING_CRESCENT,
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::New => "New moon",
+            Self::WaxingCrescent => "Waxing crescent",
+            Self::FirstQuarter => "First quarter",
+            Self::WaxingGibbous => "Waxing gibbous",
+            Self::Full => "Full moon",
+            Self::WaningGibbous => "Waning gibbous",
+            Self::LastQuarter => "Last quarter",
+            Self::WaningCrescent => "Waning crescent",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Moon {
+    pub phase: Phase,
+    // The illuminated fraction of the disc, from 0 to 1.
+    pub illumination: f64,
+    // Moonrise and moonset within the day, either may be missing.
+    pub horizon: Crossings,
+}
+
+impl Moon {
+    // The phase at `at`, and moonrise and moonset in the 24 hours after
+    // `from`.
+    pub fn on(observer: Observer, from: DateTime<Utc>, at: DateTime<Utc>) -> Self {
+        let t = centuries(julian_day(at));
+        let (moon, _) = position(t);
+        let elongation = (moon.longitude - sun_longitude(t)).rem_euclid(360.0);
+        let illumination = (-cos(moon.latitude)).mul_add(cos(elongation), 1.0) / 2.0;
+        Self {
+            phase: Phase::from_elongation(elongation),
+            illumination,
+            horizon: horizon(observer, from),
+        }
+    }
+}
+
+fn horizon(observer: Observer, from: DateTime<Utc>) -> Crossings {
+    let mut crossings = Crossings {
+        rise: None,
+        set: None,
+    };
+    let mut time = from;
+    let mut before = altitude(observer, time);
+    while time < from + TimeDelta::days(1) {
+        let next = time + STEP;
+        let after = altitude(observer, next);
+        if before.signum() != after.signum() {
+            #[allow(clippy::cast_possible_truncation, reason = "a fraction of the step")]
+            let offset = (STEP.as_seconds_f64() * before / (before - after)).round() as i64;
+            let at = Some(time + TimeDelta::seconds(offset));
+            if after > before {
+                crossings.rise = crossings.rise.or(at);
+            } else {
+                crossings.set = crossings.set.or(at);
+            }
+        }
+        (time, before) = (next, after);
+    }
+    crossings
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn phase(at: DateTime<Utc>) -> Moon {
+        Moon::on(Observer::new(0.0, 0.0), at, at)
+    }
+
+    // Principal phases of 2024 from the USNO tables.
+    #[test]
+    fn phases_match_reference_tables() {
+        let eclipse = phase(utc(2024, 4, 8, 18, 21));
+        assert_eq!(eclipse.phase, Phase::New);
+        assert!(eclipse.illumination < 0.01, "{}", eclipse.illumination);
+        let full = phase(utc(2024, 4, 23, 23, 49));
+        assert_eq!(full.phase, Phase::Full);
+        assert!(full.illumination > 0.99, "{}", full.illumination);
+        for quarter in [utc(2024, 4, 15, 19, 13), utc(2024, 5, 1, 11, 27)] {
+            let moon = phase(quarter);
+            assert!(
+                (moon.illumination - 0.5).abs() < 0.02,
+                "{}",
+                moon.illumination
+            );
+        }
+        assert_eq!(phase(utc(2024, 4, 15, 19, 13)).phase, Phase::FirstQuarter);
+        assert_eq!(phase(utc(2024, 5, 1, 11, 27)).phase, Phase::LastQuarter);
+        assert_eq!(phase(utc(2024, 4, 12, 0, 0)).phase, Phase::WaxingCrescent);
+        assert_eq!(phase(utc(2024, 4, 27, 0, 0)).phase, Phase::WaningGibbous);
+    }
+
+    #[test]
+    fn moon_rises_and_sets() {
+        let london = Observer::new(51.5074, -0.1278);
+        // The full moon rises around sunset and sets around sunrise.
+        let moon = Moon::on(london, utc(2024, 4, 23, 12, 0), utc(2024, 4, 24, 0, 0));
+        let rise = moon.horizon.rise.expect("Moonrise");
+        assert!(
+            (rise - utc(2024, 4, 23, 19, 10)).num_minutes().abs() < 60,
+            "{rise}"
+        );
+        let set = moon.horizon.set.expect("Moonset");
+        assert!(
+            (set - utc(2024, 4, 24, 4, 40)).num_minutes().abs() < 60,
+            "{set}"
+        );
+        // Moonrise runs later every day and skips a day once a month.
+        let days: Vec<_> = (0..30)
+            .map(|d| {
+                Moon::on(
+                    london,
+                    utc(2024, 4, 1, 0, 0) + TimeDelta::days(d),
+                    utc(2024, 4, 1, 0, 0),
+                )
+            })
+            .collect();
+        assert_eq!(days.iter().filter(|m| m.horizon.rise.is_none()).count(), 1);
+    }
+}
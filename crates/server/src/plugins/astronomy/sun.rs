@@ -0,0 +1,229 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+use super::{Crossings, Observer, acos, asin, centuries, cos, julian_day, sin, tan};
+
+// Altitudes of the sun's centre. Rise and set allow for refraction and the
+// sun's apparent radius.
+const HORIZON: f64 = -0.833;
+const CIVIL: f64 = -6.0;
+const NAUTICAL: f64 = -12.0;
+
+struct Position {
+    declination: f64,
+    // Minutes the true sun runs ahead of the mean sun.
+    equation_of_time: f64,
+}
+
+// After the NOAA solar calculator, accurate to about a minute for
+// latitudes within ±72°.
+fn position(jd: f64) -> Position {
+    let t = centuries(jd);
+    let mean_longitude = t.mul_add(t.mul_add(0.000_303_2, 36_000.769_83), 280.466_46) % 360.0;
+    let anomaly = t.mul_add((-0.000_153_7f64).mul_add(t, 35_999.050_29), 357.529_11);
+    let eccentricity = t.mul_add(
+        -(0.000_000_126_7f64).mul_add(t, 0.000_042_037),
+        0.016_708_634,
+    );
+    let centre = sin(anomaly) * (-t).mul_add(0.000_014f64.mul_add(t, 0.004_817), 1.914_602)
+        + sin(2.0 * anomaly) * (-0.000_101f64).mul_add(t, 0.019_993)
+        + sin(3.0 * anomaly) * 0.000_289;
+    let omega = (-1934.136f64).mul_add(t, 125.04);
+    let longitude = (-0.004_78f64).mul_add(sin(omega), mean_longitude + centre - 0.005_69);
+    let arcseconds = t * t.mul_add((-0.001_813f64).mul_add(t, 0.000_59), 46.815);
+    let mean_obliquity = 23.0 + (26.0 + (21.448 - arcseconds) / 60.0) / 60.0;
+    let obliquity = 0.002_56f64.mul_add(cos(omega), mean_obliquity);
+    let y = tan(obliquity / 2.0).powi(2);
+    let equation: f64 = [
+        y * sin(2.0 * mean_longitude),
+        -2.0 * eccentricity * sin(anomaly),
+        4.0 * eccentricity * y * sin(anomaly) * cos(2.0 * mean_longitude),
+        -0.5 * y * y * sin(4.0 * mean_longitude),
+        -1.25 * eccentricity * eccentricity * sin(2.0 * anomaly),
+    ]
+    .iter()
+    .sum();
+    Position {
+        declination: asin(sin(obliquity) * sin(longitude)),
+        equation_of_time: 4.0 * equation.to_degrees(),
+    }
+}
+
+fn at_minutes(date: NaiveDate, minutes: f64) -> DateTime<Utc> {
+    #[allow(clippy::cast_possible_truncation, reason = "minutes within a few days")]
+    let millis = (minutes * 60_000.0).round() as i64;
+    date.and_time(chrono::NaiveTime::MIN).and_utc() + TimeDelta::milliseconds(millis)
+}
+
+// Minutes after midnight UTC of `date` when the sun culminates, refined at
+// `minutes`.
+fn noon(observer: Observer, date: NaiveDate, minutes: f64) -> (f64, Position) {
+    let pos = position(julian_day(at_minutes(date, minutes)));
+    (
+        4.0f64.mul_add(-observer.longitude, 720.0) - pos.equation_of_time,
+        pos,
+    )
+}
+
+// When the sun's centre passes `altitude` on the solar day of `date`, or
+// `None` if it stays above or below all day.
+fn crossing(
+    observer: Observer,
+    date: NaiveDate,
+    altitude: f64,
+    rising: bool,
+) -> Option<DateTime<Utc>> {
+    let mut minutes = 4.0f64.mul_add(-observer.longitude, 720.0);
+    // The declination barely moves within a day, two refinements suffice.
+    for _ in 0..3 {
+        let (noon, pos) = noon(observer, date, minutes);
+        let lat = observer.latitude;
+        let cos_h = (-sin(lat)).mul_add(sin(pos.declination), sin(altitude))
+            / (cos(lat) * cos(pos.declination));
+        if !(-1.0..=1.0).contains(&cos_h) {
+            return None;
+        }
+        let offset = 4.0 * acos(cos_h);
+        minutes = if rising { noon - offset } else { noon + offset };
+    }
+    Some(at_minutes(date, minutes))
+}
+
+fn crossings(observer: Observer, date: NaiveDate, altitude: f64) -> Crossings {
+    Crossings {
+        rise: crossing(observer, date, altitude, true),
+        set: crossing(observer, date, altitude, false),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sun {
+    pub noon: DateTime<Utc>,
+    pub daylight: Crossings,
+    // Dawn is the `rise`, dusk the `set` of the twilights.
+    pub civil: Crossings,
+    pub nautical: Crossings,
+    pub day_length: TimeDelta,
+}
+
+impl Sun {
+    // Sun times for the solar day of `date` at the observer's longitude.
+    pub fn on(observer: Observer, date: NaiveDate) -> Self {
+        let (minutes, pos) = noon(observer, date, 4.0f64.mul_add(-observer.longitude, 720.0));
+        let (noon, _) = noon(observer, date, minutes);
+        let daylight = crossings(observer, date, HORIZON);
+        let day_length = match (daylight.rise, daylight.set) {
+            (Some(rise), Some(set)) => set - rise,
+            // Without crossings, the noon altitude tells a polar day from
+            // a polar night.
+            _ if 90.0 - (observer.latitude - pos.declination).abs() > HORIZON => TimeDelta::days(1),
+            _ => TimeDelta::zero(),
+        };
+        Self {
+            noon: at_minutes(date, noon),
+            daylight,
+            civil: crossings(observer, date, CIVIL),
+            nautical: crossings(observer, date, NAUTICAL),
+            day_length,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone};
+
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).expect("Valid date")
+    }
+
+    // Asserts that `actual` is within two minutes of the local `hh:mm`.
+    #[track_caller]
+    fn near(actual: Option<DateTime<Utc>>, offset_hours: i32, expected: &str) {
+        let actual = actual.expect("The event happens");
+        let offset = FixedOffset::east_opt(offset_hours * 3600).expect("Valid offset");
+        let (h, m) = expected.split_once(':').expect("hh:mm");
+        let expected = offset
+            .from_local_datetime(
+                &actual
+                    .with_timezone(&offset)
+                    .date_naive()
+                    .and_hms_opt(h.parse().expect("Hour"), m.parse().expect("Minute"), 0)
+                    .expect("Valid time"),
+            )
+            .single()
+            .expect("Unambiguous time")
+            .with_timezone(&Utc);
+        assert!(
+            (actual - expected).num_seconds().abs() <= 120,
+            "{actual} is not near {expected}"
+        );
+    }
+
+    // Reference times from the published almanac tables (NOAA / USNO), to
+    // the minute.
+    #[test]
+    fn sun_matches_reference_tables() {
+        let london = Observer::new(51.5074, -0.1278);
+        let sun = Sun::on(london, date(2024, 6, 21));
+        near(sun.daylight.rise, 1, "04:43");
+        near(sun.daylight.set, 1, "21:21");
+        let length = sun.day_length - TimeDelta::minutes(16 * 60 + 38);
+        assert!(length.num_minutes().abs() <= 2, "{length}");
+        let sun = Sun::on(london, date(2024, 12, 21));
+        near(sun.daylight.rise, 0, "08:04");
+        near(sun.daylight.set, 0, "15:53");
+
+        let new_york = Observer::new(40.7128, -74.0060);
+        let sun = Sun::on(new_york, date(2024, 6, 21));
+        near(sun.daylight.rise, -4, "05:25");
+        near(sun.daylight.set, -4, "20:31");
+
+        // The local date lies ahead of UTC east of Greenwich.
+        let sydney = Observer::new(-33.8688, 151.2093);
+        let sun = Sun::on(sydney, date(2024, 12, 21));
+        near(sun.daylight.rise, 11, "05:41");
+        near(sun.daylight.set, 11, "20:05");
+        near(Some(sun.noon), 11, "12:53");
+    }
+
+    #[test]
+    fn twilights_bracket_the_day() {
+        let berlin = Observer::new(52.52, 13.405);
+        let sun = Sun::on(berlin, date(2024, 3, 20));
+        let order = [
+            sun.nautical.rise,
+            sun.civil.rise,
+            sun.daylight.rise,
+            Some(sun.noon),
+            sun.daylight.set,
+            sun.civil.set,
+            sun.nautical.set,
+        ];
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{order:?}");
+        // Around the equinox day and night are about equally long.
+        assert!((sun.day_length - TimeDelta::hours(12)).num_minutes().abs() < 15);
+        // About half an hour of civil twilight at these latitudes.
+        let civil = sun.daylight.rise.expect("Sunrise") - sun.civil.rise.expect("Dawn");
+        assert!((30..40).contains(&civil.num_minutes()), "{civil}");
+    }
+
+    #[test]
+    fn polar_days_have_no_crossings() {
+        let tromso = Observer::new(69.6492, 18.9553);
+        let summer = Sun::on(tromso, date(2024, 6, 21));
+        assert_eq!((summer.daylight.rise, summer.daylight.set), (None, None));
+        assert_eq!(summer.day_length, TimeDelta::days(1));
+        let winter = Sun::on(tromso, date(2024, 12, 21));
+        assert_eq!((winter.daylight.rise, winter.daylight.set), (None, None));
+        assert_eq!(winter.day_length, TimeDelta::zero());
+        // The polar night still has a civil twilight around noon.
+        assert!(winter.civil.rise.is_some());
+        // Helsinki's midsummer night never gets dark enough for nautical
+        // twilight to end.
+        let helsinki = Sun::on(Observer::new(60.1699, 24.9384), date(2024, 6, 21));
+        assert!(helsinki.civil.set.is_some());
+        assert_eq!(helsinki.nautical.set, None);
+    }
+}
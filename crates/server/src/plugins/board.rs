@@ -0,0 +1,467 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::future::BoxFuture;
+use log::error;
+use sailfish::TemplateOnce;
+use serde::{Deserialize, Serialize};
+
+use super::{format::age, state};
+use crate::{
+    auth,
+    error::{Canonical, IntoCanonical},
+    generator::{self, RenderContext},
+    storage,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("there is no message {0}")]
+    NotFound(u64),
+    #[error("a message needs an author and a text")]
+    Empty,
+    #[error("the action {0} is unknown")]
+    UnknownAction(String),
+    #[error("a message has to be kept for a positive number of hours, not `{0}`")]
+    Expiry(String),
+    #[error(transparent)]
+    State(#[from] state::Error),
+}
+
+impl IntoCanonical for Error {
+    fn into_canonical(self) -> Canonical {
+        match self {
+            Self::NotFound(_) => Canonical::NotFound,
+            Self::Empty | Self::UnknownAction(_) | Self::Expiry(_) => Canonical::InvalidArgument,
+            Self::State(_) => Canonical::Internal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub id: u64,
+    pub author: String,
+    pub text: String,
+    pub posted: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl Message {
+    // Ids are random rather than counted, so they are never reused after a
+    // delete and one post keeps the same id on every board it goes to.
+    pub fn new(
+        author: &str,
+        text: &str,
+        lifetime: Option<TimeDelta>,
+        pinned: bool,
+        now: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        let (author, text) = (author.trim(), text.trim());
+        if author.is_empty() || text.is_empty() {
+            return Err(Error::Empty);
+        }
+        let expires = lifetime
+            .map(|lifetime| {
+                Some(lifetime)
+                    .filter(|lifetime| *lifetime > TimeDelta::zero())
+                    .and_then(|lifetime| now.checked_add_signed(lifetime))
+                    .ok_or_else(|| Error::Expiry(lifetime.to_string()))
+            })
+            .transpose()?;
+        Ok(Self {
+            id: fastrand::u64(..),
+            author: author.into(),
+            text: text.into(),
+            posted: now,
+            expires,
+            pinned,
+        })
+    }
+
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+// A submission of the board page. Every button posts the whole form, so the
+// fields of the other actions are simply left empty.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Update {
+    pub action: String,
+    pub id: Option<u64>,
+    pub author: String,
+    pub text: String,
+    // Hours until the message disappears, empty to keep it.
+    pub expires: String,
+    pub pinned: Option<String>,
+}
+
+// What a submission does, decided once for all boards of a name.
+#[derive(Debug, Clone)]
+pub enum Change {
+    Post(Message),
+    Pin(u64, bool),
+    Delete(u64),
+}
+
+impl Update {
+    pub fn change(&self, now: DateTime<Utc>) -> Result<Change, Error> {
+        let id = || self.id.ok_or(Error::NotFound(0));
+        match self.action.as_str() {
+            "post" => {
+                let lifetime = match self.expires.trim() {
+                    "" => None,
+                    hours => Some(
+                        hours
+                            .parse()
+                            .ok()
+                            .filter(|hours: &i64| *hours > 0)
+                            .and_then(TimeDelta::try_hours)
+                            .ok_or_else(|| Error::Expiry(hours.into()))?,
+                    ),
+                };
+                Message::new(
+                    &self.author,
+                    &self.text,
+                    lifetime,
+                    self.pinned.is_some(),
+                    now,
+                )
+                .map(Change::Post)
+            }
+            "pin" => Ok(Change::Pin(id()?, true)),
+            "unpin" => Ok(Change::Pin(id()?, false)),
+            "delete" => Ok(Change::Delete(id()?)),
+            action => Err(Error::UnknownAction(action.into())),
+        }
+    }
+}
+
+// Messages, mirrored to an optional JSON file.
+struct Messages {
+    path: Option<PathBuf>,
+    messages: Vec<Message>,
+}
+
+impl Messages {
+    fn open(path: Option<PathBuf>) -> Result<Self, Error> {
+        let messages = state::load(path.as_deref())?;
+        Ok(Self { path, messages })
+    }
+
+    fn find(&mut self, id: u64, now: DateTime<Utc>) -> Result<&mut Message, Error> {
+        self.messages.retain(|message| !message.expired(now));
+        self.messages
+            .iter_mut()
+            .find(|message| message.id == id)
+            .ok_or(Error::NotFound(id))
+    }
+
+    // Drops expired messages and writes the rest back.
+    fn save(&mut self, now: DateTime<Utc>) -> Result<(), Error> {
+        self.messages.retain(|message| !message.expired(now));
+        Ok(state::save(self.path.as_deref(), &self.messages)?)
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "board.stpl")]
+struct BoardTemplate<'a> {
+    messages: &'a [Message],
+    now: DateTime<Utc>,
+}
+
+pub struct BoardPlugin {
+    password: String,
+    limit: Option<usize>,
+    messages: Mutex<Messages>,
+}
+
+impl BoardPlugin {
+    pub fn new(
+        password: String,
+        limit: Option<usize>,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        if password.is_empty() {
+            error!("A message board needs a password");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let messages = Messages::open(state)
+            .inspect_err(|e| error!("Loading the message board: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            password,
+            limit,
+            messages: Mutex::new(messages),
+        })
+    }
+
+    // Checks HTTP basic credentials; any user name is accepted.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        auth::basic(authorization, &self.password)
+    }
+
+    // Current messages, pinned ones first, then newest first.
+    pub fn messages(&self, now: DateTime<Utc>) -> Vec<Message> {
+        let mut messages: Vec<_> = self
+            .messages
+            .lock()
+            .expect("message board lock poisoned")
+            .messages
+            .iter()
+            .filter(|message| !message.expired(now))
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.posted.cmp(&a.posted)));
+        messages
+    }
+
+    pub fn post(
+        &self,
+        author: &str,
+        text: &str,
+        lifetime: Option<TimeDelta>,
+        pinned: bool,
+        now: DateTime<Utc>,
+    ) -> Result<u64, Error> {
+        let message = Message::new(author, text, lifetime, pinned, now)?;
+        let id = message.id;
+        self.add(message, now)?;
+        Ok(id)
+    }
+
+    fn add(&self, message: Message, now: DateTime<Utc>) -> Result<(), Error> {
+        let mut messages = self.messages.lock().expect("message board lock poisoned");
+        messages.messages.push(message);
+        messages.save(now)
+    }
+
+    pub fn pin(&self, id: u64, pinned: bool, now: DateTime<Utc>) -> Result<(), Error> {
+        let mut messages = self.messages.lock().expect("message board lock poisoned");
+        messages.find(id, now)?.pinned = pinned;
+        messages.save(now)
+    }
+
+    pub fn delete(&self, id: u64, now: DateTime<Utc>) -> Result<(), Error> {
+        let mut messages = self.messages.lock().expect("message board lock poisoned");
+        messages.find(id, now)?;
+        messages.messages.retain(|message| message.id != id);
+        messages.save(now)
+    }
+
+    pub fn apply(&self, change: &Change, now: DateTime<Utc>) -> Result<(), Error> {
+        match change {
+            Change::Post(message) => self.add(message.clone(), now),
+            Change::Pin(id, pinned) => self.pin(*id, *pinned, now),
+            Change::Delete(id) => self.delete(*id, now),
+        }
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let mut messages = self.messages(now);
+        let rows = ctx.view.rows(80);
+        messages.truncate(self.limit.map_or(rows, |limit| limit.min(rows)));
+        BoardTemplate {
+            messages: &messages,
+            now,
+        }
+        .render_once()
+        .expect("board template render failed")
+    }
+}
+
+impl generator::Content for BoardPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { Ok(self.render(ctx, Utc::now())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::generator::View;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, h, m, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn board(state: Option<PathBuf>) -> BoardPlugin {
+        BoardPlugin::new("fridge".into(), None, state).expect("Board is configured")
+    }
+
+    #[test]
+    fn authorized_checks_the_password() {
+        let board = board(None);
+        // "mum:fridge" and "dad:oven".
+        assert!(board.authorized(Some("Basic bXVtOmZyaWRnZQ==")));
+        assert!(!board.authorized(Some("Basic ZGFkOm92ZW4=")));
+        assert!(!board.authorized(Some("Bearer fridge")));
+        assert!(!board.authorized(None));
+    }
+
+    #[test]
+    fn messages_are_pinned_sorted_and_expire() {
+        let board = board(None);
+        board
+            .post("Mum", "Dentist at 4", None, false, at(8, 0))
+            .expect("Posted");
+        let milk = board
+            .post(
+                "Dad",
+                "Buy milk",
+                Some(TimeDelta::hours(1)),
+                false,
+                at(8, 30),
+            )
+            .expect("Posted");
+        let wifi = board
+            .post(
+                "Kid",
+                "Wifi password is on the router",
+                None,
+                false,
+                at(7, 0),
+            )
+            .expect("Posted");
+        board.pin(wifi, true, at(8, 30)).expect("Pinned");
+
+        let texts = |now| -> Vec<_> { board.messages(now).into_iter().map(|m| m.text).collect() };
+        assert_eq!(
+            texts(at(9, 0)),
+            ["Wifi password is on the router", "Buy milk", "Dentist at 4"]
+        );
+        assert_eq!(
+            texts(at(9, 30)),
+            ["Wifi password is on the router", "Dentist at 4"]
+        );
+        assert!(matches!(
+            board.delete(milk, at(9, 30)),
+            Err(Error::NotFound(_))
+        ));
+        board.delete(wifi, at(9, 30)).expect("Deleted");
+        assert_eq!(texts(at(9, 30)), ["Dentist at 4"]);
+    }
+
+    #[test]
+    fn apply_handles_form_submissions() {
+        let board = board(None);
+        let post = Update {
+            action: "post".into(),
+            author: " Mum ".into(),
+            text: "Feed the cat".into(),
+            expires: "24".into(),
+            pinned: Some("on".into()),
+            ..Update::default()
+        };
+        let apply = |update: &Update, now| board.apply(&update.change(now)?, now);
+        apply(&post, at(8, 0)).expect("Posted");
+        let message = &board.messages(at(8, 0))[0];
+        assert_eq!(message.author, "Mum");
+        assert!(message.pinned);
+        assert_eq!(message.expires, Some(at(8, 0) + TimeDelta::days(1)));
+
+        let unpin = Update {
+            action: "unpin".into(),
+            id: Some(message.id),
+            ..Update::default()
+        };
+        apply(&unpin, at(8, 1)).expect("Unpinned");
+        assert!(!board.messages(at(8, 1))[0].pinned);
+
+        let empty = Update {
+            action: "post".into(),
+            author: "Mum".into(),
+            ..Update::default()
+        };
+        assert!(matches!(apply(&empty, at(8, 2)), Err(Error::Empty)));
+        let unknown = Update {
+            action: "shout".into(),
+            ..Update::default()
+        };
+        assert!(matches!(
+            apply(&unknown, at(8, 2)),
+            Err(Error::UnknownAction(_))
+        ));
+    }
+
+    #[test]
+    fn change_rejects_invalid_lifetimes() {
+        for expires in ["0", "-1", "99999999999999", "soon"] {
+            let post = Update {
+                action: "post".into(),
+                author: "Mum".into(),
+                text: "Feed the cat".into(),
+                expires: expires.into(),
+                ..Update::default()
+            };
+            assert!(matches!(post.change(at(8, 0)), Err(Error::Expiry(_))));
+        }
+        let board = board(None);
+        assert!(matches!(
+            board.post("Mum", "Gone", Some(-TimeDelta::hours(1)), false, at(8, 0)),
+            Err(Error::Expiry(_))
+        ));
+        assert!(matches!(
+            board.post("Mum", "Forever", Some(TimeDelta::MAX), false, at(8, 0)),
+            Err(Error::Expiry(_))
+        ));
+        assert!(board.messages(at(8, 0)).is_empty());
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let board = board(None);
+        let first = board
+            .post("Mum", "Feed the cat", None, false, at(8, 0))
+            .expect("Posted");
+        board.delete(first, at(8, 1)).expect("Deleted");
+        let second = board
+            .post("Mum", "Cat is fed", None, false, at(8, 2))
+            .expect("Posted");
+        assert_ne!(first, second);
+        assert!(matches!(
+            board.pin(first, true, at(8, 3)),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn messages_persist() {
+        let path = std::env::temp_dir().join(format!("atrmnl_board_{}.json", std::process::id()));
+        board(Some(path.clone()))
+            .post("Mum", "Back at 6", None, false, at(8, 0))
+            .expect("Posted");
+        let messages = board(Some(path.clone())).messages(at(9, 0));
+        std::fs::remove_file(&path).expect("Failed to remove state file");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].text, "Back at 6");
+    }
+
+    #[test]
+    fn render_shows_author_and_age() {
+        let board = board(None);
+        for i in 0..10 {
+            board
+                .post("Mum", &format!("Note {i}"), None, false, at(8, i))
+                .expect("Posted");
+        }
+        let html = board.render(&RenderContext::new("d"), at(9, 9));
+        assert_eq!(html.matches("class=\"item\"").count(), 6);
+        assert!(html.contains("Note 9"));
+        assert!(html.contains("Mum · 1 h ago"));
+        let html = board.render(&RenderContext::new("d").with_view(View::Quadrant), at(9, 9));
+        assert_eq!(html.matches("class=\"item\"").count(), 3);
+    }
+}
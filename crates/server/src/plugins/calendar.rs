@@ -0,0 +1,588 @@
+use std::{fmt, fs, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
+use futures::future::{BoxFuture, try_join_all};
+use log::{debug, error, warn};
+use sailfish::TemplateOnce;
+use url::Url;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    net, storage,
+};
+
+pub mod caldav;
+pub mod ics;
+pub mod tasks;
+
+use ics::{Occurrence, Span};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_DAYS: u32 = 7;
+// Hours the day view always shows; earlier or later events extend it.
+const DAY_HOURS: (u32, u32) = (7, 21);
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Agenda,
+    Day,
+    Week,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SourceConfig {
+    Location(String),
+    CalDav(caldav::Collection),
+}
+
+// Either an `http(s)://` or `webcal://` URL, a path to a local file, or a
+// `{ caldav = "...", auth = ... }` table.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(from = "SourceConfig")]
+pub enum Source {
+    Remote(Url),
+    Local(PathBuf),
+    CalDav(caldav::Collection),
+}
+
+impl From<SourceConfig> for Source {
+    fn from(value: SourceConfig) -> Self {
+        match value {
+            SourceConfig::Location(location) => location.into(),
+            SourceConfig::CalDav(collection) => Self::CalDav(collection),
+        }
+    }
+}
+
+impl From<String> for Source {
+    fn from(value: String) -> Self {
+        let url = value.strip_prefix("webcal://").map_or_else(
+            || Url::parse(&value),
+            |rest| Url::parse(&format!("https://{rest}")),
+        );
+        match url {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Self::Remote(url),
+            _ => Self::Local(PathBuf::from(value)),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remote(url) | Self::CalDav(caldav::Collection { url, .. }) => write!(f, "{url}"),
+            Self::Local(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl Source {
+    // CalDAV servers only return `component`s overlapping `range`; calendar
+    // files are always loaded whole.
+    async fn load(
+        &self,
+        component: caldav::Component,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<ics::Calendar, generator::Error> {
+        debug!("Loading calendar from {self}");
+        let data = match self {
+            Self::Remote(url) => {
+                net::retry(
+                    || async {
+                        net::client()
+                            .get(url.clone())
+                            .timeout(FETCH_TIMEOUT)
+                            .send()
+                            .await?
+                            .error_for_status()?
+                            .text()
+                            .await
+                    },
+                    RETRY_BACKOFF,
+                    RETRY_TIMEOUT,
+                )
+                .await?
+            }
+            Self::Local(path) => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || fs::read_to_string(path))
+                    .await
+                    .map_err(|_| generator::Error::Unknown)?
+                    .inspect_err(|e| error!("Reading calendar {self}: {e}"))
+                    .map_err(|_| generator::Error::Misconfigured)?
+            }
+            Self::CalDav(collection) => return collection.fetch(component, range).await,
+        };
+        ics::Calendar::parse(&data)
+            .inspect_err(|e| error!("Parsing calendar {self}: {e}"))
+            .map_err(|_| generator::Error::Fetch {
+                kind: generator::FetchErrorKind::InvalidData,
+                target: self.to_string(),
+            })
+    }
+}
+
+// Merges the calendars of all sources.
+async fn load_all(
+    sources: &[Source],
+    component: caldav::Component,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Result<ics::Calendar, generator::Error> {
+    let calendars = try_join_all(sources.iter().map(|s| s.load(component, range))).await?;
+    Ok(calendars
+        .into_iter()
+        .fold(ics::Calendar::default(), |mut all, calendar| {
+            all.extend(calendar);
+            all
+        }))
+}
+
+fn timezone(ctx: &RenderContext) -> Tz {
+    ctx.timezone.parse::<Tz>().unwrap_or_else(|_| {
+        warn!(
+            "Unknown timezone {}, showing the calendar in UTC",
+            ctx.timezone
+        );
+        Tz::UTC
+    })
+}
+
+fn midnight(tz: Tz, date: NaiveDate) -> DateTime<Tz> {
+    ics::localize(tz, date.and_time(NaiveTime::MIN))
+}
+
+pub struct Day<'a> {
+    pub date: NaiveDate,
+    pub events: Vec<&'a Occurrence>,
+    // Events that did not fit into the view.
+    pub hidden: usize,
+}
+
+struct Block<'a> {
+    event: &'a Occurrence,
+    // Minutes after the first hour shown.
+    top: u32,
+    height: u32,
+    lane: usize,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "calendar/agenda.stpl")]
+struct AgendaTemplate<'a> {
+    days: &'a [Day<'a>],
+    today: NaiveDate,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "calendar/day.stpl")]
+struct DayTemplate<'a> {
+    day: &'a Day<'a>,
+    today: NaiveDate,
+    blocks: &'a [Block<'a>],
+    lanes: usize,
+    hours: (u32, u32),
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "calendar/week.stpl")]
+struct WeekTemplate<'a> {
+    days: &'a [Day<'a>],
+    today: NaiveDate,
+}
+
+fn heading(date: NaiveDate, today: NaiveDate) -> String {
+    if date == today {
+        "Today".into()
+    } else if today.succ_opt() == Some(date) {
+        "Tomorrow".into()
+    } else {
+        date.format("%a %-d %b").to_string()
+    }
+}
+
+fn time_label(event: &Occurrence, day: NaiveDate) -> String {
+    let Span::Timed { start, end } = &event.span else {
+        return "All day".into();
+    };
+    let starts_today = start.date_naive() == day;
+    let ends_today = event.span.last_day() == day;
+    match (starts_today, ends_today) {
+        (true, true) if start == end => start.format("%H:%M").to_string(),
+        (true, true) => format!("{}–{}", start.format("%H:%M"), end.format("%H:%M")),
+        (true, false) => format!("from {}", start.format("%H:%M")),
+        (false, true) => format!("until {}", end.format("%H:%M")),
+        (false, false) => "All day".into(),
+    }
+}
+
+// Wall clock minutes of the part of a timed event that falls on `day`.
+fn minutes_on(start: DateTime<Tz>, end: DateTime<Tz>, day: NaiveDate) -> (u32, u32) {
+    let minute = |t: NaiveTime| t.hour() * 60 + t.minute();
+    let from = if start.date_naive() < day {
+        0
+    } else {
+        minute(start.time())
+    };
+    let to = if end.date_naive() > day {
+        MINUTES_PER_DAY
+    } else {
+        minute(end.time())
+    };
+    (from, to.max(from))
+}
+
+fn whole_day(event: &Occurrence, day: NaiveDate) -> bool {
+    match &event.span {
+        Span::AllDay { .. } => true,
+        Span::Timed { start, end } => minutes_on(*start, *end, day) == (0, MINUTES_PER_DAY),
+    }
+}
+
+// Lays out the timed events of a day side by side wherever they overlap.
+fn blocks<'a>(day: &Day<'a>) -> (Vec<Block<'a>>, usize, (u32, u32)) {
+    let timed: Vec<_> = day
+        .events
+        .iter()
+        .filter(|event| !whole_day(event, day.date))
+        .filter_map(|event| match &event.span {
+            Span::Timed { start, end } => Some((*event, minutes_on(*start, *end, day.date))),
+            Span::AllDay { .. } => None,
+        })
+        .collect();
+    let first = timed
+        .iter()
+        .map(|(_, (from, _))| from / 60)
+        .fold(DAY_HOURS.0, u32::min);
+    let last = timed
+        .iter()
+        .map(|(_, (_, to))| to.div_ceil(60))
+        .fold(DAY_HOURS.1, u32::max);
+    let mut lanes: Vec<u32> = Vec::new();
+    let blocks = timed
+        .into_iter()
+        .map(|(event, (from, to))| {
+            // Very short events still need room for their title.
+            let to = to.max(from + 30);
+            let lane = lanes
+                .iter()
+                .position(|end| *end <= from)
+                .unwrap_or_else(|| {
+                    lanes.push(0);
+                    lanes.len() - 1
+                });
+            lanes[lane] = to;
+            Block {
+                event,
+                top: from - first * 60,
+                height: to - from,
+                lane,
+            }
+        })
+        .collect();
+    (blocks, lanes.len().max(1), (first, last))
+}
+
+fn fit(days: &mut [Day<'_>], mut rows: usize) {
+    for day in days {
+        let shown = day.events.len().min(rows);
+        day.hidden = day.events.len() - shown;
+        day.events.truncate(shown);
+        rows -= shown;
+    }
+}
+
+pub struct CalendarPlugin {
+    sources: Vec<Source>,
+    layout: Layout,
+    days: u32,
+}
+
+impl CalendarPlugin {
+    pub fn new(
+        sources: Vec<Source>,
+        layout: Layout,
+        days: Option<u32>,
+    ) -> Result<Self, storage::LoadError> {
+        if sources.is_empty() {
+            error!("A calendar needs at least one source");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self {
+            sources,
+            layout,
+            days: days.unwrap_or(DEFAULT_DAYS),
+        })
+    }
+
+    // The first day shown and the day after the last one.
+    fn window(&self, view: View, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let (first, count) = match (self.layout, view) {
+            (Layout::Agenda, _) => (today, self.days),
+            (Layout::Day, _) => (today, 1),
+            (Layout::Week, View::Full) => (
+                today - Days::new(u64::from(today.weekday().num_days_from_monday())),
+                7,
+            ),
+            (Layout::Week, View::HalfVertical | View::Quadrant) => (today, 3),
+        };
+        (first, first + Days::new(u64::from(count)))
+    }
+
+    fn render(&self, calendar: &ics::Calendar, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let tz = timezone(ctx);
+        let now = now.with_timezone(&tz);
+        let today = now.date_naive();
+        let (first, last) = self.window(ctx.view, today);
+        let occurrences = calendar.occurrences(tz, midnight(tz, first), midnight(tz, last));
+        let mut days: Vec<_> = first
+            .iter_days()
+            .take_while(|date| *date < last)
+            .map(|date| Day {
+                date,
+                events: occurrences.iter().filter(|o| o.span.covers(date)).collect(),
+                hidden: 0,
+            })
+            .collect();
+        let rows = ctx.view.rows(48);
+        match self.layout {
+            Layout::Agenda => {
+                for day in &mut days {
+                    day.events.retain(|o| match &o.span {
+                        Span::Timed { end, .. } => *end > now,
+                        Span::AllDay { .. } => true,
+                    });
+                }
+                days.retain(|day| !day.events.is_empty());
+                fit(&mut days, rows);
+                days.retain(|day| !day.events.is_empty());
+                AgendaTemplate { days: &days, today }
+                    .render_once()
+                    .expect("calendar agenda template render failed")
+            }
+            Layout::Day => {
+                let day = &days[0];
+                let (blocks, lanes, hours) = blocks(day);
+                DayTemplate {
+                    day,
+                    today,
+                    blocks: &blocks,
+                    lanes,
+                    hours,
+                }
+                .render_once()
+                .expect("calendar day template render failed")
+            }
+            Layout::Week => {
+                for day in &mut days {
+                    fit(std::slice::from_mut(day), rows.saturating_sub(2));
+                }
+                WeekTemplate { days: &days, today }
+                    .render_once()
+                    .expect("calendar week template render failed")
+            }
+        }
+    }
+}
+
+impl generator::Content for CalendarPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let now = Utc::now();
+            let tz = timezone(ctx);
+            let (first, last) = self.window(ctx.view, now.with_timezone(&tz).date_naive());
+            let range = (
+                midnight(tz, first).with_timezone(&Utc),
+                midnight(tz, last).with_timezone(&Utc),
+            );
+            let calendar = load_all(&self.sources, caldav::Component::Event, Some(range)).await?;
+            Ok(self.render(&calendar, ctx, now))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn fixture(name: &str) -> Source {
+        Source::Local(
+            [env!("CARGO_MANIFEST_DIR"), "tests", "calendar", name]
+                .iter()
+                .collect(),
+        )
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn zurich(view: View) -> RenderContext {
+        RenderContext::new("d")
+            .with_region(Some("Europe/Zurich"), None)
+            .with_view(view)
+    }
+
+    async fn render(layout: Layout, view: View, now: DateTime<Utc>) -> String {
+        let plugin = CalendarPlugin::new(vec![fixture("recurring.ics")], layout, Some(10))
+            .expect("Plugin is configured");
+        let calendar = plugin.sources[0]
+            .load(caldav::Component::Event, None)
+            .await
+            .expect("Calendar loads");
+        plugin.render(&calendar, &zurich(view), now)
+    }
+
+    #[test]
+    fn source_from_string() {
+        assert!(matches!(
+            Source::from("webcal://example.com/cal.ics".to_owned()),
+            Source::Remote(url) if url.as_str() == "https://example.com/cal.ics"
+        ));
+        assert!(matches!(
+            Source::from("https://example.com/cal.ics".to_owned()),
+            Source::Remote(_)
+        ));
+        assert!(matches!(
+            Source::from("calendars/home.ics".to_owned()),
+            Source::Local(path) if path.as_os_str() == "calendars/home.ics"
+        ));
+    }
+
+    #[test]
+    fn source_from_caldav_table() {
+        #[derive(serde::Deserialize)]
+        struct Config {
+            sources: Vec<Source>,
+        }
+        let config: Config = toml::from_str(
+            r#"sources = [
+                "calendars/home.ics",
+                { caldav = "https://cloud.example.com/remote.php/dav/calendars/alice/personal/", auth = { user = "alice", password = "secret" } },
+            ]"#,
+        )
+        .expect("Sources parse");
+        assert!(matches!(config.sources[0], Source::Local(_)));
+        assert!(matches!(
+            &config.sources[1],
+            Source::CalDav(caldav::Collection {
+                auth: Some(caldav::Auth::Basic { user, .. }),
+                ..
+            }) if user == "alice"
+        ));
+    }
+
+    #[test]
+    fn new_requires_sources() {
+        assert!(CalendarPlugin::new(vec![], Layout::Agenda, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn load_rejects_invalid_data() {
+        let result = Source::Local(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml").into())
+            .load(caldav::Component::Event, None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(generator::Error::Fetch {
+                kind: generator::FetchErrorKind::InvalidData,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn agenda_lists_upcoming_events() {
+        // 10:00 in Zurich, so the first standup has already ended.
+        let html = render(Layout::Agenda, View::Full, at(2024, 1, 1, 9)).await;
+        assert!(!html.contains("Today"));
+        assert!(!html.contains("Wed 3 Jan"));
+        assert!(html.contains("Mon 8 Jan"));
+        assert!(html.contains("Standup (moved)"));
+        assert!(html.contains("14:00–14:15"));
+        assert!(html.contains("Wed 10 Jan"));
+        assert!(html.contains("09:00–09:15"));
+    }
+
+    #[tokio::test]
+    async fn agenda_fits_the_view() {
+        let plugin = CalendarPlugin::new(vec![fixture("busy.ics")], Layout::Agenda, None)
+            .expect("Plugin is configured");
+        let calendar = plugin.sources[0]
+            .load(caldav::Component::Event, None)
+            .await
+            .expect("Calendar loads");
+        let now = at(2024, 5, 6, 5);
+        let full = plugin.render(&calendar, &zurich(View::Full), now);
+        let quadrant = plugin.render(&calendar, &zurich(View::Quadrant), now);
+        assert_eq!(full.matches("class=\"item\"").count(), 10);
+        assert_eq!(quadrant.matches("class=\"item\"").count(), 5);
+    }
+
+    #[tokio::test]
+    async fn day_view_places_overlapping_events_side_by_side() {
+        let plugin = CalendarPlugin::new(vec![fixture("busy.ics")], Layout::Day, None)
+            .expect("Plugin is configured");
+        let calendar = plugin.sources[0]
+            .load(caldav::Component::Event, None)
+            .await
+            .expect("Calendar loads");
+        let html = plugin.render(&calendar, &zurich(View::HalfVertical), at(2024, 5, 6, 5));
+        assert!(html.contains("Holiday"));
+        assert!(html.contains("width: calc(100% / 2)"));
+    }
+
+    #[tokio::test]
+    async fn week_view_depends_on_size() {
+        // Wednesday, 3 January 2024.
+        let now = at(2024, 1, 3, 12);
+        let full = render(Layout::Week, View::Full, now).await;
+        let half = render(Layout::Week, View::HalfVertical, now).await;
+        assert!(full.contains("Mon 1 Jan"));
+        assert!(full.contains("Sun 7 Jan"));
+        assert!(!half.contains("Mon 1 Jan"));
+        assert!(half.contains("Today"));
+        assert!(half.contains("Fri 5 Jan"));
+        assert!(!half.contains("Sat 6 Jan"));
+    }
+
+    #[test]
+    fn blocks_use_lanes_for_overlaps() {
+        let tz = chrono_tz::Europe::Zurich;
+        let day = NaiveDate::from_ymd_opt(2024, 5, 6).expect("Valid date");
+        let event = |h: u32, len: u32| Occurrence {
+            summary: String::new(),
+            location: None,
+            span: Span::Timed {
+                start: ics::localize(tz, day.and_hms_opt(h, 0, 0).expect("Valid time")),
+                end: ics::localize(tz, day.and_hms_opt(h + len, 0, 0).expect("Valid time")),
+            },
+        };
+        let (a, b, c) = (event(6, 2), event(7, 1), event(8, 1));
+        let day = Day {
+            date: day,
+            events: vec![&a, &b, &c],
+            hidden: 0,
+        };
+        let (blocks, lanes, hours) = blocks(&day);
+        assert_eq!(lanes, 2);
+        assert_eq!(hours, (6, 21));
+        assert_eq!(
+            blocks.iter().map(|b| (b.top, b.lane)).collect::<Vec<_>>(),
+            [(0, 0), (60, 1), (120, 0)]
+        );
+    }
+}
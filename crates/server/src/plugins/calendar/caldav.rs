@@ -0,0 +1,285 @@
+use std::fmt::Write as _;
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, warn};
+use reqwest::{Method, header};
+use serde::Deserialize;
+use url::Url;
+
+use super::{FETCH_TIMEOUT, RETRY_BACKOFF, RETRY_TIMEOUT, ics};
+use crate::{generator, net};
+
+const CALDAV_NS: &str = "urn:ietf:params:xml:ns:caldav";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Auth {
+    Basic { user: String, password: String },
+    Bearer { token: String },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Component {
+    Event,
+    Todo,
+}
+
+impl Component {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Event => "VEVENT",
+            Self::Todo => "VTODO",
+        }
+    }
+}
+
+// A calendar collection on a CalDAV server such as Nextcloud or Radicale.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Collection {
+    #[serde(rename = "caldav")]
+    pub url: Url,
+    pub auth: Option<Auth>,
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+// The body of a `calendar-query` REPORT for one component type, optionally
+// limited to objects overlapping `range`.
+fn query(component: Component, range: Option<(DateTime<Utc>, DateTime<Utc>)>) -> String {
+    let mut filter = format!(r#"<C:comp-filter name="{}">"#, component.name());
+    if let Some((start, end)) = range {
+        let _ = write!(
+            filter,
+            r#"<C:time-range start="{}" end="{}"/>"#,
+            timestamp(start),
+            timestamp(end)
+        );
+    }
+    filter.push_str("</C:comp-filter>");
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<C:calendar-query xmlns:D="DAV:" xmlns:C="{ns}">"#,
+            "<D:prop><D:getetag/><C:calendar-data/></D:prop>",
+            r#"<C:filter><C:comp-filter name="VCALENDAR">{filter}</C:comp-filter></C:filter>"#,
+            "</C:calendar-query>",
+        ),
+        ns = CALDAV_NS,
+        filter = filter,
+    )
+}
+
+// Every `calendar-data` of a multistatus response, whatever the prefixes.
+fn calendar_data(xml: &str) -> Result<Vec<String>, roxmltree::Error> {
+    let doc = roxmltree::Document::parse(xml)?;
+    Ok(doc
+        .descendants()
+        .filter(|node| node.has_tag_name((CALDAV_NS, "calendar-data")))
+        .filter_map(|node| node.text())
+        .map(str::to_owned)
+        .collect())
+}
+
+impl Collection {
+    pub async fn fetch(
+        &self,
+        component: Component,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<ics::Calendar, generator::Error> {
+        debug!("Querying CalDAV collection {}", self.url);
+        let body = query(component, range);
+        let report = Method::from_bytes(b"REPORT").expect("REPORT is a valid method");
+        let xml = net::retry(
+            || async {
+                let request = net::client()
+                    .request(report.clone(), self.url.clone())
+                    .header("Depth", "1")
+                    .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+                    .body(body.clone())
+                    .timeout(FETCH_TIMEOUT);
+                let request = match &self.auth {
+                    Some(Auth::Basic { user, password }) => {
+                        request.basic_auth(user, Some(password))
+                    }
+                    Some(Auth::Bearer { token }) => request.bearer_auth(token),
+                    None => request,
+                };
+                request.send().await?.error_for_status()?.text().await
+            },
+            RETRY_BACKOFF,
+            RETRY_TIMEOUT,
+        )
+        .await?;
+        let invalid = || generator::Error::Fetch {
+            kind: generator::FetchErrorKind::InvalidData,
+            target: self.url.to_string(),
+        };
+        let objects = calendar_data(&xml)
+            .inspect_err(|e| error!("Parsing CalDAV response from {}: {e}", self.url))
+            .map_err(|_| invalid())?;
+        let mut calendar = ics::Calendar::default();
+        for object in objects {
+            match ics::Calendar::parse(&object) {
+                Ok(object) => calendar.extend(object),
+                Err(e) => warn!("Skipping calendar object from {}: {e}", self.url),
+            }
+        }
+        Ok(calendar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::Body,
+        http::{HeaderMap, Request, StatusCode},
+        response::IntoResponse,
+        routing::any,
+    };
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Zurich;
+
+    use super::*;
+    use crate::testing;
+
+    fn recorded(name: &str) -> String {
+        let path: std::path::PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "calendar", name]
+            .iter()
+            .collect();
+        std::fs::read_to_string(path).expect("Fixture is readable")
+    }
+
+    // Replays the recorded Radicale responses for REPORT requests that carry
+    // the expected credentials.
+    async fn mock_server() -> Url {
+        async fn report(headers: HeaderMap, request: Request<Body>) -> impl IntoResponse {
+            // "alice:secret" and the token of the second account.
+            let authorized = matches!(
+                headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok()),
+                Some("Basic YWxpY2U6c2VjcmV0" | "Bearer t0ken")
+            );
+            if !authorized {
+                return (StatusCode::UNAUTHORIZED, String::new());
+            }
+            if request.method().as_str() != "REPORT"
+                || headers.get("Depth").and_then(|v| v.to_str().ok()) != Some("1")
+            {
+                return (StatusCode::METHOD_NOT_ALLOWED, String::new());
+            }
+            let body = axum::body::to_bytes(request.into_body(), usize::MAX)
+                .await
+                .expect("Body is readable");
+            let body = String::from_utf8_lossy(&body);
+            if body.contains(r#"name="VTODO""#) {
+                (StatusCode::MULTI_STATUS, recorded("caldav_todos.xml"))
+            } else if body.contains(r#"<C:time-range start="20240311T000000Z""#) {
+                (StatusCode::MULTI_STATUS, recorded("caldav_events.xml"))
+            } else {
+                (StatusCode::BAD_REQUEST, String::new())
+            }
+        }
+
+        testing::mock_server(Router::new().route("/alice/calendar/", any(report)))
+            .await
+            .join("alice/calendar/")
+            .expect("Valid URL")
+    }
+
+    fn collection(url: Url, auth: Option<Auth>) -> Collection {
+        Collection { url, auth }
+    }
+
+    fn range() -> (DateTime<Utc>, DateTime<Utc>) {
+        let at = |d| {
+            Utc.with_ymd_and_hms(2024, 3, d, 0, 0, 0)
+                .single()
+                .expect("Valid time")
+        };
+        (at(11), at(18))
+    }
+
+    #[test]
+    fn query_limits_time_range() {
+        let body = query(Component::Event, Some(range()));
+        assert!(body.contains(
+            r#"<C:comp-filter name="VEVENT"><C:time-range start="20240311T000000Z" end="20240318T000000Z"/></C:comp-filter>"#
+        ));
+        assert!(
+            query(Component::Todo, None)
+                .contains(r#"<C:comp-filter name="VTODO"></C:comp-filter>"#)
+        );
+    }
+
+    #[test]
+    fn calendar_data_ignores_prefixes() {
+        let objects = calendar_data(concat!(
+            r#"<multistatus xmlns="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">"#,
+            "<response><propstat><prop>",
+            "<cal:calendar-data><![CDATA[BEGIN:VCALENDAR\nEND:VCALENDAR]]></cal:calendar-data>",
+            "</prop></propstat></response>",
+            "<response><propstat><prop>",
+            "<calendar-data>not caldav</calendar-data>",
+            "</prop></propstat></response>",
+            "</multistatus>",
+        ))
+        .expect("Response parses");
+        assert_eq!(objects, ["BEGIN:VCALENDAR\nEND:VCALENDAR"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_events_with_basic_auth() {
+        let url = mock_server().await;
+        let calendar = collection(
+            url,
+            Some(Auth::Basic {
+                user: "alice".into(),
+                password: "secret".into(),
+            }),
+        )
+        .fetch(Component::Event, Some(range()))
+        .await
+        .expect("Events are fetched");
+        let from = ics::localize(Zurich, range().0.naive_utc());
+        let to = ics::localize(Zurich, range().1.naive_utc());
+        let summaries: Vec<_> = calendar
+            .occurrences(Zurich, from, to)
+            .into_iter()
+            .map(|o| o.summary)
+            .collect();
+        assert_eq!(summaries, ["Standup", "Dentist", "Standup"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_todos_with_bearer_auth() {
+        let url = mock_server().await;
+        let calendar = collection(
+            url,
+            Some(Auth::Bearer {
+                token: "t0ken".into(),
+            }),
+        )
+        .fetch(Component::Todo, None)
+        .await
+        .expect("Tasks are fetched");
+        let open: Vec<_> = calendar.open_todos().map(|t| t.summary.as_str()).collect();
+        assert_eq!(open, ["Renew passport", "Water plants"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_rejects_missing_credentials() {
+        let url = mock_server().await;
+        let result = collection(url, None).fetch(Component::Todo, None).await;
+        assert!(matches!(
+            result,
+            Err(generator::Error::Fetch {
+                kind: generator::FetchErrorKind::Request(StatusCode::UNAUTHORIZED),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,793 @@
+use std::collections::HashSet;
+
+use chrono::{
+    DateTime, Days, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use log::warn;
+
+const MAX_OCCURRENCES: u16 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the data is not an iCalendar")]
+    NotACalendar,
+    #[error("invalid date or time `{0}`")]
+    DateTime(String),
+    #[error("invalid duration `{0}`")]
+    Duration(String),
+    #[error("invalid recurrence rule")]
+    Rule(#[from] rrule::RRuleError),
+}
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        // The value starts at the first colon outside of a quoted parameter.
+        let mut quoted = false;
+        let split = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        })?;
+        let (head, value) = (&line[..split], &line[split + 1..]);
+        let mut parts = head.split(';');
+        let name = parts.next()?.to_ascii_uppercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"').to_owned()))
+            .collect();
+        Some(Self {
+            name,
+            params,
+            value: value.to_owned(),
+        })
+    }
+}
+
+// Long lines are folded by inserting a line break followed by a space or tab.
+fn unfold(data: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Time {
+    Date(NaiveDate),
+    // Local time wherever the calendar is viewed.
+    Floating(NaiveDateTime),
+    Zoned(DateTime<Tz>),
+}
+
+impl Time {
+    fn parse(value: &str, prop: &Property) -> Result<Self, Error> {
+        let invalid = || Error::DateTime(value.to_owned());
+        if prop.param("VALUE") == Some("DATE") || value.len() == 8 {
+            return NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(Self::Date)
+                .map_err(|_| invalid());
+        }
+        if let Some(utc) = value.strip_suffix('Z') {
+            let time =
+                NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+            return Ok(Self::Zoned(Tz::UTC.from_utc_datetime(&time)));
+        }
+        let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        let Some(tzid) = prop.param("TZID") else {
+            return Ok(Self::Floating(time));
+        };
+        Ok(tzid.parse::<Tz>().map_or_else(
+            |_| {
+                warn!("Unknown timezone {tzid}, treating {value} as local time");
+                Self::Floating(time)
+            },
+            |tz| Self::Zoned(localize(tz, time)),
+        ))
+    }
+
+    fn parse_list(prop: &Property) -> Result<Vec<Self>, Error> {
+        prop.value
+            .split(',')
+            .map(|value| Self::parse(value, prop))
+            .collect()
+    }
+
+    // All-day values are expanded at midnight UTC and read back as dates.
+    fn resolve(self, tz: Tz) -> DateTime<rrule::Tz> {
+        match self {
+            Self::Date(date) => rrule::Tz::UTC.from_utc_datetime(&date.and_time(NaiveTime::MIN)),
+            Self::Floating(time) => localize(tz, time).with_timezone(&rrule::Tz::Tz(tz)),
+            Self::Zoned(time) => time.with_timezone(&rrule::Tz::Tz(time.timezone())),
+        }
+    }
+
+    // Dates stand for local midnight in `tz`.
+    pub fn to_utc(self, tz: Tz) -> DateTime<Utc> {
+        match self {
+            Self::Date(date) => localize(tz, date.and_time(NaiveTime::MIN)).with_timezone(&Utc),
+            Self::Floating(time) => localize(tz, time).with_timezone(&Utc),
+            Self::Zoned(time) => time.with_timezone(&Utc),
+        }
+    }
+}
+
+// Times inside a DST gap are read with the offset from before the gap, which
+// moves them forward as RFC 5545 requires. Repeated times use the first one.
+pub fn localize(tz: Tz, time: NaiveDateTime) -> DateTime<Tz> {
+    tz.from_local_datetime(&time).earliest().unwrap_or_else(|| {
+        let before = tz
+            .offset_from_utc_datetime(&(time - TimeDelta::days(1)))
+            .fix();
+        tz.from_utc_datetime(&(time - before))
+    })
+}
+
+fn parse_duration(value: &str) -> Result<TimeDelta, Error> {
+    let invalid = || Error::Duration(value.to_owned());
+    let negative = value.starts_with('-');
+    let rest = value.trim_start_matches(['-', '+']);
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' {
+            continue;
+        }
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        let part = match c {
+            'W' => TimeDelta::try_weeks(n),
+            'D' => TimeDelta::try_days(n),
+            'H' => TimeDelta::try_hours(n),
+            'M' => TimeDelta::try_minutes(n),
+            'S' => TimeDelta::try_seconds(n),
+            _ => return Err(invalid()),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(invalid)?;
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(if negative { -total } else { total })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Span {
+    // Both days are included.
+    AllDay {
+        first: NaiveDate,
+        last: NaiveDate,
+    },
+    Timed {
+        start: DateTime<Tz>,
+        end: DateTime<Tz>,
+    },
+}
+
+impl Span {
+    pub fn first_day(&self) -> NaiveDate {
+        match self {
+            Self::AllDay { first, .. } => *first,
+            Self::Timed { start, .. } => start.date_naive(),
+        }
+    }
+
+    // Events ending exactly at midnight do not reach into the next day.
+    pub fn last_day(&self) -> NaiveDate {
+        match self {
+            Self::AllDay { last, .. } => *last,
+            Self::Timed { start, end } if end > start && end.time() == NaiveTime::MIN => {
+                end.date_naive() - Days::new(1)
+            }
+            Self::Timed { end, .. } => end.date_naive(),
+        }
+    }
+
+    pub fn covers(&self, day: NaiveDate) -> bool {
+        (self.first_day()..=self.last_day()).contains(&day)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub summary: String,
+    pub location: Option<String>,
+    pub span: Span,
+}
+
+impl Occurrence {
+    fn sort_key(&self) -> (NaiveDate, bool, Option<DateTime<Tz>>) {
+        match &self.span {
+            Span::AllDay { first, .. } => (*first, false, None),
+            Span::Timed { start, .. } => (start.date_naive(), true, Some(*start)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Event {
+    uid: String,
+    summary: String,
+    location: Option<String>,
+    start: Option<Time>,
+    end: Option<Time>,
+    duration: Option<TimeDelta>,
+    rule: Option<String>,
+    exdates: Vec<Time>,
+    rdates: Vec<Time>,
+    recurrence_id: Option<Time>,
+    cancelled: bool,
+}
+
+impl Event {
+    fn set(&mut self, prop: &Property) -> Result<(), Error> {
+        match prop.name.as_str() {
+            "UID" => self.uid.clone_from(&prop.value),
+            "SUMMARY" => self.summary = unescape(&prop.value),
+            "LOCATION" => self.location = Some(unescape(&prop.value)),
+            "DTSTART" => self.start = Some(Time::parse(&prop.value, prop)?),
+            "DTEND" => self.end = Some(Time::parse(&prop.value, prop)?),
+            "DURATION" => self.duration = Some(parse_duration(&prop.value)?),
+            "RRULE" => self.rule = Some(prop.value.clone()),
+            "EXDATE" => self.exdates.extend(Time::parse_list(prop)?),
+            "RDATE" => self.rdates.extend(Time::parse_list(prop)?),
+            "RECURRENCE-ID" => self.recurrence_id = Some(Time::parse(&prop.value, prop)?),
+            "STATUS" => self.cancelled = prop.value.eq_ignore_ascii_case("CANCELLED"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn length(&self, start: Time, tz: Tz) -> TimeDelta {
+        match (self.end, self.duration) {
+            (Some(end), _) => end.resolve(tz) - start.resolve(tz),
+            (None, Some(duration)) => duration,
+            (None, None) if matches!(start, Time::Date(_)) => TimeDelta::days(1),
+            (None, None) => TimeDelta::zero(),
+        }
+    }
+
+    // Fails for lengths that reach past the dates chrono can represent.
+    fn span(&self, start: DateTime<rrule::Tz>, length: TimeDelta, tz: Tz) -> Result<Span, Error> {
+        let too_long = || Error::Duration(length.to_string());
+        if matches!(self.start, Some(Time::Date(_))) {
+            let first = start.date_naive();
+            let days = length.num_days().max(1).unsigned_abs();
+            Ok(Span::AllDay {
+                first,
+                last: first
+                    .checked_add_days(Days::new(days - 1))
+                    .ok_or_else(too_long)?,
+            })
+        } else {
+            let start = start.with_timezone(&tz);
+            Ok(Span::Timed {
+                start,
+                end: start.checked_add_signed(length).ok_or_else(too_long)?,
+            })
+        }
+    }
+
+    // Start times of all instances that may overlap the window.
+    fn starts(
+        &self,
+        start: Time,
+        tz: Tz,
+        window: (DateTime<rrule::Tz>, DateTime<rrule::Tz>),
+        overridden: &HashSet<(&str, DateTime<Utc>)>,
+    ) -> Result<Vec<DateTime<rrule::Tz>>, Error> {
+        let dtstart = start.resolve(tz);
+        let (from, to) = window;
+        let mut starts = match &self.rule {
+            Some(rule) => {
+                let mut set = rule
+                    .parse::<rrule::RRule<rrule::Unvalidated>>()?
+                    .build(dtstart)?;
+                for exdate in &self.exdates {
+                    set = set.exdate(exdate.resolve(tz));
+                }
+                for rdate in &self.rdates {
+                    set = set.rdate(rdate.resolve(tz));
+                }
+                set.after(from).before(to).all(MAX_OCCURRENCES).dates
+            }
+            None => std::iter::once(dtstart)
+                .chain(self.rdates.iter().map(|r| r.resolve(tz)))
+                .filter(|s| (from..=to).contains(s))
+                .collect(),
+        };
+        starts.retain(|s| !overridden.contains(&(self.uid.as_str(), s.with_timezone(&Utc))));
+        Ok(starts)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Todo {
+    pub summary: String,
+    pub description: String,
+    pub start: Option<Time>,
+    pub due: Option<Time>,
+    // 1 is the highest priority, 9 the lowest and 0 means undefined.
+    pub priority: u8,
+    pub done: bool,
+}
+
+impl Todo {
+    fn set(&mut self, prop: &Property) -> Result<(), Error> {
+        match prop.name.as_str() {
+            "SUMMARY" => self.summary = unescape(&prop.value),
+            "DESCRIPTION" => self.description = unescape(&prop.value),
+            "DTSTART" => self.start = Some(Time::parse(&prop.value, prop)?),
+            "DUE" => self.due = Some(Time::parse(&prop.value, prop)?),
+            "PRIORITY" => self.priority = prop.value.trim().parse().unwrap_or_default(),
+            "STATUS" => {
+                self.done |= matches!(
+                    prop.value.to_ascii_uppercase().as_str(),
+                    "COMPLETED" | "CANCELLED"
+                );
+            }
+            "COMPLETED" => self.done = true,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+enum Component {
+    Event(Event),
+    Todo(Todo),
+}
+
+// Events and tasks of one or more merged calendars.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    events: Vec<Event>,
+    todos: Vec<Todo>,
+}
+
+impl Calendar {
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut events = Vec::new();
+        let mut todos = Vec::new();
+        let mut seen_calendar = false;
+        let mut current: Option<Component> = None;
+        let mut nested = 0usize;
+        for line in unfold(data) {
+            let Some(prop) = Property::parse(line.trim_end()) else {
+                continue;
+            };
+            match (prop.name.as_str(), prop.value.to_ascii_uppercase().as_str()) {
+                ("BEGIN", "VCALENDAR") => seen_calendar = true,
+                ("BEGIN", "VEVENT") if current.is_none() => {
+                    current = Some(Component::Event(Event::default()));
+                }
+                ("BEGIN", "VTODO") if current.is_none() => {
+                    current = Some(Component::Todo(Todo::default()));
+                }
+                ("END", "VEVENT" | "VTODO") if nested == 0 => match current.take() {
+                    Some(Component::Event(event)) => events.push(event),
+                    Some(Component::Todo(todo)) => todos.push(todo),
+                    None => {}
+                },
+                // Alarms and other components inside an event have their own DTSTART.
+                ("BEGIN", _) if current.is_some() => nested += 1,
+                ("END", _) if current.is_some() => nested = nested.saturating_sub(1),
+                _ => {
+                    let result = match (&mut current, nested) {
+                        (Some(Component::Event(event)), 0) => event.set(&prop),
+                        (Some(Component::Todo(todo)), 0) => todo.set(&prop),
+                        _ => Ok(()),
+                    };
+                    if let Err(e) = result {
+                        warn!("Skipping property {}: {e}", prop.name);
+                    }
+                }
+            }
+        }
+        if !seen_calendar {
+            return Err(Error::NotACalendar);
+        }
+        Ok(Self { events, todos })
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.events.extend(other.events);
+        self.todos.extend(other.todos);
+    }
+
+    // Tasks that are neither completed nor cancelled.
+    pub fn open_todos(&self) -> impl Iterator<Item = &Todo> {
+        self.todos.iter().filter(|todo| !todo.done)
+    }
+
+    // Occurrences overlapping `[from, to)`, in `tz` and sorted by start.
+    pub fn occurrences(&self, tz: Tz, from: DateTime<Tz>, to: DateTime<Tz>) -> Vec<Occurrence> {
+        let overridden: HashSet<_> = self
+            .events
+            .iter()
+            .filter_map(|e| {
+                let id = e.recurrence_id?;
+                Some((e.uid.as_str(), id.resolve(tz).with_timezone(&Utc)))
+            })
+            .collect();
+        let mut occurrences = Vec::new();
+        for event in &self.events {
+            let Some(start) = event.start else {
+                warn!("Skipping event {:?} without a start", event.summary);
+                continue;
+            };
+            if event.cancelled {
+                continue;
+            }
+            let length = event.length(start, tz);
+            // All-day events cover whole local days, wherever their UTC stand-in falls.
+            let window = if matches!(start, Time::Date(_)) {
+                let midnight = |t: DateTime<Tz>| Time::Date(t.date_naive()).resolve(tz);
+                midnight(from)
+                    .checked_sub_signed(length)
+                    .map(|first| (first, midnight(to)))
+            } else {
+                from.checked_sub_signed(length).map(|first| {
+                    (
+                        first.with_timezone(&rrule::Tz::UTC),
+                        to.with_timezone(&rrule::Tz::UTC),
+                    )
+                })
+            };
+            let Some(window) = window else {
+                warn!("Skipping event {:?}: it lasts too long", event.summary);
+                continue;
+            };
+            let starts = if event.recurrence_id.is_some() {
+                event.starts(start, tz, window, &HashSet::new())
+            } else {
+                event.starts(start, tz, window, &overridden)
+            };
+            let starts = match starts {
+                Ok(starts) => starts,
+                Err(e) => {
+                    warn!("Skipping event {:?}: {e}", event.summary);
+                    continue;
+                }
+            };
+            occurrences.extend(
+                starts
+                    .into_iter()
+                    .filter_map(|s| {
+                        event
+                            .span(s, length, tz)
+                            .inspect_err(|e| warn!("Skipping event {:?}: {e}", event.summary))
+                            .ok()
+                    })
+                    .filter(|span| match span {
+                        Span::AllDay { first, last } => {
+                            *first < to.date_naive() && *last >= from.date_naive()
+                        }
+                        Span::Timed { start, end } => {
+                            *start < to && (*end > from || (start == end && *start >= from))
+                        }
+                    })
+                    .map(|span| Occurrence {
+                        summary: event.summary.clone(),
+                        location: event.location.clone(),
+                        span,
+                    }),
+            );
+        }
+        occurrences.sort_by_key(Occurrence::sort_key);
+        occurrences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Timelike};
+    use chrono_tz::{America::New_York, Europe::Zurich};
+
+    use super::*;
+
+    fn fixture(name: &str) -> Calendar {
+        let path: std::path::PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "calendar", name]
+            .iter()
+            .collect();
+        Calendar::parse(&std::fs::read_to_string(path).expect("Fixture is readable"))
+            .expect("Fixture parses")
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).expect("Valid date")
+    }
+
+    fn midnight(tz: Tz, day: NaiveDate) -> DateTime<Tz> {
+        localize(tz, day.and_time(NaiveTime::MIN))
+    }
+
+    fn between(cal: &Calendar, tz: Tz, from: NaiveDate, to: NaiveDate) -> Vec<Occurrence> {
+        cal.occurrences(tz, midnight(tz, from), midnight(tz, to))
+    }
+
+    fn starts(occurrences: &[Occurrence]) -> Vec<String> {
+        occurrences
+            .iter()
+            .map(|o| match &o.span {
+                Span::Timed { start, .. } => start.format("%m-%d %H:%M").to_string(),
+                Span::AllDay { first, .. } => first.format("%m-%d").to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_unfolds_and_unescapes() {
+        let cal = Calendar::parse(concat!(
+            "BEGIN:VCALENDAR\r\n",
+            "BEGIN:VEVENT\r\n",
+            "DTSTART:20240102T100000Z\r\n",
+            "SUMMARY:Lunch\\, then a very long\r\n",
+            "  walk\r\n",
+            "LOCATION;LANGUAGE=\"en:gb\":Park\\nEntrance\r\n",
+            "BEGIN:VALARM\r\n",
+            "DTSTART:20240101T000000Z\r\n",
+            "END:VALARM\r\n",
+            "END:VEVENT\r\n",
+            "END:VCALENDAR\r\n",
+        ))
+        .expect("Calendar parses");
+        let [event] = &cal.events[..] else {
+            panic!("Exactly one event is parsed");
+        };
+        assert_eq!(event.summary, "Lunch, then a very long walk");
+        assert_eq!(event.location.as_deref(), Some("Park\nEntrance"));
+        assert_eq!(
+            event.start,
+            Some(Time::Zoned(
+                Tz::UTC
+                    .with_ymd_and_hms(2024, 1, 2, 10, 0, 0)
+                    .single()
+                    .expect("Valid time")
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_reads_todos() {
+        let cal = Calendar::parse(concat!(
+            "BEGIN:VCALENDAR\r\n",
+            "BEGIN:VTODO\r\n",
+            "SUMMARY:Buy milk\r\n",
+            "DUE;VALUE=DATE:20240105\r\n",
+            "PRIORITY:1\r\n",
+            "BEGIN:VALARM\r\n",
+            "SUMMARY:Reminder\r\n",
+            "END:VALARM\r\n",
+            "END:VTODO\r\n",
+            "BEGIN:VTODO\r\n",
+            "SUMMARY:File taxes\r\n",
+            "STATUS:COMPLETED\r\n",
+            "END:VTODO\r\n",
+            "END:VCALENDAR\r\n",
+        ))
+        .expect("Calendar parses");
+        assert!(cal.events.is_empty());
+        let open: Vec<_> = cal.open_todos().collect();
+        let [todo] = &open[..] else {
+            panic!("Exactly one open task is parsed");
+        };
+        assert_eq!(todo.summary, "Buy milk");
+        assert_eq!(todo.priority, 1);
+        assert_eq!(
+            todo.due.map(|due| due.to_utc(Zurich)),
+            Utc.with_ymd_and_hms(2024, 1, 4, 23, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_other_data() {
+        assert!(matches!(
+            Calendar::parse("<html></html>"),
+            Err(Error::NotACalendar)
+        ));
+    }
+
+    #[test]
+    fn parse_duration_formats() {
+        assert_eq!(
+            parse_duration("PT1H30M").expect("Valid duration"),
+            TimeDelta::minutes(90)
+        );
+        assert_eq!(
+            parse_duration("P1W").expect("Valid duration"),
+            TimeDelta::days(7)
+        );
+        assert_eq!(
+            parse_duration("-P1DT2H").expect("Valid duration"),
+            -TimeDelta::hours(26)
+        );
+        assert!(parse_duration("1H").is_err());
+    }
+
+    #[test]
+    fn oversized_durations_are_rejected() {
+        assert!(matches!(
+            parse_duration("P99999999999999W"),
+            Err(Error::Duration(_))
+        ));
+        assert!(matches!(
+            parse_duration("PT9223372036854775807S"),
+            Err(Error::Duration(_))
+        ));
+        // Representable, but far beyond any date chrono can hold.
+        let cal = Calendar::parse(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:long\nSUMMARY:Forever\n\
+             DTSTART:20240101T090000Z\nDURATION:P99999999W\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:short\nSUMMARY:Meeting\n\
+             DTSTART:20240102T090000Z\nDURATION:PT1H\nEND:VEVENT\nEND:VCALENDAR\n",
+        )
+        .expect("Calendar parses");
+        let occurrences = between(&cal, Zurich, date(2024, 1, 1), date(2024, 1, 8));
+        assert_eq!(starts(&occurrences), ["01-02 10:00"]);
+    }
+
+    #[test]
+    fn recurring_events_honour_exdate_and_overrides() {
+        let cal = fixture("recurring.ics");
+        let occurrences = between(&cal, Zurich, date(2024, 1, 1), date(2024, 1, 15));
+        assert_eq!(
+            starts(&occurrences),
+            [
+                // Weekly standup on Mondays and Wednesdays, with Jan 3 excluded
+                // and Jan 8 moved to the afternoon.
+                "01-01 09:00",
+                "01-08 14:00",
+                "01-10 09:00",
+            ]
+        );
+        assert_eq!(occurrences[1].summary, "Standup (moved)");
+    }
+
+    #[test]
+    fn recurring_events_stop_at_count() {
+        let cal = fixture("recurring.ics");
+        let occurrences = between(&cal, Zurich, date(2024, 2, 1), date(2024, 3, 1));
+        assert!(occurrences.iter().all(|o| o.summary != "Standup"));
+    }
+
+    #[test]
+    fn all_day_events_span_whole_days() {
+        let cal = fixture("all_day.ics");
+        let occurrences = between(&cal, New_York, date(2024, 3, 1), date(2024, 3, 8));
+        let spans: Vec<_> = occurrences
+            .iter()
+            .map(|o| (o.summary.as_str(), o.span.clone()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (
+                    "Conference",
+                    Span::AllDay {
+                        first: date(2024, 3, 4),
+                        last: date(2024, 3, 6)
+                    }
+                ),
+                (
+                    "Birthday",
+                    Span::AllDay {
+                        first: date(2024, 3, 5),
+                        last: date(2024, 3, 5)
+                    }
+                ),
+            ]
+        );
+        assert!(occurrences[0].span.covers(date(2024, 3, 6)));
+        assert!(!occurrences[0].span.covers(date(2024, 3, 7)));
+    }
+
+    #[test]
+    fn all_day_events_recur_yearly() {
+        let cal = fixture("all_day.ics");
+        let occurrences = between(&cal, Zurich, date(2027, 3, 1), date(2027, 3, 31));
+        assert_eq!(starts(&occurrences), ["03-05"]);
+    }
+
+    #[test]
+    fn recurrences_keep_local_time_across_dst() {
+        let cal = fixture("dst.ics");
+        // The call is at 09:00 in New York. Europe switches to summer time three
+        // weeks after the US, so it moves in Zurich for those weeks.
+        let occurrences = between(&cal, Zurich, date(2024, 3, 1), date(2024, 4, 6));
+        let calls: Vec<_> = occurrences
+            .into_iter()
+            .filter(|o| o.summary == "Call with New York")
+            .collect();
+        assert_eq!(
+            starts(&calls),
+            [
+                "03-05 15:00",
+                "03-12 14:00",
+                "03-19 14:00",
+                "03-26 14:00",
+                "04-02 15:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn times_in_dst_gap_move_forward() {
+        let cal = fixture("dst.ics");
+        let occurrences = between(&cal, Zurich, date(2024, 3, 31), date(2024, 4, 1));
+        let gap = occurrences
+            .iter()
+            .find(|o| o.summary == "Early train")
+            .expect("Event in the DST gap is kept");
+        let Span::Timed { start, end } = &gap.span else {
+            panic!("Event is timed");
+        };
+        assert_eq!((start.hour(), start.minute()), (3, 30));
+        assert_eq!(*end - *start, TimeDelta::hours(1));
+    }
+
+    #[test]
+    fn floating_events_follow_the_viewer() {
+        let cal = fixture("dst.ics");
+        let zurich = between(&cal, Zurich, date(2024, 4, 10), date(2024, 4, 11));
+        let new_york = between(&cal, New_York, date(2024, 4, 10), date(2024, 4, 11));
+        assert_eq!(starts(&zurich), ["04-10 07:00"]);
+        assert_eq!(starts(&new_york), ["04-10 07:00"]);
+    }
+
+    #[test]
+    fn timed_events_ending_at_midnight_stay_on_their_day() {
+        let span = Span::Timed {
+            start: localize(
+                Zurich,
+                date(2024, 1, 1).and_hms_opt(22, 0, 0).expect("Valid time"),
+            ),
+            end: midnight(Zurich, date(2024, 1, 2)),
+        };
+        assert_eq!(span.last_day(), date(2024, 1, 1));
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use log::error;
+
+use super::{Source, caldav, ics, load_all, timezone};
+use crate::{
+    generator::{self, RenderContext},
+    plugins::ticktick::{self, Priority},
+    storage,
+};
+
+// RFC 5545 splits its nine levels into high, medium and low thirds.
+const fn priority(level: u8) -> Priority {
+    match level {
+        1..=4 => Priority::High,
+        5 => Priority::Medium,
+        6..=9 => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+// Open VTODOs from calendar files or CalDAV collections, shown with the same
+// layout as TickTick tasks.
+pub struct TasksPlugin {
+    sources: Vec<Source>,
+}
+
+impl TasksPlugin {
+    pub fn new(sources: Vec<Source>) -> Result<Self, storage::LoadError> {
+        if sources.is_empty() {
+            error!("A task list needs at least one source");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self { sources })
+    }
+
+    // Due tasks come first, most urgent at the top.
+    fn tasks(calendar: &ics::Calendar, tz: Tz) -> Vec<ticktick::Task> {
+        let mut todos: Vec<_> = calendar.open_todos().collect();
+        todos.sort_by_key(|todo| {
+            (
+                todo.due
+                    .map_or(DateTime::<Utc>::MAX_UTC, |due| due.to_utc(tz)),
+                if todo.priority == 0 {
+                    10
+                } else {
+                    todo.priority
+                },
+            )
+        });
+        todos
+            .into_iter()
+            .map(|todo| {
+                ticktick::Task::new(
+                    todo.summary.clone(),
+                    todo.description.clone(),
+                    todo.start.map(|start| start.to_utc(tz)),
+                    todo.due.map(|due| due.to_utc(tz)),
+                    priority(todo.priority),
+                )
+            })
+            .collect()
+    }
+}
+
+impl generator::Content for TasksPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let calendar = load_all(&self.sources, caldav::Component::Todo, None).await?;
+            let tasks = Self::tasks(&calendar, timezone(ctx));
+            Ok(ticktick::content(&tasks, Utc::now()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Content;
+
+    #[test]
+    fn priority_levels() {
+        assert!(matches!(priority(1), Priority::High));
+        assert!(matches!(priority(5), Priority::Medium));
+        assert!(matches!(priority(9), Priority::Low));
+        assert!(matches!(priority(0), Priority::None));
+    }
+
+    #[test]
+    fn new_requires_sources() {
+        assert!(TasksPlugin::new(vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn generate_lists_open_tasks_by_due_date() {
+        let path: std::path::PathBuf =
+            [env!("CARGO_MANIFEST_DIR"), "tests", "calendar", "tasks.ics"]
+                .iter()
+                .collect();
+        let plugin = TasksPlugin::new(vec![Source::Local(path)]).expect("Plugin is configured");
+        let ctx = RenderContext::new("d").with_region(Some("Europe/Zurich"), None);
+        let html = plugin.generate(&ctx).await.expect("Tasks render");
+        let order: Vec<_> = ["Pay rent", "Book flights", "Sort photos"]
+            .iter()
+            .map(|title| html.find(title).expect("Open task is listed"))
+            .collect();
+        assert!(order.is_sorted());
+        assert!(html.contains("iconoir-priority-high"));
+        assert!(!html.contains("Old chore"));
+    }
+}
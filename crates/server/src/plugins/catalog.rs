@@ -0,0 +1,360 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{PluginConfig, wasm};
+
+const MANIFEST: &str = "plugin.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read {path}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("the plugin manifest at {path} is invalid")]
+    Manifest {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("the entry point {0} does not exist")]
+    MissingEntry(PathBuf),
+    #[error("plugin {0} is installed more than once")]
+    Duplicate(String),
+    #[error("plugin {0} is not installed")]
+    NotInstalled(String),
+    #[error("the config of plugin {0} must be a table")]
+    NotATable(String),
+    #[error("plugin {plugin} requires the config key `{key}`")]
+    MissingKey { plugin: String, key: String },
+    #[error("plugin {plugin} does not know the config key `{key}`")]
+    UnknownKey { plugin: String, key: String },
+    #[error("config key `{key}` of plugin {plugin} must be {expected}")]
+    WrongType {
+        plugin: String,
+        key: String,
+        expected: FieldType,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+    Table,
+}
+
+impl FieldType {
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            Self::String => value.is_string(),
+            Self::Integer => value.is_i64() || value.is_u64(),
+            Self::Number => value.is_number(),
+            Self::Boolean => value.is_boolean(),
+            Self::Array => value.is_array(),
+            Self::Table => value.is_object(),
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Array => "array",
+            Self::Table => "table",
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Field {
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    // Keys without a default are required.
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub description: String,
+}
+
+// The `plugin.toml` found in every plugin folder.
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub entry: PathBuf,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub config: BTreeMap<String, Field>,
+    #[serde(default)]
+    pub templates: HashMap<String, PathBuf>,
+    pub refresh_secs: Option<u64>,
+}
+
+impl Manifest {
+    fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(MANIFEST);
+        let data = fs::read_to_string(&path).map_err(|source| Error::Read {
+            path: path.clone(),
+            source,
+        })?;
+        let mut manifest: Self =
+            toml::from_str(&data).map_err(|source| Error::Manifest { path, source })?;
+        // Paths in the manifest are relative to the plugin folder.
+        manifest.entry = dir.join(&manifest.entry);
+        if !manifest.entry.is_file() {
+            return Err(Error::MissingEntry(manifest.entry));
+        }
+        for template in manifest.templates.values_mut() {
+            *template = dir.join(&*template);
+        }
+        Ok(manifest)
+    }
+
+    // Checks a device's config against the schema and fills in the defaults.
+    pub fn validate(&self, config: Value) -> Result<Value, Error> {
+        let mut config = match config {
+            Value::Null => Map::new(),
+            Value::Object(config) => config,
+            _ => return Err(Error::NotATable(self.name.clone())),
+        };
+        if let Some(key) = config.keys().find(|k| !self.config.contains_key(*k)) {
+            return Err(Error::UnknownKey {
+                plugin: self.name.clone(),
+                key: key.clone(),
+            });
+        }
+        for (key, field) in &self.config {
+            if !config.contains_key(key) {
+                let Some(default) = &field.default else {
+                    return Err(Error::MissingKey {
+                        plugin: self.name.clone(),
+                        key: key.clone(),
+                    });
+                };
+                config.insert(key.clone(), default.clone());
+            }
+            if !field.kind.accepts(&config[key]) {
+                return Err(Error::WrongType {
+                    plugin: self.name.clone(),
+                    key: key.clone(),
+                    expected: field.kind,
+                });
+            }
+        }
+        Ok(Value::Object(config))
+    }
+}
+
+// Plugins discovered in `--plugins_dir`, keyed by their manifest name.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    plugins: BTreeMap<String, Manifest>,
+}
+
+impl Catalog {
+    pub fn discover(dir: &Path) -> Result<Self, Error> {
+        let entries = fs::read_dir(dir).map_err(|source| Error::Read {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+        let mut plugins = BTreeMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|source| Error::Read {
+                    path: dir.to_path_buf(),
+                    source,
+                })?
+                .path();
+            if !path.join(MANIFEST).is_file() {
+                if path.is_dir() {
+                    warn!("Skipping {}: no {MANIFEST}", path.display());
+                }
+                continue;
+            }
+            let manifest = Manifest::load(&path)?;
+            info!(
+                "Discovered plugin {} {} in {}",
+                manifest.name,
+                manifest.version,
+                path.display()
+            );
+            if plugins.contains_key(&manifest.name) {
+                return Err(Error::Duplicate(manifest.name));
+            }
+            plugins.insert(manifest.name.clone(), manifest);
+        }
+        Ok(Self { plugins })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Manifest> {
+        self.plugins.values()
+    }
+
+    // Turns a reference to an installed plugin into the WASM plugin it stands
+    // for. Every other kind of plugin is returned as is.
+    pub fn resolve(&self, config: PluginConfig) -> Result<PluginConfig, Error> {
+        let PluginConfig::Installed {
+            plugin,
+            name,
+            config,
+            state,
+        } = config
+        else {
+            return Ok(config);
+        };
+        let manifest = self
+            .plugins
+            .get(&plugin)
+            .ok_or_else(|| Error::NotInstalled(plugin.clone()))?;
+        Ok(PluginConfig::Wasm {
+            name: name.unwrap_or(plugin),
+            path: manifest.entry.clone(),
+            config: manifest.validate(config)?,
+            limits: wasm::Limits {
+                allowed_hosts: manifest.hosts.clone(),
+                ..wasm::Limits::default()
+            },
+            state,
+            templates: manifest.templates.clone(),
+            refresh_secs: manifest.refresh_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn install(root: &Path, folder: &str, manifest: &str) {
+        let dir = root.join(folder);
+        fs::create_dir_all(&dir).expect("Failed to create plugin folder");
+        fs::write(dir.join(MANIFEST), manifest).expect("Failed to write manifest");
+        fs::write(dir.join("plugin.wasm"), "").expect("Failed to write entry point");
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("atrmnl_catalog_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Failed to create plugins dir");
+        dir
+    }
+
+    const FORECAST: &str = r#"
+name = "forecast"
+version = "1.2.0"
+entry = "plugin.wasm"
+hosts = ["api.example.com"]
+
+[config.city]
+type = "string"
+
+[config.days]
+type = "integer"
+default = 3
+"#;
+
+    fn forecast() -> Manifest {
+        toml::from_str(FORECAST).expect("Manifest parses")
+    }
+
+    #[test]
+    fn discover_reads_manifests() {
+        let dir = temp_dir("discover");
+        install(&dir, "forecast", FORECAST);
+        fs::create_dir_all(dir.join("scratch")).expect("Failed to create folder");
+        let catalog = Catalog::discover(&dir).expect("Plugins are discovered");
+        fs::remove_dir_all(&dir).expect("Failed to remove plugins dir");
+
+        let plugins: Vec<_> = catalog.iter().collect();
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].version, "1.2.0");
+        assert_eq!(plugins[0].entry, dir.join("forecast").join("plugin.wasm"));
+    }
+
+    #[test]
+    fn discover_rejects_duplicates() {
+        let dir = temp_dir("duplicates");
+        install(&dir, "a", FORECAST);
+        install(&dir, "b", FORECAST);
+        let result = Catalog::discover(&dir);
+        fs::remove_dir_all(&dir).expect("Failed to remove plugins dir");
+        assert!(matches!(result, Err(Error::Duplicate(name)) if name == "forecast"));
+    }
+
+    #[test]
+    fn validate_fills_in_defaults() {
+        assert_eq!(
+            forecast()
+                .validate(json!({"city": "Bern"}))
+                .expect("Config is valid"),
+            json!({"city": "Bern", "days": 3})
+        );
+    }
+
+    #[test]
+    fn validate_rejects_invalid_configs() {
+        let manifest = forecast();
+        assert!(matches!(
+            manifest.validate(json!({})),
+            Err(Error::MissingKey { key, .. }) if key == "city"
+        ));
+        assert!(matches!(
+            manifest.validate(json!({"city": "Bern", "cty": "Bern"})),
+            Err(Error::UnknownKey { key, .. }) if key == "cty"
+        ));
+        assert!(matches!(
+            manifest.validate(json!({"city": "Bern", "days": "3"})),
+            Err(Error::WrongType {
+                expected: FieldType::Integer,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn resolve_uses_manifest_hosts() {
+        let catalog = Catalog {
+            plugins: BTreeMap::from([("forecast".into(), forecast())]),
+        };
+        let resolved = catalog
+            .resolve(PluginConfig::Installed {
+                plugin: "forecast".into(),
+                name: None,
+                config: json!({"city": "Bern"}),
+                state: None,
+            })
+            .expect("Plugin resolves");
+        let PluginConfig::Wasm { name, limits, .. } = resolved else {
+            panic!("Installed plugins resolve to WASM plugins");
+        };
+        assert_eq!(name, "forecast");
+        assert_eq!(limits.allowed_hosts, ["api.example.com"]);
+    }
+}
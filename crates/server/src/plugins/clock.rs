@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use log::{error, warn};
+use sailfish::TemplateOnce;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    storage,
+};
+
+// Time between fetching the image and the panel showing it.
+const DEFAULT_LEAD: Duration = Duration::from_secs(10);
+const MIN_REFRESH: Duration = Duration::from_secs(5);
+// Wake-ups aim this far past the minute, so a device waking slightly early
+// still renders the minute that has just begun.
+const SLACK: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ZoneConfig {
+    Name(String),
+    Labelled { label: String, timezone: String },
+}
+
+struct Zone {
+    label: String,
+    tz: Tz,
+}
+
+impl TryFrom<ZoneConfig> for Zone {
+    type Error = String;
+
+    fn try_from(value: ZoneConfig) -> Result<Self, Self::Error> {
+        let (label, name) = match value {
+            // "America/New_York" is labelled "New York".
+            ZoneConfig::Name(name) => (
+                name.rsplit('/').next().unwrap_or(&name).replace('_', " "),
+                name,
+            ),
+            ZoneConfig::Labelled { label, timezone } => (label, timezone),
+        };
+        let tz = name.parse().map_err(|_| name)?;
+        Ok(Self { label, tz })
+    }
+}
+
+struct ZoneTime {
+    label: String,
+    time: String,
+    // Days ahead of or behind the main clock.
+    offset: i64,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "clock.stpl")]
+struct ClockTemplate<'a> {
+    time: String,
+    date: String,
+    week: String,
+    zones: &'a [ZoneTime],
+    compact: bool,
+}
+
+pub struct ClockPlugin {
+    zones: Vec<Zone>,
+    // Minutes between two updates of the clock.
+    interval: u32,
+    lead: Duration,
+    hour12: bool,
+}
+
+impl ClockPlugin {
+    pub fn new(
+        zones: Vec<ZoneConfig>,
+        interval_mins: Option<u32>,
+        lead_secs: Option<u64>,
+        hour12: bool,
+    ) -> Result<Self, storage::LoadError> {
+        let zones = zones
+            .into_iter()
+            .map(Zone::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|name| {
+                error!("Unknown timezone {name} for the clock");
+                storage::LoadError::InvalidConfig
+            })?;
+        let interval = interval_mins.unwrap_or(1);
+        if interval == 0 || 60 % interval != 0 {
+            error!("The clock interval must divide an hour, not {interval} minutes");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self {
+            zones,
+            interval,
+            lead: lead_secs.map_or(DEFAULT_LEAD, Duration::from_secs),
+            hour12,
+        })
+    }
+
+    fn lead(&self) -> TimeDelta {
+        TimeDelta::from_std(self.lead).unwrap_or_default()
+    }
+
+    // The minute the panel shows when the image is rendered at `now`. It is
+    // never shown before it has started.
+    fn display_moment(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let moment = now + self.lead();
+        moment
+            .duration_trunc(TimeDelta::minutes(1))
+            .unwrap_or(moment)
+    }
+
+    // Wakes the device just early enough for the next update to appear right
+    // after the start of its minute.
+    fn until_next(&self, now: DateTime<Utc>) -> Duration {
+        let shown = self.display_moment(now).timestamp() / 60;
+        let interval = i64::from(self.interval);
+        let next = (shown / interval + 1) * interval;
+        let slack = TimeDelta::from_std(SLACK).unwrap_or_default();
+        DateTime::from_timestamp(next * 60, 0)
+            .map(|next| next + slack - self.lead() - now)
+            .and_then(|wait| wait.to_std().ok())
+            .unwrap_or_default()
+            .max(MIN_REFRESH)
+    }
+
+    const fn time_format(&self) -> &'static str {
+        if self.hour12 { "%-I:%M %p" } else { "%H:%M" }
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let tz = ctx.timezone.parse::<Tz>().unwrap_or_else(|_| {
+            warn!(
+                "Unknown timezone {}, showing the clock in UTC",
+                ctx.timezone
+            );
+            Tz::UTC
+        });
+        let moment = self.display_moment(now);
+        let local = moment.with_timezone(&tz);
+        let today = local.date_naive();
+        let zones: Vec<_> = self
+            .zones
+            .iter()
+            .map(|zone| {
+                let there = moment.with_timezone(&zone.tz);
+                ZoneTime {
+                    label: zone.label.clone(),
+                    time: there.format(self.time_format()).to_string(),
+                    offset: (there.date_naive() - today).num_days(),
+                }
+            })
+            .collect();
+        ClockTemplate {
+            time: local.format(self.time_format()).to_string(),
+            date: local.format("%A, %-d %B %Y").to_string(),
+            week: local.format("Week %V").to_string(),
+            zones: &zones,
+            compact: ctx.view == View::Quadrant,
+        }
+        .render_once()
+        .expect("clock template render failed")
+    }
+}
+
+impl generator::Content for ClockPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { Ok(self.render(ctx, Utc::now())) })
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Some(self.until_next(Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 12, 30, h, m, s)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn clock(interval: Option<u32>) -> ClockPlugin {
+        ClockPlugin::new(
+            vec![
+                ZoneConfig::Name("Asia/Tokyo".into()),
+                ZoneConfig::Labelled {
+                    label: "HQ".into(),
+                    timezone: "America/Los_Angeles".into(),
+                },
+            ],
+            interval,
+            None,
+            false,
+        )
+        .expect("Clock is configured")
+    }
+
+    #[test]
+    fn display_moment_includes_lead() {
+        let clock = clock(None);
+        assert_eq!(clock.display_moment(at(11, 59, 50)), at(12, 0, 0));
+        assert_eq!(clock.display_moment(at(11, 59, 58)), at(12, 0, 0));
+        assert_eq!(clock.display_moment(at(11, 59, 10)), at(11, 59, 0));
+        // Noon only shows once it has begun.
+        assert_eq!(clock.display_moment(at(11, 59, 30)), at(11, 59, 0));
+        assert_eq!(clock.display_moment(at(11, 59, 49)), at(11, 59, 0));
+    }
+
+    #[test]
+    fn until_next_aligns_to_minutes() {
+        let clock = clock(None);
+        assert_eq!(clock.until_next(at(11, 59, 50)), Duration::from_secs(62));
+        // A late device catches up with the next minute.
+        assert_eq!(clock.until_next(at(11, 59, 58)), Duration::from_secs(54));
+        let clock = self::clock(Some(5));
+        assert_eq!(clock.until_next(at(12, 1, 20)), Duration::from_secs(212));
+    }
+
+    #[test]
+    fn wake_up_renders_the_started_minute() {
+        let clock = clock(None);
+        let now = at(11, 59, 50);
+        let wake = now + TimeDelta::from_std(clock.until_next(now)).expect("Short wait");
+        assert_eq!(clock.display_moment(wake), at(12, 1, 0));
+        // The image appears after 12:01 has begun, even for a device waking early.
+        assert!(wake + clock.lead() >= at(12, 1, 0));
+        let early = wake - TimeDelta::seconds(1);
+        assert_eq!(clock.display_moment(early), at(12, 1, 0));
+    }
+
+    #[test]
+    fn render_shows_the_display_moment() {
+        let ctx = RenderContext::new("d").with_region(Some("Europe/Zurich"), None);
+        let html = clock(None).render(&ctx, at(22, 59, 55));
+        assert!(html.contains("00:00"));
+        assert!(html.contains("Tuesday, 31 December 2024"));
+        assert!(html.contains("Week 01"));
+        assert!(html.contains("Tokyo"));
+        assert!(html.contains("08:00"));
+        assert!(html.contains("HQ"));
+        assert!(html.contains("15:00"));
+        assert!(html.contains("−1"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        let zone = |name: &str| vec![ZoneConfig::Name(name.into())];
+        assert!(ClockPlugin::new(zone("Mars/Olympus_Mons"), None, None, false).is_err());
+        assert!(ClockPlugin::new(vec![], Some(7), None, false).is_err());
+        assert!(ClockPlugin::new(zone("Europe/Zurich"), Some(15), None, true).is_ok());
+    }
+}
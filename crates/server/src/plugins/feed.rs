@@ -0,0 +1,342 @@
+use std::{cmp::Reverse, collections::HashSet, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, join_all};
+use log::{debug, error};
+use roxmltree::Node;
+use sailfish::TemplateOnce;
+use url::Url;
+
+use super::ticktick::format_relative;
+use crate::{
+    generator::{self, RenderContext},
+    net, storage,
+};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the feed is not well-formed XML")]
+    Xml(#[from] roxmltree::Error),
+    #[error("the data is neither an RSS nor an Atom feed")]
+    NotAFeed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub title: String,
+    pub link: Option<String>,
+    // The RSS guid or Atom id.
+    pub id: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    // Title of the feed the entry came from.
+    pub source: String,
+}
+
+impl Entry {
+    fn key(&self) -> &str {
+        self.link
+            .as_deref()
+            .or(self.id.as_deref())
+            .unwrap_or(&self.title)
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "feed.stpl")]
+struct FeedTemplate<'a> {
+    entries: &'a [Entry],
+    now: DateTime<Utc>,
+}
+
+// All text below `node`, with whitespace collapsed.
+fn text(node: Node<'_, '_>) -> String {
+    node.descendants()
+        .filter(Node::is_text)
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn child_text(node: Node<'_, '_>, name: &str) -> Option<String> {
+    child(node, name).map(text).filter(|t| !t.is_empty())
+}
+
+// RSS uses RFC 2822 dates, Atom and Dublin Core RFC 3339; feeds mix them up.
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn atom_link(entry: Node<'_, '_>) -> Option<String> {
+    entry
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "link")
+        .find(|n| n.attribute("rel").is_none_or(|rel| rel == "alternate"))
+        .and_then(|n| n.attribute("href"))
+        .map(str::to_owned)
+}
+
+// Entries of an RSS 0.9x/1.0/2.0 or Atom 1.0 feed.
+pub fn parse(data: &str) -> Result<Vec<Entry>, Error> {
+    let doc = roxmltree::Document::parse(data)?;
+    let root = doc.root_element();
+    let entries = match root.tag_name().name() {
+        "feed" => {
+            let source = child_text(root, "title").unwrap_or_default();
+            root.children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "entry")
+                .map(|entry| Entry {
+                    title: child_text(entry, "title").unwrap_or_default(),
+                    link: atom_link(entry),
+                    id: child_text(entry, "id"),
+                    published: child_text(entry, "published")
+                        .or_else(|| child_text(entry, "updated"))
+                        .as_deref()
+                        .and_then(parse_date),
+                    source: source.clone(),
+                })
+                .collect()
+        }
+        // RSS 1.0 keeps its items next to the channel instead of inside it.
+        "rss" | "RDF" => {
+            let source = child(root, "channel")
+                .and_then(|channel| child_text(channel, "title"))
+                .unwrap_or_default();
+            root.descendants()
+                .filter(|n| n.is_element() && n.tag_name().name() == "item")
+                .map(|item| Entry {
+                    title: child_text(item, "title").unwrap_or_default(),
+                    link: child_text(item, "link"),
+                    id: child_text(item, "guid"),
+                    published: child_text(item, "pubDate")
+                        .or_else(|| child_text(item, "date"))
+                        .as_deref()
+                        .and_then(parse_date),
+                    source: source.clone(),
+                })
+                .collect()
+        }
+        _ => return Err(Error::NotAFeed),
+    };
+    Ok(entries)
+}
+
+// Newest first, keeping only the first of entries sharing a link or id.
+fn merge(feeds: impl IntoIterator<Item = Vec<Entry>>) -> Vec<Entry> {
+    let mut entries: Vec<_> = feeds
+        .into_iter()
+        .flatten()
+        .filter(|entry| !entry.title.is_empty())
+        .collect();
+    entries.sort_by_key(|entry| Reverse(entry.published));
+    let mut seen = HashSet::new();
+    entries.retain(|entry| seen.insert(entry.key().to_owned()));
+    entries
+}
+
+pub struct FeedPlugin {
+    urls: Vec<Url>,
+    limit: Option<usize>,
+}
+
+impl FeedPlugin {
+    pub fn new(urls: Vec<Url>, limit: Option<usize>) -> Result<Self, storage::LoadError> {
+        if urls.is_empty() {
+            error!("A feed plugin needs at least one URL");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self { urls, limit })
+    }
+
+    async fn load(url: &Url) -> Result<Vec<Entry>, generator::Error> {
+        debug!("Loading feed {url}");
+        let data = net::retry(
+            || async {
+                net::client()
+                    .get(url.clone())
+                    .timeout(FETCH_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await
+            },
+            RETRY_BACKOFF,
+            RETRY_TIMEOUT,
+        )
+        .await?;
+        parse(&data)
+            .inspect_err(|e| error!("Parsing feed {url}: {e}"))
+            .map_err(|_| generator::Error::Fetch {
+                kind: generator::FetchErrorKind::InvalidData,
+                target: url.to_string(),
+            })
+    }
+
+    fn render(&self, mut entries: Vec<Entry>, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let rows = ctx.view.rows(60);
+        entries.truncate(self.limit.map_or(rows, |limit| limit.min(rows)));
+        FeedTemplate {
+            entries: &entries,
+            now,
+        }
+        .render_once()
+        .expect("feed template render failed")
+    }
+}
+
+impl generator::Content for FeedPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            // A feed that is down should not hide the headlines of the others.
+            let mut feeds = Vec::new();
+            let mut failure = None;
+            for result in join_all(self.urls.iter().map(Self::load)).await {
+                match result {
+                    Ok(entries) => feeds.push(entries),
+                    Err(e) => failure = Some(e),
+                }
+            }
+            if let (true, Some(e)) = (feeds.is_empty(), failure) {
+                return Err(e);
+            }
+            Ok(self.render(merge(feeds), ctx, Utc::now()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::generator::View;
+
+    fn fixture(name: &str) -> Vec<Entry> {
+        let path: std::path::PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "feed", name]
+            .iter()
+            .collect();
+        parse(&std::fs::read_to_string(path).expect("Fixture is readable")).expect("Feed parses")
+    }
+
+    fn at(d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, d, h, 0, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    #[test]
+    fn parse_rss() {
+        let entries = fixture("rss.xml");
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].title, "Lake levels rise after a week of rain");
+        assert_eq!(entries[0].source, "Local News");
+        assert_eq!(entries[0].published, Some(at(6, 7)));
+        assert_eq!(entries[1].title, "Night trains return to the timetable");
+        assert_eq!(
+            entries[1].link.as_deref(),
+            Some("https://news.example.com/trains")
+        );
+        assert_eq!(entries[3].published, None);
+    }
+
+    #[test]
+    fn parse_atom() {
+        let entries = fixture("atom.xml");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Release 2.0 is out");
+        assert_eq!(entries[0].source, "Project Blog");
+        assert_eq!(
+            entries[0].link.as_deref(),
+            Some("https://blog.example.com/2.0")
+        );
+        // Entries without a published date use their last update.
+        assert_eq!(entries[1].published, Some(at(5, 12)));
+    }
+
+    #[test]
+    fn parse_rdf() {
+        let entries = fixture("rdf.xml");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source, "Science Digest");
+        assert_eq!(entries[0].published, Some(at(4, 9)));
+    }
+
+    #[test]
+    fn parse_rejects_other_documents() {
+        assert!(matches!(parse("<html></html>"), Err(Error::NotAFeed)));
+        assert!(matches!(parse("not xml"), Err(Error::Xml(_))));
+    }
+
+    #[test]
+    fn merge_sorts_and_deduplicates() {
+        let entries = merge([fixture("rss.xml"), fixture("atom.xml")]);
+        let titles: Vec<_> = entries.iter().map(|e| e.title.as_str()).collect();
+        // The cross-posted release keeps the source listed first.
+        assert_eq!(entries[1].source, "Local News");
+        assert_eq!(
+            titles,
+            [
+                "Lake levels rise after a week of rain",
+                "Release 2.0 is out",
+                "Maintenance window on Sunday",
+                "Night trains return to the timetable",
+                "Undated notice",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_fits_the_view() {
+        let plugin = FeedPlugin::new(
+            vec![Url::parse("https://news.example.com/rss").expect("Valid URL")],
+            None,
+        )
+        .expect("Plugin is configured");
+        let entries: Vec<_> = (0..20)
+            .map(|i| Entry {
+                title: format!("Headline {i}"),
+                link: None,
+                id: None,
+                published: Some(at(6, 6)),
+                source: "News".into(),
+            })
+            .collect();
+        let now = at(6, 12);
+        let count = |view| {
+            plugin
+                .render(
+                    entries.clone(),
+                    &RenderContext::new("d").with_view(view),
+                    now,
+                )
+                .matches("class=\"item\"")
+                .count()
+        };
+        assert_eq!(count(View::Full), 8);
+        assert_eq!(count(View::Quadrant), 4);
+        let html = plugin.render(entries, &RenderContext::new("d"), now);
+        assert!(html.contains("News · today"));
+    }
+
+    #[test]
+    fn new_requires_urls() {
+        assert!(FeedPlugin::new(vec![], None).is_err());
+    }
+}
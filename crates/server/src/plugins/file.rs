@@ -0,0 +1,494 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::{debug, error};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{
+    format::age,
+    template::{self, Layouts},
+};
+use crate::{
+    generator::{self, RenderContext},
+    storage,
+};
+
+const MISSING: &str = "—";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the file is not valid JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("the file is not valid CSV")]
+    Csv(#[from] csv::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    Table,
+    List,
+    KeyValue,
+}
+
+impl Layout {
+    // Tables for records, key-value pairs for a single object, else a list.
+    fn of(data: &Value) -> Self {
+        match data {
+            Value::Array(items) if items.iter().all(Value::is_object) => Self::Table,
+            Value::Object(_) => Self::KeyValue,
+            _ => Self::List,
+        }
+    }
+}
+
+struct Loaded {
+    modified: SystemTime,
+    data: Value,
+    // Column order of a CSV file; its rows become JSON objects.
+    headers: Vec<String>,
+}
+
+enum Body {
+    Table {
+        columns: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    List(Vec<String>),
+    KeyValue(Vec<(String, String)>),
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "file.stpl")]
+struct FileTemplate<'a> {
+    title: Option<&'a str>,
+    body: &'a Body,
+    stale: Option<String>,
+}
+
+fn parse_csv(data: &str) -> Result<(Value, Vec<String>), Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_owned).collect();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let row: Map<_, _> = headers
+            .iter()
+            .cloned()
+            .zip(record?.iter().map(|field| Value::String(field.into())))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok((Value::Array(rows), headers))
+}
+
+pub fn parse(format: Format, data: &str) -> Result<(Value, Vec<String>), Error> {
+    match format {
+        Format::Json => Ok((serde_json::from_str(data)?, Vec::new())),
+        Format::Csv => parse_csv(data),
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => MISSING.into(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn read(
+    path: &Path,
+    format: Format,
+    cached: Option<Arc<Loaded>>,
+) -> Result<Arc<Loaded>, generator::Error> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .inspect_err(|e| error!("Reading {}: {e}", path.display()))
+        .map_err(|_| generator::Error::Misconfigured)?;
+    if let Some(cached) = cached.filter(|cached| cached.modified == modified) {
+        return Ok(cached);
+    }
+    debug!("Loading {}", path.display());
+    let invalid = || generator::Error::Fetch {
+        kind: generator::FetchErrorKind::InvalidData,
+        target: path.display().to_string(),
+    };
+    let data = fs::read_to_string(path)
+        .inspect_err(|e| error!("Reading {}: {e}", path.display()))
+        .map_err(|_| invalid())?;
+    let (data, headers) = parse(format, &data)
+        .inspect_err(|e| error!("Parsing {}: {e}", path.display()))
+        .map_err(|_| invalid())?;
+    Ok(Arc::new(Loaded {
+        modified,
+        data,
+        headers,
+    }))
+}
+
+pub struct FilePlugin {
+    path: PathBuf,
+    format: Format,
+    layout: Option<Layout>,
+    columns: Vec<String>,
+    title: Option<String>,
+    layouts: Option<Layouts>,
+    stale_after: Option<Duration>,
+    limit: Option<usize>,
+    // The last read of the file, reused until its modification time changes.
+    cache: Mutex<Option<Arc<Loaded>>>,
+}
+
+impl FilePlugin {
+    #[allow(clippy::too_many_arguments, reason = "mirrors the configuration table")]
+    pub fn new(
+        path: PathBuf,
+        format: Option<Format>,
+        layout: Option<Layout>,
+        columns: Vec<String>,
+        title: Option<String>,
+        markup: Option<&template::Markup>,
+        stale_after: Option<Duration>,
+        limit: Option<usize>,
+    ) -> Result<Self, storage::LoadError> {
+        let Some(format) = format.or_else(|| Format::of(&path)) else {
+            error!(
+                "Set the format of {}, it is neither .json nor .csv",
+                path.display()
+            );
+            return Err(storage::LoadError::InvalidConfig);
+        };
+        let layouts = markup
+            .map(|markup| {
+                Layouts::parse(&markup.read()?)
+                    .inspect_err(|e| error!("Failed to parse liquid template: {e}"))
+                    .map_err(|_| storage::LoadError::InvalidConfig)
+            })
+            .transpose()?;
+        Ok(Self {
+            path,
+            format,
+            layout,
+            columns,
+            title,
+            layouts,
+            stale_after,
+            limit,
+            cache: Mutex::new(None),
+        })
+    }
+
+    async fn load(&self) -> Result<Arc<Loaded>, generator::Error> {
+        let (path, format) = (self.path.clone(), self.format);
+        let cached = self.cache.lock().expect("file cache lock poisoned").clone();
+        let loaded = tokio::task::spawn_blocking(move || read(&path, format, cached))
+            .await
+            .map_err(|_| generator::Error::Unknown)??;
+        *self.cache.lock().expect("file cache lock poisoned") = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+
+    fn columns(&self, loaded: &Loaded, items: &[&Value]) -> Vec<String> {
+        if !self.columns.is_empty() {
+            return self.columns.clone();
+        }
+        if !loaded.headers.is_empty() {
+            return loaded.headers.clone();
+        }
+        let mut columns: Vec<String> = Vec::new();
+        for key in items
+            .iter()
+            .filter_map(|item| item.as_object())
+            .flat_map(Map::keys)
+        {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        columns
+    }
+
+    fn body(&self, loaded: &Loaded, rows: usize) -> Body {
+        let items: Vec<_> = match &loaded.data {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        let columns = self.columns(loaded, &items);
+        let cells = |item: &Value| -> Vec<String> {
+            columns
+                .iter()
+                .map(|column| item.get(column).map_or_else(|| MISSING.into(), display))
+                .collect()
+        };
+        match self.layout.unwrap_or_else(|| Layout::of(&loaded.data)) {
+            Layout::Table => Body::Table {
+                rows: items.iter().take(rows).map(|item| cells(item)).collect(),
+                columns,
+            },
+            Layout::List => Body::List(
+                items
+                    .iter()
+                    .take(rows)
+                    .map(|item| match item {
+                        Value::Object(_) => cells(item).join(" · "),
+                        other => display(other),
+                    })
+                    .collect(),
+            ),
+            Layout::KeyValue => Body::KeyValue(
+                items
+                    .first()
+                    .map(|item| {
+                        columns
+                            .iter()
+                            .filter_map(|key| Some((key.clone(), display(item.get(key)?))))
+                            .take(rows)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn render(
+        &self,
+        loaded: &Loaded,
+        ctx: &RenderContext,
+        now: DateTime<Utc>,
+    ) -> Result<String, generator::Error> {
+        let modified = DateTime::<Utc>::from(loaded.modified);
+        let stale = self
+            .stale_after
+            .and_then(|limit| chrono::TimeDelta::from_std(limit).ok())
+            .is_some_and(|limit| now - modified > limit);
+        if let Some(layouts) = &self.layouts {
+            let mut vars = template::variables(vec![loaded.data.clone()], &Map::new(), ctx, now);
+            vars.insert("modified".into(), modified.to_rfc3339().into());
+            vars.insert("stale".into(), stale.into());
+            let globals = liquid::to_object(&Value::Object(vars))
+                .map_err(|e| generator::Error::Template(e.to_string()))?;
+            return layouts
+                .for_view(ctx.view)
+                .render(&globals)
+                .map_err(|e| generator::Error::Template(e.to_string()));
+        }
+        let rows = ctx.view.rows(40).saturating_sub(2);
+        Ok(FileTemplate {
+            title: self.title.as_deref(),
+            body: &self.body(loaded, self.limit.map_or(rows, |limit| limit.min(rows))),
+            stale: stale.then(|| format!("Stale · updated {}", age(modified, now))),
+        }
+        .render_once()
+        .expect("file template render failed"))
+    }
+}
+
+impl generator::Content for FilePlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let loaded = self.load().await?;
+            self.render(&loaded, ctx, Utc::now())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Content;
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "file", name]
+            .iter()
+            .collect()
+    }
+
+    fn plugin(path: PathBuf) -> FilePlugin {
+        FilePlugin::new(path, None, None, Vec::new(), None, None, None, None)
+            .expect("Plugin is configured")
+    }
+
+    fn loaded(plugin: &FilePlugin) -> Loaded {
+        let data = fs::read_to_string(&plugin.path).expect("Fixture is readable");
+        let (data, headers) = parse(plugin.format, &data).expect("Fixture parses");
+        Loaded {
+            modified: SystemTime::now(),
+            data,
+            headers,
+        }
+    }
+
+    #[test]
+    fn parse_csv_keeps_column_order() {
+        let data = fs::read_to_string(fixture("backups.csv")).expect("Fixture is readable");
+        let (rows, headers) = parse(Format::Csv, &data).expect("CSV parses");
+        assert_eq!(headers, ["host", "job", "last run", "status"]);
+        assert_eq!(rows[2]["status"], "failed");
+        assert!(matches!(
+            parse(Format::Csv, "a,b\n1,2,3\n"),
+            Err(Error::Csv(_))
+        ));
+    }
+
+    #[test]
+    fn layouts_follow_the_data() {
+        let csv = plugin(fixture("backups.csv"));
+        let Body::Table { columns, rows } = csv.body(&loaded(&csv), 2) else {
+            panic!("CSV files are tables");
+        };
+        assert_eq!(columns, ["host", "job", "last run", "status"]);
+        assert_eq!(
+            rows,
+            [
+                ["nas", "photos", "2024-05-06 02:00", "ok"],
+                ["nas", "documents", "2024-05-06 02:30", "ok"]
+            ]
+        );
+
+        let json = plugin(fixture("status.json"));
+        let Body::KeyValue(pairs) = json.body(&loaded(&json), 10) else {
+            panic!("Objects are key-value pairs");
+        };
+        assert_eq!(pairs[0], ("disk_free_gb".into(), "118".into()));
+        assert_eq!(pairs[1], ("last_error".into(), MISSING.into()));
+        assert_eq!(pairs[4], ("uptime".into(), "12 days".into()));
+    }
+
+    #[test]
+    fn list_joins_selected_columns() {
+        let plugin = FilePlugin::new(
+            fixture("chores.json"),
+            None,
+            Some(Layout::List),
+            vec!["chore".into(), "who".into()],
+            None,
+            None,
+            None,
+            None,
+        )
+        .expect("Plugin is configured");
+        let Body::List(items) = plugin.body(&loaded(&plugin), 10) else {
+            panic!("The layout is configured");
+        };
+        assert_eq!(items, ["Bins out · Sam", "Water plants · Alex"]);
+    }
+
+    #[tokio::test]
+    async fn generate_reloads_changed_files_and_flags_stale_data() {
+        let path = std::env::temp_dir().join(format!("atrmnl_file_{}.csv", std::process::id()));
+        fs::write(&path, "job,status\nphotos,ok\n").expect("Failed to write file");
+        let hour_ago = SystemTime::now() - Duration::from_hours(1);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(hour_ago))
+            .expect("Failed to set the modification time");
+        let plugin = FilePlugin::new(
+            path.clone(),
+            None,
+            None,
+            Vec::new(),
+            Some("Backups".into()),
+            None,
+            Some(Duration::from_mins(30)),
+            None,
+        )
+        .expect("Plugin is configured");
+        let ctx = RenderContext::new("d");
+
+        let html = plugin.generate(&ctx).await.expect("File renders");
+        assert!(html.contains("Backups"));
+        assert!(html.contains("photos"));
+        assert!(html.contains("Stale · updated 1 h ago"));
+
+        fs::write(&path, "job,status\nphotos,failed\n").expect("Failed to write file");
+        let html = plugin.generate(&ctx).await.expect("File renders");
+        fs::remove_file(&path).expect("Failed to remove file");
+        assert!(html.contains("failed"));
+        assert!(!html.contains("Stale"));
+    }
+
+    #[tokio::test]
+    async fn generate_renders_templates() {
+        let template =
+            std::env::temp_dir().join(format!("atrmnl_file_{}.liquid", std::process::id()));
+        fs::write(
+            &template,
+            "{{ uptime }} {% if stale %}old{% else %}fresh{% endif %}",
+        )
+        .expect("Failed to write template");
+        let markup = template::Markup {
+            full: template.clone(),
+            half: None,
+            quadrant: None,
+            shared: None,
+        };
+        let plugin = FilePlugin::new(
+            fixture("status.json"),
+            None,
+            None,
+            Vec::new(),
+            None,
+            Some(&markup),
+            None,
+            None,
+        );
+        fs::remove_file(&template).expect("Failed to remove template");
+        let html = plugin
+            .expect("Plugin is configured")
+            .generate(&RenderContext::new("d"))
+            .await
+            .expect("Template renders");
+        assert_eq!(html, "12 days fresh");
+    }
+
+    #[test]
+    fn new_needs_a_known_format() {
+        let new = |path: &str, format| {
+            FilePlugin::new(
+                path.into(),
+                format,
+                None,
+                Vec::new(),
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        assert!(new("/srv/report.txt", None).is_err());
+        assert!(new("/srv/report.txt", Some(Format::Csv)).is_ok());
+        assert!(new("/srv/REPORT.JSON", None).is_ok());
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+
+// "just now", "5 min ago", "3 h ago" or "2 d ago".
+pub fn age(since: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now - since;
+    if age.num_minutes() < 1 {
+        "just now".into()
+    } else if age.num_hours() < 1 {
+        format!("{} min ago", age.num_minutes())
+    } else if age.num_days() < 1 {
+        format!("{} h ago", age.num_hours())
+    } else {
+        format!("{} d ago", age.num_days())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone};
+
+    use super::*;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, h, m, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    #[test]
+    fn age_reads_naturally() {
+        assert_eq!(age(at(8, 0), at(8, 0)), "just now");
+        assert_eq!(age(at(8, 0), at(8, 5)), "5 min ago");
+        assert_eq!(age(at(8, 0), at(11, 30)), "3 h ago");
+        assert_eq!(age(at(8, 0), at(8, 0) + TimeDelta::days(2)), "2 d ago");
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, time::Duration};
+
+use futures::future::BoxFuture;
+use log::{debug, error, warn};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    net, storage,
+};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+const UNAVAILABLE: &str = "—";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum EntityConfig {
+    Id(String),
+    Labelled { entity: String, label: String },
+}
+
+impl EntityConfig {
+    fn id(&self) -> &str {
+        match self {
+            Self::Id(id) | Self::Labelled { entity: id, .. } => id,
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self {
+            Self::Id(_) => None,
+            Self::Labelled { label, .. } => Some(label),
+        }
+    }
+}
+
+// An entry of `/api/states`.
+#[derive(Debug, Deserialize)]
+struct State {
+    entity_id: String,
+    #[serde(rename = "state")]
+    value: String,
+    #[serde(default)]
+    attributes: HashMap<String, Value>,
+}
+
+impl State {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).and_then(Value::as_str)
+    }
+
+    fn number(&self, name: &str) -> Option<f64> {
+        self.attributes.get(name).and_then(Value::as_f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    Climate,
+    Sensors,
+    Energy,
+    BinarySensors,
+}
+
+impl Group {
+    fn of(state: &State) -> Self {
+        let domain = state.entity_id.split('.').next().unwrap_or_default();
+        match (domain, state.attribute("device_class")) {
+            ("climate" | "water_heater", _) => Self::Climate,
+            ("binary_sensor", _) => Self::BinarySensors,
+            (_, Some("energy" | "power" | "gas" | "water" | "battery")) => Self::Energy,
+            _ => Self::Sensors,
+        }
+    }
+
+    const fn title(self) -> &'static str {
+        match self {
+            Self::Climate => "Climate",
+            Self::Sensors => "Sensors",
+            Self::Energy => "Energy",
+            Self::BinarySensors => "Status",
+        }
+    }
+}
+
+struct Tile {
+    label: String,
+    value: String,
+    detail: Option<String>,
+}
+
+struct Section {
+    title: &'static str,
+    tiles: Vec<Tile>,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "home_assistant.stpl")]
+struct DashboardTemplate<'a> {
+    sections: &'a [Section],
+    columns: usize,
+}
+
+// Drops trailing zeros so "21.50" reads "21.5" and "40.0" reads "40".
+fn trim(number: f64, decimals: usize) -> String {
+    let text = format!("{number:.decimals$}");
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_owned()
+    } else {
+        text
+    }
+}
+
+fn quantity(number: f64, unit: Option<&str>) -> String {
+    match unit {
+        Some("W") if number.abs() >= 1000.0 => format!("{} kW", trim(number / 1000.0, 1)),
+        Some("Wh") if number.abs() >= 1000.0 => format!("{} kWh", trim(number / 1000.0, 1)),
+        Some(unit @ ("%" | "°C" | "°F")) => format!("{}{unit}", trim(number, 1)),
+        Some(unit) => format!("{} {unit}", trim(number, 1)),
+        None => trim(number, 2),
+    }
+}
+
+// Binary sensors read "on" and "off"; their device class says what that means.
+fn binary(state: &str, device_class: Option<&str>) -> String {
+    let on = state == "on";
+    let (yes, no) = match device_class {
+        Some("door" | "window" | "garage_door" | "opening") => ("Open", "Closed"),
+        Some("lock") => ("Unlocked", "Locked"),
+        Some("motion" | "occupancy" | "presence") => ("Detected", "Clear"),
+        Some("moisture") => ("Wet", "Dry"),
+        Some("connectivity") => ("Connected", "Disconnected"),
+        Some("battery") => ("Low", "Normal"),
+        Some("problem") => ("Problem", "OK"),
+        _ => ("On", "Off"),
+    };
+    (if on { yes } else { no }).into()
+}
+
+fn capitalize(text: &str) -> String {
+    let text = text.replace('_', " ");
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+fn tile(state: &State, label: Option<&str>) -> Tile {
+    let label = label
+        .or_else(|| state.attribute("friendly_name"))
+        .unwrap_or(&state.entity_id)
+        .to_owned();
+    if matches!(state.value.as_str(), "unavailable" | "unknown") {
+        return Tile {
+            label,
+            value: UNAVAILABLE.into(),
+            detail: None,
+        };
+    }
+    let unit = state.attribute("unit_of_measurement");
+    match Group::of(state) {
+        Group::Climate => {
+            let unit = state.attribute("temperature_unit").or(Some("°C"));
+            let action = state
+                .attribute("hvac_action")
+                .map_or_else(|| capitalize(&state.value), capitalize);
+            Tile {
+                label,
+                value: state
+                    .number("current_temperature")
+                    .map_or_else(|| UNAVAILABLE.into(), |t| quantity(t, unit)),
+                detail: Some(match state.number("temperature") {
+                    Some(target) => format!("{action} · {}", quantity(target, unit)),
+                    None => action,
+                }),
+            }
+        }
+        Group::BinarySensors => Tile {
+            label,
+            value: binary(&state.value, state.attribute("device_class")),
+            detail: None,
+        },
+        Group::Sensors | Group::Energy => Tile {
+            label,
+            value: state.value.parse::<f64>().map_or_else(
+                |_| capitalize(&state.value),
+                |number| quantity(number, unit),
+            ),
+            detail: None,
+        },
+    }
+}
+
+pub struct HomeAssistantPlugin {
+    url: Url,
+    token: String,
+    entities: Vec<EntityConfig>,
+}
+
+impl HomeAssistantPlugin {
+    pub fn new(
+        mut url: Url,
+        token: String,
+        entities: Vec<EntityConfig>,
+    ) -> Result<Self, storage::LoadError> {
+        if entities.is_empty() {
+            error!("The Home Assistant plugin needs at least one entity");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        // Keeps a path prefix when joining the API path.
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Ok(Self {
+            url,
+            token,
+            entities,
+        })
+    }
+
+    async fn fetch(&self) -> Result<Vec<State>, generator::Error> {
+        let url = self
+            .url
+            .join("api/states")
+            .map_err(|_| generator::Error::Misconfigured)?;
+        debug!("Fetching Home Assistant states from {url}");
+        Ok(net::retry(
+            || async {
+                net::client()
+                    .get(url.clone())
+                    .bearer_auth(&self.token)
+                    .timeout(FETCH_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+            },
+            RETRY_BACKOFF,
+            RETRY_TIMEOUT,
+        )
+        .await?)
+    }
+
+    fn render(&self, states: &[State], view: View) -> String {
+        let by_id: HashMap<_, _> = states.iter().map(|s| (s.entity_id.as_str(), s)).collect();
+        let mut sections: Vec<Section> = Vec::new();
+        let mut grouped: Vec<_> = self
+            .entities
+            .iter()
+            .filter_map(|entity| {
+                let Some(state) = by_id.get(entity.id()) else {
+                    warn!("Home Assistant has no entity {}", entity.id());
+                    return None;
+                };
+                Some((Group::of(state), tile(state, entity.label())))
+            })
+            .collect();
+        // Stable, so entities keep their configured order within a group.
+        grouped.sort_by_key(|(group, _)| *group);
+        for (group, tile) in grouped {
+            match sections.last_mut() {
+                Some(section) if section.title == group.title() => section.tiles.push(tile),
+                _ => sections.push(Section {
+                    title: group.title(),
+                    tiles: vec![tile],
+                }),
+            }
+        }
+        DashboardTemplate {
+            sections: &sections,
+            columns: if view == View::Full { 4 } else { 2 },
+        }
+        .render_once()
+        .expect("home assistant template render failed")
+    }
+}
+
+impl generator::Content for HomeAssistantPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let states = self.fetch().await?;
+            Ok(self.render(&states, ctx.view))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::HeaderMap, http::StatusCode, routing::get};
+
+    use super::*;
+    use crate::{generator::Content, testing};
+
+    const TOKEN: &str = "long-lived-token";
+
+    async fn mock_server() -> Url {
+        async fn states(headers: HeaderMap) -> (StatusCode, String) {
+            let authorized = headers.get("authorization").and_then(|v| v.to_str().ok())
+                == Some(&format!("Bearer {TOKEN}"));
+            if !authorized {
+                return (StatusCode::UNAUTHORIZED, "401: Unauthorized".into());
+            }
+            let path: std::path::PathBuf = [
+                env!("CARGO_MANIFEST_DIR"),
+                "tests",
+                "home_assistant",
+                "states.json",
+            ]
+            .iter()
+            .collect();
+            (
+                StatusCode::OK,
+                std::fs::read_to_string(path).expect("Fixture is readable"),
+            )
+        }
+
+        testing::mock_server(Router::new().route("/api/states", get(states))).await
+    }
+
+    fn entities() -> Vec<EntityConfig> {
+        [
+            "binary_sensor.front_door",
+            "sensor.living_room_temperature",
+            "sensor.grid_power",
+            "climate.living_room",
+            "sensor.missing",
+            "sensor.outdoor_humidity",
+            "binary_sensor.hallway_motion",
+            "sensor.solar_energy_today",
+            "sensor.washing_machine",
+        ]
+        .into_iter()
+        .map(|id| EntityConfig::Id(id.into()))
+        .chain([EntityConfig::Labelled {
+            entity: "sensor.cellar_temperature".into(),
+            label: "Cellar".into(),
+        }])
+        .collect()
+    }
+
+    #[test]
+    fn quantities_are_formatted() {
+        assert_eq!(quantity(21.46, Some("°C")), "21.5°C");
+        assert_eq!(quantity(40.0, Some("%")), "40%");
+        assert_eq!(quantity(1520.0, Some("W")), "1.5 kW");
+        assert_eq!(quantity(230.0, Some("W")), "230 W");
+        assert_eq!(quantity(12.345, Some("kWh")), "12.3 kWh");
+        assert_eq!(quantity(1.234_56, None), "1.23");
+    }
+
+    #[test]
+    fn binary_states_follow_device_class() {
+        assert_eq!(binary("on", Some("door")), "Open");
+        assert_eq!(binary("off", Some("motion")), "Clear");
+        assert_eq!(binary("on", None), "On");
+    }
+
+    #[tokio::test]
+    async fn generate_groups_entities() {
+        let plugin = HomeAssistantPlugin::new(mock_server().await, TOKEN.into(), entities())
+            .expect("Plugin is configured");
+        let html = plugin
+            .generate(&RenderContext::new("d"))
+            .await
+            .expect("Dashboard renders");
+        let order: Vec<_> = [
+            "Climate",
+            "Heating · 21.5°C",
+            "Sensors",
+            "Living Room Temperature",
+            "Outdoor Humidity",
+            "Cellar",
+            "Energy",
+            "1.5 kW",
+            "12.3 kWh",
+            "Status",
+            "Closed",
+            "Detected",
+        ]
+        .iter()
+        .map(|text| html.find(text).unwrap_or_else(|| panic!("{text} is shown")))
+        .collect();
+        assert!(order.is_sorted());
+        assert!(html.contains("grid--cols-4"));
+        // Unavailable entities keep their tile.
+        assert!(html.contains("Washing Machine"));
+        assert!(html.contains(UNAVAILABLE));
+    }
+
+    #[tokio::test]
+    async fn generate_reports_bad_tokens() {
+        let plugin = HomeAssistantPlugin::new(mock_server().await, "wrong".into(), entities())
+            .expect("Plugin is configured");
+        let result = plugin.generate(&RenderContext::new("d")).await;
+        assert!(matches!(
+            result,
+            Err(generator::Error::Fetch {
+                kind: generator::FetchErrorKind::Request(StatusCode::UNAUTHORIZED),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn new_requires_entities() {
+        let url = Url::parse("http://homeassistant.local:8123").expect("Valid URL");
+        assert!(HomeAssistantPlugin::new(url, TOKEN.into(), vec![]).is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use sailfish::TemplateOnce;
 
 use super::Plugin;
-use crate::generator;
+use crate::generator::{self, RenderContext, View};
 
 #[derive(TemplateOnce)]
 #[template(path = "mashup/single.stpl")]
@@ -18,12 +18,19 @@ struct LeftRightTemplate {
     right: String,
 }
 
+#[derive(TemplateOnce)]
+#[template(path = "mashup/quadrants.stpl")]
+struct QuadrantsTemplate {
+    quadrants: [String; 4],
+}
+
 pub enum Mashup {
     Single(Arc<Plugin>),
     LeftRight {
         left: Arc<Plugin>,
         right: Arc<Plugin>,
     },
+    Quadrants([Arc<Plugin>; 4]),
 }
 
 impl std::fmt::Debug for Mashup {
@@ -31,25 +38,53 @@ impl std::fmt::Debug for Mashup {
         match self {
             Self::Single(_) => f.debug_tuple("Single").finish(),
             Self::LeftRight { .. } => f.debug_struct("LeftRight").finish(),
+            Self::Quadrants(_) => f.debug_tuple("Quadrants").finish(),
         }
     }
 }
 
 impl generator::Content for Mashup {
-    fn generate(&self) -> futures::future::BoxFuture<'_, Result<String, generator::Error>> {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> futures::future::BoxFuture<'a, Result<String, generator::Error>> {
         match self {
             Self::Single(p) => Box::pin(async {
-                let inner = p.generate().await?;
+                let inner = p.generate(&ctx.with_view(View::Full)).await?;
                 Ok(SingleTemplate { inner }
                     .render_once()
                     .expect("mashup single template render failed"))
             }),
             Self::LeftRight { left, right } => Box::pin(async {
-                let (left, right) = tokio::try_join!(left.generate(), right.generate())?;
+                let ctx = ctx.with_view(View::HalfVertical);
+                let (left, right) = tokio::try_join!(left.generate(&ctx), right.generate(&ctx))?;
                 Ok(LeftRightTemplate { left, right }
                     .render_once()
                     .expect("mashup left_right template render failed"))
             }),
+            Self::Quadrants([a, b, c, d]) => Box::pin(async {
+                let ctx = ctx.with_view(View::Quadrant);
+                let quadrants = tokio::try_join!(
+                    a.generate(&ctx),
+                    b.generate(&ctx),
+                    c.generate(&ctx),
+                    d.generate(&ctx)
+                )?;
+                Ok(QuadrantsTemplate {
+                    quadrants: quadrants.into(),
+                }
+                .render_once()
+                .expect("mashup quadrants template render failed"))
+            }),
         }
     }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        let plugins = match self {
+            Self::Single(p) => vec![p],
+            Self::LeftRight { left, right } => vec![left, right],
+            Self::Quadrants(quadrants) => quadrants.iter().collect(),
+        };
+        plugins.iter().filter_map(|p| p.refresh_interval()).min()
+    }
 }
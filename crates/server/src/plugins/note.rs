@@ -0,0 +1,617 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use futures::future::BoxFuture;
+use log::{debug, error};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use sailfish::TemplateOnce;
+
+use crate::{
+    generator::{self, RenderContext},
+    storage,
+};
+
+const EXTENSIONS: [&str; 2] = ["md", "markdown"];
+// Rough metrics of TRMNL's typography, used to decide how much of a note fits.
+const CHAR_WIDTH: u32 = 9;
+const TITLE_CHAR_WIDTH: u32 = 13;
+const LINE_HEIGHT: u32 = 24;
+const TITLE_HEIGHT: u32 = 36;
+const CODE_LINE_HEIGHT: u32 = 20;
+const TABLE_ROW_HEIGHT: u32 = 32;
+const RULE_HEIGHT: u32 = 8;
+const GAP: u32 = 8;
+// Space kept free for the padding around the view, the header and "…more".
+const MARGIN: u32 = 40;
+const HEADER_HEIGHT: u32 = 32;
+const MORE_HEIGHT: u32 = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("no notes found in {0}")]
+    Empty(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Heading,
+    Text,
+    Code,
+    Table,
+    Rule,
+}
+
+impl Kind {
+    const fn line_height(self) -> u32 {
+        match self {
+            Self::Heading => TITLE_HEIGHT,
+            Self::Text => LINE_HEIGHT,
+            Self::Code => CODE_LINE_HEIGHT,
+            Self::Table => TABLE_ROW_HEIGHT,
+            Self::Rule => RULE_HEIGHT,
+        }
+    }
+
+    // Tables, code and rules never wrap, every line counts once.
+    const fn char_width(self) -> Option<u32> {
+        match self {
+            Self::Heading => Some(TITLE_CHAR_WIDTH),
+            Self::Text => Some(CHAR_WIDTH),
+            Self::Code | Self::Table | Self::Rule => None,
+        }
+    }
+}
+
+// A top-level element of the note, or one item of a top-level list, with an
+// estimate of its rendered height.
+#[derive(Debug)]
+struct Block {
+    html: String,
+    height: u32,
+}
+
+fn escape(text: &str, into: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => into.push_str("&amp;"),
+            '<' => into.push_str("&lt;"),
+            '>' => into.push_str("&gt;"),
+            '"' => into.push_str("&quot;"),
+            '\'' => into.push_str("&#39;"),
+            c => into.push(c),
+        }
+    }
+}
+
+// Turns Markdown events into blocks styled with TRMNL's classes.
+struct Builder {
+    width: u32,
+    blocks: Vec<Block>,
+    kind: Kind,
+    html: String,
+    // Closing markup of the open elements in the current block.
+    open: Vec<&'static str>,
+    // Next number of each open list, `None` for bullets.
+    lists: Vec<Option<u64>>,
+    // The marker of a top-level item, shown in its own column.
+    marker: Option<String>,
+    // Where the marker of a nested item starts, so a checkbox can replace it.
+    marker_at: Option<usize>,
+    in_head: bool,
+    line_start: bool,
+    lines: u32,
+    chars: u32,
+}
+
+impl Builder {
+    const fn new(width: u32) -> Self {
+        Self {
+            width,
+            blocks: Vec::new(),
+            kind: Kind::Text,
+            html: String::new(),
+            open: Vec::new(),
+            lists: Vec::new(),
+            marker: None,
+            marker_at: None,
+            in_head: false,
+            line_start: true,
+            lines: 0,
+            chars: 0,
+        }
+    }
+
+    const fn begin(&mut self, kind: Kind) {
+        self.kind = kind;
+        self.line_start = true;
+        self.lines = 0;
+        self.chars = 0;
+    }
+
+    // Ends the current line of the estimate.
+    fn flush(&mut self) {
+        self.lines += match self.kind.char_width() {
+            Some(width) => (self.chars * width).div_ceil(self.width).max(1),
+            None => 1,
+        };
+        self.chars = 0;
+    }
+
+    fn line_break(&mut self) {
+        self.html.push_str("<br>");
+        self.flush();
+        self.line_start = true;
+    }
+
+    fn finish(&mut self) {
+        if self.chars > 0 || self.lines == 0 {
+            self.flush();
+        }
+        let mut html = std::mem::take(&mut self.html);
+        if let Some(marker) = self.marker.take() {
+            html = format!(
+                r#"<div class="flex flex--row flex--top gap--small"><span class="description">{marker}</span><span class="description">{html}</span></div>"#
+            );
+        }
+        self.blocks.push(Block {
+            html,
+            height: self.lines * self.kind.line_height(),
+        });
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.kind == Kind::Code {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.html.push('\n');
+                    self.flush();
+                }
+                escape(line, &mut self.html);
+                self.chars += u32::try_from(line.chars().count()).unwrap_or(u32::MAX);
+            }
+        } else {
+            escape(text, &mut self.html);
+            self.chars = self
+                .chars
+                .saturating_add(u32::try_from(text.chars().count()).unwrap_or(u32::MAX));
+        }
+        self.line_start = false;
+    }
+
+    fn item_marker(&mut self) -> String {
+        match self.lists.last_mut() {
+            Some(Some(number)) => {
+                *number += 1;
+                format!("{}.", *number - 1)
+            }
+            _ => "•".into(),
+        }
+    }
+
+    // Items of nested lists go on their own indented line.
+    fn nested_item(&mut self) {
+        if !self.line_start {
+            self.line_break();
+        }
+        let marker = self.item_marker();
+        for _ in 1..self.lists.len() {
+            self.html.push_str("&emsp;");
+        }
+        self.marker_at = Some(self.html.len());
+        self.html.push_str(&marker);
+        self.html.push(' ');
+    }
+
+    fn start(&mut self, tag: &Tag) {
+        if self.open.is_empty() {
+            match tag {
+                Tag::List(first) => {
+                    self.lists.push(*first);
+                    return;
+                }
+                Tag::Heading { .. } => self.begin(Kind::Heading),
+                Tag::CodeBlock(_) => self.begin(Kind::Code),
+                Tag::Table(_) => self.begin(Kind::Table),
+                _ => self.begin(Kind::Text),
+            }
+        }
+        let top = self.open.is_empty();
+        let close = match tag {
+            Tag::Heading { level, .. } => {
+                self.html.push_str(if *level == HeadingLevel::H1 {
+                    r#"<span class="title">"#
+                } else {
+                    r#"<span class="title title--small">"#
+                });
+                "</span>"
+            }
+            Tag::Paragraph | Tag::HtmlBlock | Tag::BlockQuote(_) if top => {
+                self.html.push_str(r#"<p class="description">"#);
+                "</p>"
+            }
+            Tag::Paragraph | Tag::HtmlBlock | Tag::BlockQuote(_) => {
+                if !self.line_start {
+                    self.line_break();
+                }
+                ""
+            }
+            Tag::CodeBlock(_) => {
+                self.html
+                    .push_str(r#"<pre class="description" style="font-family: monospace">"#);
+                "</pre>"
+            }
+            Tag::List(first) => {
+                self.lists.push(*first);
+                ""
+            }
+            Tag::Item if top => {
+                self.marker = Some(self.item_marker());
+                self.marker_at = None;
+                ""
+            }
+            Tag::Item => {
+                self.nested_item();
+                ""
+            }
+            Tag::Table(_) => {
+                self.html
+                    .push_str(r#"<table class="table table--condensed">"#);
+                "</tbody></table>"
+            }
+            Tag::TableHead => {
+                self.in_head = true;
+                self.html.push_str("<thead><tr>");
+                "</tr></thead><tbody>"
+            }
+            Tag::TableRow => {
+                self.html.push_str("<tr>");
+                "</tr>"
+            }
+            Tag::TableCell if self.in_head => {
+                self.html
+                    .push_str(r#"<th><span class="title title--small">"#);
+                "</span></th>"
+            }
+            Tag::TableCell => {
+                self.html.push_str(r#"<td><span class="label">"#);
+                "</span></td>"
+            }
+            Tag::Emphasis => {
+                self.html.push_str("<em>");
+                "</em>"
+            }
+            Tag::Strong => {
+                self.html.push_str("<strong>");
+                "</strong>"
+            }
+            Tag::Strikethrough => {
+                self.html.push_str("<s>");
+                "</s>"
+            }
+            Tag::Link { .. } => {
+                self.html.push_str("<u>");
+                "</u>"
+            }
+            // Images show their alt text.
+            _ => "",
+        };
+        self.open.push(close);
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        let Some(close) = self.open.pop() else {
+            // The end of a top-level list, whose items were blocks of their own.
+            self.lists.pop();
+            return;
+        };
+        self.html.push_str(close);
+        match tag {
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::TableHead => {
+                self.in_head = false;
+                self.flush();
+            }
+            TagEnd::TableRow => self.flush(),
+            _ => {}
+        }
+        if self.open.is_empty() {
+            self.finish();
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(&tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text)
+            | Event::Html(text)
+            | Event::InlineHtml(text)
+            | Event::InlineMath(text)
+            | Event::DisplayMath(text)
+            | Event::FootnoteReference(text) => self.text(&text),
+            Event::Code(code) => {
+                self.html.push_str("<code>");
+                self.text(&code);
+                self.html.push_str("</code>");
+            }
+            Event::SoftBreak => self.text(" "),
+            Event::HardBreak => self.line_break(),
+            Event::Rule if self.open.is_empty() => {
+                self.begin(Kind::Rule);
+                self.html.push_str("<hr>");
+                self.finish();
+            }
+            Event::Rule => {
+                self.html.push_str("<hr>");
+                self.flush();
+                self.line_start = true;
+            }
+            Event::TaskListMarker(done) => {
+                let checkbox = if done { "☑" } else { "☐" };
+                match self.marker_at {
+                    None => self.marker = Some(checkbox.into()),
+                    Some(at) => {
+                        self.html.truncate(at);
+                        self.html.push_str(checkbox);
+                        self.html.push(' ');
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The blocks of `markdown` when laid out `width` pixels wide.
+fn blocks(markdown: &str, width: u32) -> Vec<Block> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    let mut builder = Builder::new(width.max(1));
+    for event in Parser::new_ext(markdown, options) {
+        builder.event(event);
+    }
+    builder.blocks
+}
+
+// How many blocks fit in `height` pixels, leaving room for "…more" when not
+// all of them do. The first block is always shown.
+fn fit(blocks: &[Block], height: u32) -> usize {
+    let mut used = 0;
+    let mut shown = 0;
+    for block in blocks {
+        let gap = if shown == 0 { 0 } else { GAP };
+        if used + gap + block.height > height {
+            break;
+        }
+        used += gap + block.height;
+        shown += 1;
+    }
+    if shown < blocks.len() {
+        while shown > 1 && used + GAP + MORE_HEIGHT > height {
+            shown -= 1;
+            used -= blocks[shown].height + GAP;
+        }
+    }
+    shown.max(1).min(blocks.len())
+}
+
+fn is_note(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+// Every note directly in `dir`, sorted by path. Hidden files are skipped.
+fn list(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(dir).map_err(|source| Error::Io {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let mut notes: Vec<_> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_note(path))
+        .collect();
+    notes.sort();
+    Ok(notes)
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "note.stpl")]
+struct NoteTemplate<'a> {
+    title: &'a str,
+    position: Option<String>,
+    blocks: &'a [Block],
+    more: bool,
+}
+
+pub struct NotePlugin {
+    path: PathBuf,
+    title: Option<String>,
+    // The note shown last when rotating through a folder.
+    last: Mutex<Option<PathBuf>>,
+}
+
+impl NotePlugin {
+    pub fn new(path: PathBuf, title: Option<String>) -> Result<Self, storage::LoadError> {
+        if !path.exists() {
+            error!("The note {} does not exist", path.display());
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self {
+            path,
+            title,
+            last: Mutex::new(None),
+        })
+    }
+
+    // The note to show and, in a folder, its position among the others.
+    fn next(&self) -> Result<(PathBuf, Option<String>), Error> {
+        if !self.path.is_dir() {
+            return Ok((self.path.clone(), None));
+        }
+        let notes = list(&self.path)?;
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let next = last
+            .as_ref()
+            .and_then(|last| notes.iter().position(|n| n == last))
+            .map_or(0, |i| (i + 1) % notes.len().max(1));
+        let note = notes
+            .get(next)
+            .ok_or_else(|| Error::Empty(self.path.clone()))?
+            .clone();
+        *last = Some(note.clone());
+        drop(last);
+        let position = (notes.len() > 1).then(|| format!("{} of {}", next + 1, notes.len()));
+        Ok((note, position))
+    }
+
+    fn render(
+        &self,
+        note: &Path,
+        markdown: &str,
+        position: Option<String>,
+        ctx: &RenderContext,
+    ) -> String {
+        let (width, height) = ctx.view.size();
+        let blocks = blocks(markdown, width.saturating_sub(MARGIN));
+        let shown = fit(&blocks, height.saturating_sub(MARGIN + HEADER_HEIGHT));
+        let stem = note.file_stem().map(|s| s.to_string_lossy());
+        NoteTemplate {
+            title: self
+                .title
+                .as_deref()
+                .or(stem.as_deref())
+                .unwrap_or_default(),
+            position,
+            blocks: &blocks[..shown],
+            more: shown < blocks.len(),
+        }
+        .render_once()
+        .expect("note template render failed")
+    }
+
+    fn load(&self, ctx: &RenderContext) -> Result<String, Error> {
+        let (note, position) = self.next()?;
+        debug!("Showing note {}", note.display());
+        let markdown = fs::read_to_string(&note).map_err(|source| Error::Io {
+            path: note.clone(),
+            source,
+        })?;
+        Ok(self.render(&note, &markdown, position, ctx))
+    }
+}
+
+impl generator::Content for NotePlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            self.load(ctx)
+                .inspect_err(|e| error!("Loading a note: {e}"))
+                .map_err(|_| generator::Error::Misconfigured)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::View;
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "notes", name]
+            .iter()
+            .collect()
+    }
+
+    fn html(markdown: &str) -> String {
+        blocks(markdown, 760).into_iter().map(|b| b.html).collect()
+    }
+
+    #[test]
+    fn checklists_become_checkboxes() {
+        let html = html("- [ ] Milk\n- [x] Bread\n  - [ ] Rye\n- Eggs & ham\n");
+        assert!(html.contains(
+            r#"<span class="description">☐</span><span class="description">Milk</span>"#
+        ));
+        assert!(html.contains("☑"));
+        assert!(html.contains("<br>&emsp;☐ Rye"));
+        assert!(html.contains(
+            r#"<span class="description">•</span><span class="description">Eggs &amp; ham</span>"#
+        ));
+    }
+
+    #[test]
+    fn tables_and_code_use_trmnl_classes() {
+        let html = html(
+            "# Pantry\n\n| Item | Qty |\n| --- | --- |\n| Rice | 2 |\n\n```\nlet x = 1 < 2;\n```\n\n1. one\n2. **two**\n",
+        );
+        assert!(html.contains(r#"<span class="title">Pantry</span>"#));
+        assert!(html.contains(r#"<table class="table table--condensed"><thead><tr><th><span class="title title--small">Item</span></th>"#));
+        assert!(html.contains(r#"<td><span class="label">Rice</span></td>"#));
+        assert!(html.contains("let x = 1 &lt; 2;\n</pre>"));
+        assert!(html.contains(r#"<span class="description">2.</span><span class="description"><strong>two</strong></span>"#));
+    }
+
+    #[test]
+    fn estimates_grow_with_content() {
+        let blocks = blocks(
+            &format!("short\n\n{}\n\n```\na\nb\nc\n```\n", "word ".repeat(80)),
+            360,
+        );
+        let heights: Vec<_> = blocks.iter().map(|b| b.height).collect();
+        assert_eq!(heights[0], LINE_HEIGHT);
+        // 400 characters at 9px wrap onto 10 lines of 360px.
+        assert_eq!(heights[1], 10 * LINE_HEIGHT);
+        assert_eq!(heights[2], 3 * CODE_LINE_HEIGHT);
+    }
+
+    #[test]
+    fn long_notes_are_truncated() {
+        let plugin = NotePlugin::new(fixture("groceries.md"), None).expect("Valid config");
+        let markdown = fs::read_to_string(fixture("groceries.md")).expect("Fixture is readable");
+        let full = plugin.render(
+            &fixture("groceries.md"),
+            &markdown,
+            None,
+            &RenderContext::new("d"),
+        );
+        assert!(full.contains("groceries"));
+        assert!(full.contains("Coffee beans"));
+        assert!(!full.contains("…more"));
+        let quadrant = plugin.render(
+            &fixture("groceries.md"),
+            &markdown,
+            None,
+            &RenderContext::new("d").with_view(View::Quadrant),
+        );
+        assert!(quadrant.contains("…more"));
+        assert!(!quadrant.contains("Coffee beans"));
+    }
+
+    #[test]
+    fn folders_rotate_through_notes() {
+        let plugin = NotePlugin::new(fixture(""), Some("Notes".into())).expect("Valid config");
+        let shown: Vec<_> = (0..3)
+            .map(|_| plugin.next().expect("Notes exist"))
+            .map(|(path, position)| (path.file_name().expect("File").to_owned(), position))
+            .collect();
+        assert_eq!(shown[0], ("groceries.md".into(), Some("1 of 2".into())));
+        assert_eq!(shown[1], ("todo.md".into(), Some("2 of 2".into())));
+        assert_eq!(shown[2], shown[0]);
+        assert!(NotePlugin::new(fixture("missing.md"), None).is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufReader, Cursor},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use base64::Engine;
+use chrono::{Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use image::{
+    DynamicImage, GrayImage, ImageDecoder, ImageFormat, ImageReader, imageops::FilterType,
+};
+use log::{debug, error, warn};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+
+use super::state;
+use crate::{
+    generator::{self, RenderContext},
+    storage,
+};
+
+const EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+const DEFAULT_RECENT: usize = 50;
+// Share of the darkest and brightest pixels clipped by the contrast stretch.
+const CLIP: f32 = 0.01;
+// Lifts the midtones, which e-ink panels render too dark.
+const GAMMA: f32 = 0.8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("no photos found in {0}")]
+    Empty(PathBuf),
+    #[error("failed to decode {path}")]
+    Image {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error(transparent)]
+    State(#[from] state::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    #[default]
+    Sequential,
+    Shuffle,
+    // Photos taken on today's date in earlier years, shuffled.
+    OnThisDay,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    // Fills the view, cropping what does not fit.
+    #[default]
+    Cover,
+    // Shows the whole photo, leaving white bars.
+    Contain,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "photos.stpl")]
+struct PhotoTemplate<'a> {
+    data: &'a str,
+}
+
+fn is_photo(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+// Every photo below `dir`, sorted by path. Hidden files and folders are skipped.
+fn list(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut photos = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir).map_err(|source| Error::Io {
+            path: dir.clone(),
+            source,
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_photo(&path) {
+                photos.push(path);
+            }
+        }
+    }
+    photos.sort();
+    Ok(photos)
+}
+
+// The date the photo was taken according to its EXIF data.
+fn taken(path: &Path) -> Option<NaiveDate> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+    let exif::Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let taken = exif::DateTime::from_ascii(values.first()?).ok()?;
+    NaiveDate::from_ymd_opt(
+        i32::from(taken.year),
+        u32::from(taken.month),
+        u32::from(taken.day),
+    )
+}
+
+// Recently shown photos, oldest first, optionally persisted across restarts.
+#[derive(Debug, Default)]
+struct History {
+    path: Option<PathBuf>,
+    recent: VecDeque<PathBuf>,
+    capacity: usize,
+    taken: HashMap<PathBuf, Option<NaiveDate>>,
+}
+
+impl History {
+    fn open(path: Option<PathBuf>, capacity: usize) -> Result<Self, Error> {
+        let recent = state::load(path.as_deref())?;
+        Ok(Self {
+            path,
+            recent,
+            capacity,
+            taken: HashMap::new(),
+        })
+    }
+
+    fn record(&mut self, photo: &Path) {
+        self.recent.retain(|p| p != photo);
+        self.recent.push_back(photo.to_path_buf());
+        while self.recent.len() > self.capacity {
+            self.recent.pop_front();
+        }
+        if let Err(e) = state::save(self.path.as_deref(), &self.recent) {
+            warn!("Failed to save the photo history: {e}");
+        }
+    }
+
+    // Picks a photo that was not shown recently, or else the one shown longest ago.
+    fn shuffle<'a>(&self, candidates: &[&'a PathBuf]) -> Option<&'a PathBuf> {
+        let fresh: Vec<_> = candidates
+            .iter()
+            .filter(|p| !self.recent.contains(p))
+            .collect();
+        if fresh.is_empty() {
+            return candidates
+                .iter()
+                .min_by_key(|p| self.recent.iter().position(|r| r == **p))
+                .copied();
+        }
+        Some(fresh[fastrand::usize(..fresh.len())])
+    }
+
+    fn pick<'a>(
+        &mut self,
+        order: Order,
+        photos: &'a [PathBuf],
+        today: NaiveDate,
+    ) -> Option<&'a PathBuf> {
+        let photo = match order {
+            Order::Sequential => {
+                let next = self
+                    .recent
+                    .back()
+                    .and_then(|last| photos.iter().position(|p| p == last))
+                    .map_or(0, |i| (i + 1) % photos.len());
+                photos.get(next)
+            }
+            Order::Shuffle => self.shuffle(&photos.iter().collect::<Vec<_>>()),
+            Order::OnThisDay => {
+                let on_this_day: Vec<_> = photos
+                    .iter()
+                    .filter(|p| {
+                        self.taken
+                            .entry((*p).clone())
+                            .or_insert_with(|| taken(p))
+                            .is_some_and(|d| (d.month(), d.day()) == (today.month(), today.day()))
+                    })
+                    .collect();
+                if on_this_day.is_empty() {
+                    debug!("No photos taken on {}, picking any", today.format("%-d %b"));
+                    self.shuffle(&photos.iter().collect::<Vec<_>>())
+                } else {
+                    self.shuffle(&on_this_day)
+                }
+            }
+        }?;
+        self.record(photo);
+        Some(photo)
+    }
+}
+
+fn load(path: &Path) -> Result<DynamicImage, image::ImageError> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+// Stretches the contrast, lifts the midtones and dithers to `levels` shades of
+// grey, which reads far better on e-ink than smooth gradients.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss,
+    reason = "pixel values stay within 0..=255"
+)]
+fn tone_map(image: &mut GrayImage, levels: u8) {
+    let mut histogram = [0usize; 256];
+    for pixel in image.pixels() {
+        histogram[usize::from(pixel.0[0])] += 1;
+    }
+    let clipped = (image.len() as f32 * CLIP) as usize;
+    // The first value reached after skipping `clipped` pixels.
+    let bound = |bins: &mut dyn Iterator<Item = (usize, &usize)>| {
+        let mut seen = 0;
+        for (value, count) in bins {
+            seen += count;
+            if seen > clipped {
+                return value as f32;
+            }
+        }
+        0.0
+    };
+    let low = bound(&mut histogram.iter().enumerate());
+    let high = bound(&mut histogram.iter().enumerate().rev()).max(low + 1.0);
+
+    let (width, height) = image.dimensions();
+    let mut values: Vec<f32> = image
+        .pixels()
+        .map(|p| {
+            ((f32::from(p.0[0]) - low) / (high - low))
+                .clamp(0.0, 1.0)
+                .powf(GAMMA)
+                * 255.0
+        })
+        .collect();
+    let step = 255.0 / f32::from(levels.max(2) - 1);
+    let (width, height) = (width as usize, height as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let old = values[i];
+            let new = ((old / step).round() * step).clamp(0.0, 255.0);
+            values[i] = new;
+            let error = old - new;
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x.wrapping_add_signed(dx);
+                if nx < width && y + dy < height {
+                    values[(y + dy) * width + nx] += error * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    for (pixel, value) in image.pixels_mut().zip(values) {
+        pixel.0[0] = value as u8;
+    }
+}
+
+// The photo scaled to `size`, tone mapped and encoded as a PNG data URI.
+fn prepare(path: &Path, size: (u32, u32), fit: Fit, levels: u8) -> Result<String, Error> {
+    let image = load(path).map_err(|source| Error::Image {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let (width, height) = size;
+    let image = match fit {
+        Fit::Cover => image.resize_to_fill(width, height, FilterType::Lanczos3),
+        Fit::Contain => image.resize(width, height, FilterType::Lanczos3),
+    };
+    let mut gray = image.into_luma8();
+    tone_map(&mut gray, levels);
+    let mut png = Cursor::new(Vec::new());
+    gray.write_to(&mut png, ImageFormat::Png)
+        .map_err(|source| Error::Image {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png.into_inner())
+    ))
+}
+
+pub struct PhotosPlugin {
+    dir: PathBuf,
+    order: Order,
+    fit: Fit,
+    levels: u8,
+    history: Arc<Mutex<History>>,
+}
+
+impl PhotosPlugin {
+    pub fn new(
+        dir: PathBuf,
+        order: Order,
+        fit: Fit,
+        levels: Option<u8>,
+        recent: Option<usize>,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        let levels = levels.unwrap_or(2);
+        if !(2..=16).contains(&levels) {
+            error!("Photo frames support 2 to 16 grey levels, not {levels}");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        if !dir.is_dir() {
+            error!("The photo folder {} does not exist", dir.display());
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let history = History::open(state, recent.unwrap_or(DEFAULT_RECENT))
+            .inspect_err(|e| error!("Loading the photo history: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            dir,
+            order,
+            fit,
+            levels,
+            history: Arc::new(Mutex::new(history)),
+        })
+    }
+
+    fn next(&self, ctx: &RenderContext) -> Result<String, Error> {
+        let photos = list(&self.dir)?;
+        let tz = ctx.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let photo = self
+            .history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pick(self.order, &photos, today)
+            .ok_or_else(|| Error::Empty(self.dir.clone()))?
+            .clone();
+        debug!("Showing photo {}", photo.display());
+        let data = prepare(&photo, ctx.view.size(), self.fit, self.levels)?;
+        Ok(PhotoTemplate { data: &data }
+            .render_once()
+            .expect("photo template render failed"))
+    }
+}
+
+impl generator::Content for PhotosPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let frame = Self {
+                dir: self.dir.clone(),
+                history: Arc::clone(&self.history),
+                ..*self
+            };
+            let ctx = ctx.clone();
+            // Decoding and dithering are too slow for the async runtime.
+            tokio::task::spawn_blocking(move || frame.next(&ctx))
+                .await
+                .map_err(|_| generator::Error::Unknown)?
+                .inspect_err(|e| error!("Preparing a photo: {e}"))
+                .map_err(|e| match e {
+                    Error::Empty(_) | Error::Io { .. } => generator::Error::Misconfigured,
+                    Error::Image { .. } | Error::State(_) => generator::Error::Unknown,
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+
+    use super::*;
+    use crate::generator::{Content, View};
+
+    fn fixtures() -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "photos"]
+            .iter()
+            .collect()
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        fixtures().join(name)
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).expect("Valid date")
+    }
+
+    fn history(capacity: usize) -> History {
+        History::open(None, capacity).expect("History opens")
+    }
+
+    #[test]
+    fn list_skips_other_files() {
+        let photos = list(&fixtures()).expect("Folder is readable");
+        assert_eq!(
+            photos,
+            [
+                fixture("beach.jpg"),
+                fixture("city.jpg"),
+                fixture("forest.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn taken_reads_exif() {
+        assert_eq!(taken(&fixture("beach.jpg")), Some(date(2019, 5, 6)));
+        assert_eq!(taken(&fixture("forest.png")), None);
+    }
+
+    #[test]
+    fn load_applies_exif_orientation() {
+        // Stored as portrait with an orientation that rotates it to landscape.
+        let city = load(&fixture("city.jpg")).expect("Photo decodes");
+        assert_eq!(city.dimensions(), (96, 48));
+    }
+
+    #[test]
+    fn sequential_wraps_around() {
+        let photos = list(&fixtures()).expect("Folder is readable");
+        let mut history = history(DEFAULT_RECENT);
+        let today = date(2024, 1, 1);
+        let shown: Vec<_> = (0..4)
+            .map(|_| {
+                history
+                    .pick(Order::Sequential, &photos, today)
+                    .expect("A photo is picked")
+            })
+            .collect();
+        assert_eq!(shown, [&photos[0], &photos[1], &photos[2], &photos[0]]);
+    }
+
+    #[test]
+    fn shuffle_avoids_recent_photos() {
+        let photos = list(&fixtures()).expect("Folder is readable");
+        let mut history = history(2);
+        let today = date(2024, 1, 1);
+        for _ in 0..10 {
+            let before = history.recent.clone();
+            let photo = history
+                .pick(Order::Shuffle, &photos, today)
+                .expect("A photo is picked");
+            assert!(!before.contains(photo));
+        }
+    }
+
+    #[test]
+    fn on_this_day_prefers_matching_photos() {
+        let photos = list(&fixtures()).expect("Folder is readable");
+        let mut history = history(DEFAULT_RECENT);
+        for _ in 0..3 {
+            assert_eq!(
+                history.pick(Order::OnThisDay, &photos, date(2024, 5, 6)),
+                Some(&photos[0])
+            );
+        }
+        assert!(
+            history
+                .pick(Order::OnThisDay, &photos, date(2024, 2, 29))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn history_is_persisted() {
+        let path = std::env::temp_dir().join(format!("atrmnl_photos_{}.json", std::process::id()));
+        let mut history = History::open(Some(path.clone()), 2).expect("History opens");
+        for photo in ["a.jpg", "b.jpg", "c.jpg"] {
+            history.record(Path::new(photo));
+        }
+        let reopened = History::open(Some(path.clone()), 2).expect("History reopens");
+        fs::remove_file(&path).expect("Failed to remove state");
+        assert_eq!(
+            reopened.recent,
+            [PathBuf::from("b.jpg"), PathBuf::from("c.jpg")]
+        );
+    }
+
+    #[test]
+    fn tone_map_dithers_to_levels() {
+        let mut image = GrayImage::from_fn(32, 32, |x, _| {
+            image::Luma([u8::try_from(x * 4 + 60).expect("Value fits")])
+        });
+        tone_map(&mut image, 4);
+        assert!(image.pixels().all(|p| [0, 85, 170, 255].contains(&p.0[0])));
+        // The stretch reaches both ends of the scale.
+        assert!(image.pixels().any(|p| p.0[0] == 0));
+        assert!(image.pixels().any(|p| p.0[0] == 255));
+    }
+
+    #[tokio::test]
+    async fn generate_fits_the_view() {
+        let plugin = PhotosPlugin::new(fixtures(), Order::Sequential, Fit::Cover, None, None, None)
+            .expect("Plugin is configured");
+        let ctx = RenderContext::new("d").with_view(View::Quadrant);
+        let html = plugin.generate(&ctx).await.expect("Photo renders");
+        let data = html
+            .split("base64,")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("Photo is embedded");
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .expect("Data is base64");
+        let image = image::load_from_memory(&png).expect("Data is a PNG");
+        assert_eq!(image.dimensions(), (400, 240));
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        let new = |dir: PathBuf, levels| {
+            PhotosPlugin::new(dir, Order::Shuffle, Fit::Contain, levels, None, None)
+        };
+        assert!(new(fixtures(), Some(1)).is_err());
+        assert!(new(fixture("missing"), None).is_err());
+        assert!(new(fixtures(), Some(4)).is_ok());
+    }
+}
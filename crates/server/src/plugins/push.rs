@@ -0,0 +1,298 @@
+use std::{collections::VecDeque, path::PathBuf, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{
+    state,
+    template::{self, Layouts, Sources},
+};
+use crate::{
+    auth,
+    error::{Canonical, IntoCanonical},
+    generator::{self, RenderContext},
+    storage,
+};
+
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the payload exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+    #[error("the payload is not valid JSON")]
+    NotJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    State(#[from] state::Error),
+}
+
+impl IntoCanonical for Error {
+    fn into_canonical(self) -> Canonical {
+        match self {
+            Self::TooLarge { .. } => Canonical::ResourceExhausted,
+            Self::NotJson(_) => Canonical::InvalidArgument,
+            Self::State(_) => Canonical::Internal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payload {
+    pub received: DateTime<Utc>,
+    pub data: Value,
+}
+
+// Received payloads, oldest first, mirrored to an optional JSON file.
+struct History {
+    path: Option<PathBuf>,
+    payloads: VecDeque<Payload>,
+    capacity: usize,
+}
+
+impl History {
+    fn open(path: Option<PathBuf>, capacity: usize) -> Result<Self, Error> {
+        let payloads = state::load(path.as_deref())?;
+        let mut history = Self {
+            path,
+            payloads,
+            capacity,
+        };
+        history.truncate();
+        Ok(history)
+    }
+
+    fn truncate(&mut self) {
+        while self.payloads.len() > self.capacity {
+            self.payloads.pop_front();
+        }
+    }
+
+    fn push(&mut self, payload: Payload) -> Result<(), Error> {
+        self.payloads.push_back(payload);
+        self.truncate();
+        Ok(state::save(self.path.as_deref(), &self.payloads)?)
+    }
+}
+
+pub struct PushPlugin {
+    token: String,
+    max_bytes: usize,
+    layouts: Layouts,
+    fields: Map<String, Value>,
+    history: Mutex<History>,
+}
+
+impl PushPlugin {
+    pub fn new(
+        token: String,
+        markup: &template::Markup,
+        fields: Map<String, Value>,
+        history: Option<usize>,
+        max_bytes: Option<usize>,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        Self::from_sources(token, &markup.read()?, fields, history, max_bytes, state)
+    }
+
+    pub fn from_sources(
+        token: String,
+        sources: &Sources,
+        fields: Map<String, Value>,
+        history: Option<usize>,
+        max_bytes: Option<usize>,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        if token.is_empty() {
+            error!("A push plugin needs a token");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let capacity = history.unwrap_or(1);
+        if capacity == 0 {
+            error!("A push plugin has to keep at least one payload");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let layouts = Layouts::parse(sources)
+            .inspect_err(|e| error!("Failed to parse liquid template: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        let history = History::open(state, capacity)
+            .inspect_err(|e| error!("Loading pushed data: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            token,
+            max_bytes: max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            layouts,
+            fields,
+            history: Mutex::new(history),
+        })
+    }
+
+    // Whether the request carries `Authorization: Bearer <token>`.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        auth::bearer(authorization, &self.token)
+    }
+
+    // Checks a pushed body without storing it.
+    pub fn accept(&self, body: &[u8], now: DateTime<Utc>) -> Result<Payload, Error> {
+        if body.len() > self.max_bytes {
+            return Err(Error::TooLarge {
+                limit: self.max_bytes,
+            });
+        }
+        // TRMNL webhooks wrap the data in `merge_variables`.
+        let data = match serde_json::from_slice(body)? {
+            Value::Object(mut object) => object
+                .remove("merge_variables")
+                .unwrap_or(Value::Object(object)),
+            other => other,
+        };
+        debug!("Received {} bytes of pushed data", body.len());
+        Ok(Payload {
+            received: now,
+            data,
+        })
+    }
+
+    pub fn store(&self, payload: Payload) -> Result<(), Error> {
+        self.history
+            .lock()
+            .expect("push history lock poisoned")
+            .push(payload)
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> Result<String, generator::Error> {
+        let history = self.history.lock().expect("push history lock poisoned");
+        let latest = history.payloads.back();
+        let data = latest.map(|p| vec![p.data.clone()]).unwrap_or_default();
+        let mut vars = template::variables(data, &self.fields, ctx, now);
+        vars.insert(
+            "received".into(),
+            latest.map_or(Value::Null, |p| p.received.to_rfc3339().into()),
+        );
+        vars.insert(
+            "history".into(),
+            serde_json::to_value(history.payloads.iter().rev().collect::<Vec<_>>())
+                .expect("Payloads serialize"),
+        );
+        drop(history);
+        let globals = liquid::to_object(&Value::Object(vars))
+            .map_err(|e| generator::Error::Template(e.to_string()))?;
+        self.layouts
+            .for_view(ctx.view)
+            .render(&globals)
+            .map_err(|e| generator::Error::Template(e.to_string()))
+    }
+}
+
+impl generator::Content for PushPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { self.render(ctx, Utc::now()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const TOKEN: &str = "Bearer s3cret";
+
+    fn at(h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, h, 0, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn receive(plugin: &PushPlugin, body: &[u8], now: DateTime<Utc>) -> Result<(), Error> {
+        plugin.store(plugin.accept(body, now)?)
+    }
+
+    fn plugin(full: &str, history: Option<usize>, state: Option<PathBuf>) -> PushPlugin {
+        let sources = Sources {
+            full: full.into(),
+            ..Sources::default()
+        };
+        PushPlugin::from_sources("s3cret".into(), &sources, Map::new(), history, None, state)
+            .expect("Push plugin is configured")
+    }
+
+    #[test]
+    fn authorized_requires_the_token() {
+        let plugin = plugin("{{ data }}", None, None);
+        for authorization in [None, Some("Bearer wrong"), Some("s3cret")] {
+            assert!(!plugin.authorized(authorization));
+        }
+        assert!(plugin.authorized(Some(TOKEN)));
+    }
+
+    #[test]
+    fn receive_limits_payload_size() {
+        let sources = Sources {
+            full: "{{ data }}".into(),
+            ..Sources::default()
+        };
+        let plugin =
+            PushPlugin::from_sources("s3cret".into(), &sources, Map::new(), None, Some(16), None)
+                .expect("Push plugin is configured");
+        let result = receive(&plugin, br#"{"message": "far too long"}"#, at(8));
+        assert!(matches!(result, Err(Error::TooLarge { limit: 16 })));
+        assert!(receive(&plugin, br#"{"n": 1}"#, at(8)).is_ok());
+        assert!(matches!(
+            receive(&plugin, b"{", at(8)),
+            Err(Error::NotJson(_))
+        ));
+    }
+
+    #[test]
+    fn render_uses_the_latest_payload() {
+        let plugin = plugin(
+            "{% if received %}{{ temp }}° at {{ received }}{% else %}waiting{% endif %}",
+            None,
+            None,
+        );
+        let ctx = RenderContext::new("d");
+        assert_eq!(plugin.render(&ctx, at(8)).expect("Renders"), "waiting");
+        receive(&plugin, br#"{"merge_variables": {"temp": 21}}"#, at(8))
+            .expect("Payload is stored");
+        receive(&plugin, br#"{"temp": 23}"#, at(9)).expect("Payload is stored");
+        assert_eq!(
+            plugin.render(&ctx, at(10)).expect("Renders"),
+            "23° at 2024-05-06T09:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn history_rolls_and_persists() {
+        let path = std::env::temp_dir().join(format!("atrmnl_push_{}.json", std::process::id()));
+        let template = "{% for p in history %}{{ p.data.n }} {% endfor %}";
+        let first = plugin(template, Some(2), Some(path.clone()));
+        for (n, h) in [(1, 7), (2, 8), (3, 9)] {
+            receive(&first, format!(r#"{{"n": {n}}}"#).as_bytes(), at(h))
+                .expect("Payload is stored");
+        }
+        let ctx = RenderContext::new("d");
+        assert_eq!(first.render(&ctx, at(10)).expect("Renders"), "3 2 ");
+
+        let reopened = plugin(template, Some(1), Some(path.clone()));
+        let html = reopened.render(&ctx, at(10)).expect("Renders");
+        std::fs::remove_file(&path).expect("Failed to remove state file");
+        assert_eq!(html, "3 ");
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        let sources = Sources::default();
+        let new = |token: &str, history| {
+            PushPlugin::from_sources(token.into(), &sources, Map::new(), history, None, None)
+        };
+        assert!(new("", None).is_err());
+        assert!(new("s3cret", Some(0)).is_err());
+        assert!(new("s3cret", Some(5)).is_ok());
+    }
+}
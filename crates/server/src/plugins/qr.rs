@@ -0,0 +1,146 @@
+use futures::future::BoxFuture;
+use log::error;
+use sailfish::TemplateOnce;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    qr::{Correction, Qr, Security, Wifi},
+    storage,
+};
+
+// Space kept free around the code and below it for the caption.
+const MARGIN: u32 = 40;
+const CAPTION_HEIGHT: u32 = 80;
+// Longer payloads are not repeated under the code.
+const MAX_SHOWN: usize = 64;
+
+#[derive(TemplateOnce)]
+#[template(path = "qr.stpl")]
+struct QrTemplate<'a> {
+    svg: &'a str,
+    title: Option<&'a str>,
+    details: &'a [(&'static str, String)],
+    compact: bool,
+}
+
+pub struct QrPlugin {
+    payload: String,
+    title: Option<String>,
+    details: Vec<(&'static str, String)>,
+    correction: Correction,
+}
+
+impl QrPlugin {
+    pub fn new(
+        data: Option<String>,
+        wifi: Option<Wifi>,
+        title: Option<String>,
+        correction: Correction,
+    ) -> Result<Self, storage::LoadError> {
+        let plugin = match (data, wifi) {
+            (Some(data), None) => Self {
+                details: if data.len() <= MAX_SHOWN {
+                    vec![("", data.clone())]
+                } else {
+                    Vec::new()
+                },
+                payload: data,
+                title,
+                correction,
+            },
+            (None, Some(wifi)) => {
+                let mut details = vec![("Network", wifi.ssid.clone())];
+                details.extend(
+                    (wifi.security != Security::Open)
+                        .then(|| wifi.password.clone())
+                        .flatten()
+                        .map(|p| ("Password", p)),
+                );
+                Self {
+                    payload: wifi.payload(),
+                    title: title.or_else(|| Some("Wi-Fi".into())),
+                    details,
+                    correction,
+                }
+            }
+            _ => {
+                error!("A QR code needs either `data` or `wifi`");
+                return Err(storage::LoadError::InvalidConfig);
+            }
+        };
+        Qr::new(&plugin.payload, 0)
+            .with_correction(correction)
+            .validate()
+            .inspect_err(|e| error!("Encoding the QR code: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(plugin)
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<String, generator::Error> {
+        let compact = ctx.view == View::Quadrant;
+        let (width, height) = ctx.view.size();
+        let caption = if compact { 0 } else { CAPTION_HEIGHT };
+        let size = width
+            .min(height.saturating_sub(caption))
+            .saturating_sub(MARGIN);
+        let svg = Qr::new(&self.payload, size)
+            .with_correction(self.correction)
+            .svg()
+            .inspect_err(|e| error!("Rendering the QR code: {e}"))
+            .map_err(|_| generator::Error::Misconfigured)?;
+        Ok(QrTemplate {
+            svg: &svg,
+            title: self.title.as_deref(),
+            details: &self.details,
+            compact,
+        }
+        .render_once()
+        .expect("QR template render failed"))
+    }
+}
+
+impl generator::Content for QrPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { self.render(ctx) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guest() -> Wifi {
+        Wifi {
+            ssid: "Guests".into(),
+            password: Some("welcome home".into()),
+            security: Security::Wpa,
+            hidden: false,
+        }
+    }
+
+    #[test]
+    fn render_fits_the_view() {
+        let plugin =
+            QrPlugin::new(None, Some(guest()), None, Correction::Medium).expect("Valid config");
+        let html = plugin.render(&RenderContext::new("d")).expect("Code fits");
+        assert!(html.contains("<svg"));
+        assert!(html.contains("Wi-Fi"));
+        assert!(html.contains("welcome home"));
+        let quadrant = plugin
+            .render(&RenderContext::new("d").with_view(View::Quadrant))
+            .expect("Code fits");
+        assert!(!quadrant.contains("welcome home"));
+    }
+
+    #[test]
+    fn new_needs_exactly_one_payload() {
+        let url = || Some("https://example.com".to_owned());
+        assert!(QrPlugin::new(url(), None, None, Correction::Low).is_ok());
+        assert!(QrPlugin::new(None, None, None, Correction::Low).is_err());
+        assert!(QrPlugin::new(url(), Some(guest()), None, Correction::Low).is_err());
+        assert!(QrPlugin::new(Some("x".repeat(3000)), None, None, Correction::Low).is_err());
+    }
+}
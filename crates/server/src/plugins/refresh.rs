@@ -0,0 +1,116 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{debug, error};
+
+use super::Plugin;
+use crate::generator::RenderContext;
+
+// How often plugins without a `refresh` export are checked again, since a
+// reloaded module may have gained one.
+const IDLE_INTERVAL: Duration = Duration::from_mins(1);
+
+// Runs the background work of every plugin on the plugin's schedule, such as
+// the `refresh` export of WASM plugins, starting right away so the first
+// render already finds data.
+pub fn schedule<'a>(plugins: impl IntoIterator<Item = (RenderContext, &'a Arc<Plugin>)>) {
+    for (ctx, plugin) in plugins {
+        let Some(display) = plugin.refresh_label() else {
+            continue;
+        };
+        let plugin = Arc::clone(plugin);
+        tokio::spawn(async move {
+            loop {
+                let Some(interval) = plugin.refresh_schedule() else {
+                    tokio::time::sleep(IDLE_INTERVAL).await;
+                    continue;
+                };
+                match plugin.refresh(&ctx).await {
+                    Ok(()) => debug!("Refreshed {display} for {}", ctx.device),
+                    Err(e) => error!("Refreshing {display} for {} failed: {e}", ctx.device),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{generator::Content, plugins::PluginConfig};
+
+    #[tokio::test]
+    async fn schedule_refreshes_right_away() {
+        let plugin = Arc::new(
+            Plugin::new(PluginConfig::Wasm {
+                name: "refresh".into(),
+                path: [
+                    env!("CARGO_MANIFEST_DIR"),
+                    "tests",
+                    "plugins",
+                    "refresh.wat",
+                ]
+                .iter()
+                .collect(),
+                config: json!({"key": "data", "value": "fresh"}),
+                limits: crate::plugins::wasm::Limits::default(),
+                state: None,
+                templates: std::collections::HashMap::new(),
+                refresh_secs: Some(3600),
+            })
+            .await
+            .expect("Plugin loads"),
+        );
+        let ctx = RenderContext::new("d");
+        assert_eq!(plugin.refresh_schedule(), Some(Duration::from_hours(1)));
+        schedule([(ctx.clone(), &plugin)]);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while plugin.generate(&ctx).await.expect("Renders") != r#""fresh""# {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("Plugin is refreshed in time");
+    }
+
+    #[tokio::test]
+    async fn schedule_runs_uptime_checks() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Binds a free port");
+        let addr = listener.local_addr().expect("Bound address");
+        let plugin = Arc::new(
+            Plugin::new(PluginConfig::Uptime {
+                name: None,
+                targets: vec![crate::plugins::uptime::TargetConfig {
+                    name: Some("local".into()),
+                    tcp: Some(addr.to_string()),
+                    http: None,
+                    expect: None,
+                }],
+                interval_secs: Some(3600),
+                timeout_secs: Some(2),
+                history: None,
+            })
+            .await
+            .expect("Plugin loads"),
+        );
+        assert_eq!(plugin.refresh_schedule(), Some(Duration::from_hours(1)));
+        let ctx = RenderContext::new("d");
+        schedule([(ctx.clone(), &plugin)]);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !plugin
+                .generate(&ctx)
+                .await
+                .expect("Renders")
+                .contains("All systems up")
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("Targets are checked in time");
+    }
+}
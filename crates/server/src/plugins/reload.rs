@@ -0,0 +1,114 @@
+use std::{fs, path::Path, sync::Arc, time::Duration, time::SystemTime};
+
+use log::{error, info};
+use tokio::sync::watch;
+
+use super::Plugin;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Counts successful reloads; the value changes whenever any module was swapped.
+pub type Reloads = watch::Receiver<u64>;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Polls the module of every WASM plugin and swaps in the new version whenever
+// the file changes. A module that fails to load leaves the previous one active.
+pub fn watch<'a>(plugins: impl IntoIterator<Item = &'a Arc<Plugin>>) -> Reloads {
+    let (tx, rx) = watch::channel(0);
+    let mut watched: Vec<_> = plugins
+        .into_iter()
+        .filter_map(|p| {
+            let path = p.module_path()?;
+            Some((Arc::clone(p), modified(path)))
+        })
+        .collect();
+    if watched.is_empty() {
+        return rx;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            for (plugin, seen) in &mut watched {
+                let path = plugin.module_path().expect("Only WASM plugins are watched");
+                let current = modified(path);
+                if current == *seen {
+                    continue;
+                }
+                *seen = current;
+                let display = path.display().to_string();
+                let reloading = Arc::clone(plugin);
+                match tokio::task::spawn_blocking(move || reloading.reload()).await {
+                    Ok(Ok(())) => {
+                        info!("Reloaded WASM plugin {display}");
+                        tx.send_modify(|generation| *generation += 1);
+                    }
+                    Ok(Err(e)) => error!("Keeping previous version of {display}: {e}"),
+                    Err(e) => error!("Reloading {display} panicked: {e}"),
+                }
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        generator::{Content, RenderContext},
+        plugins::PluginConfig,
+    };
+
+    #[tokio::test]
+    async fn watch_swaps_changed_modules() {
+        let source: std::path::PathBuf =
+            [env!("CARGO_MANIFEST_DIR"), "tests", "plugins", "echo.wat"]
+                .iter()
+                .collect();
+        let echo = fs::read_to_string(source).expect("Fixture is readable");
+        let path = std::env::temp_dir().join(format!("atrmnl_reload_{}.wat", std::process::id()));
+        fs::write(&path, &echo).expect("Failed to write module");
+
+        let plugin = Arc::new(
+            Plugin::new(PluginConfig::Wasm {
+                name: "echo".into(),
+                path: path.clone(),
+                config: json!("x"),
+                limits: crate::plugins::wasm::Limits::default(),
+                state: None,
+                templates: std::collections::HashMap::new(),
+                refresh_secs: None,
+            })
+            .await
+            .expect("Plugin loads"),
+        );
+        let mut reloads = watch([&plugin]);
+        let ctx = RenderContext::new("d");
+
+        // Broken modules are rejected and the previous version keeps serving.
+        fs::write(&path, "(module").expect("Failed to write module");
+        tokio::time::sleep(POLL_INTERVAL * 3).await;
+        assert_eq!(*reloads.borrow(), 0);
+        assert_eq!(
+            plugin.generate(&ctx).await.expect("Old module runs"),
+            r#"<p>"x"</p>"#
+        );
+
+        fs::write(&path, echo.replace("<p></p>", "<b></b>")).expect("Failed to write module");
+        tokio::time::timeout(POLL_INTERVAL * 5, reloads.changed())
+            .await
+            .expect("Module is reloaded in time")
+            .expect("Watcher is running");
+        fs::remove_file(&path).expect("Failed to remove module");
+        assert_eq!(
+            plugin.generate(&ctx).await.expect("New module runs"),
+            r#"<b>"x"</b>"#
+        );
+    }
+}
@@ -0,0 +1,509 @@
+use std::{collections::VecDeque, fs, io, path::PathBuf, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use log::{debug, error, warn};
+use sailfish::TemplateOnce;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{format, state};
+use crate::{
+    auth,
+    chart::{Chart, Kind},
+    error::{Canonical, IntoCanonical},
+    generator::{self, RenderContext, View},
+    storage,
+};
+
+const DEFAULT_WINDOW: Duration = Duration::from_hours(24);
+// Two days of readings sent every minute.
+const DEFAULT_LIMIT: usize = 2880;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the reading is not valid JSON")]
+    NotJson(#[from] serde_json::Error),
+    #[error("the reading has no numeric value")]
+    NoValue,
+    #[error("failed to read the sensor file {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    State(#[from] state::Error),
+}
+
+impl IntoCanonical for Error {
+    fn into_canonical(self) -> Canonical {
+        match self {
+            Self::NotJson(_) | Self::NoValue => Canonical::InvalidArgument,
+            Self::Io { .. } | Self::State(_) => Canonical::Internal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub time: DateTime<Utc>,
+    pub value: f64,
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|v: &f64| v.is_finite())
+}
+
+// Accepts a bare number or `{"value": 21.5, "time": "<RFC 3339>"}`.
+fn parse(text: &str, now: DateTime<Utc>) -> Result<Reading, Error> {
+    if let Ok(value) = text.trim().parse::<f64>() {
+        return Some(value)
+            .filter(|v| v.is_finite())
+            .map(|value| Reading { time: now, value })
+            .ok_or(Error::NoValue);
+    }
+    match serde_json::from_str(text)? {
+        Value::Object(object) => {
+            let value = object.get("value").and_then(number).ok_or(Error::NoValue)?;
+            let time = object
+                .get("time")
+                .and_then(Value::as_str)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map_or(now, |time| time.to_utc());
+            Ok(Reading { time, value })
+        }
+        other => number(&other)
+            .map(|value| Reading { time: now, value })
+            .ok_or(Error::NoValue),
+    }
+}
+
+// Readings, oldest first, mirrored to an optional JSON file.
+struct History {
+    path: Option<PathBuf>,
+    readings: VecDeque<Reading>,
+    window: TimeDelta,
+    limit: usize,
+    // Modification time of the sensor file when it was last read.
+    sampled: Option<DateTime<Utc>>,
+}
+
+impl History {
+    fn open(path: Option<PathBuf>, window: TimeDelta, limit: usize) -> Result<Self, Error> {
+        let readings = state::load(path.as_deref())?;
+        Ok(Self {
+            path,
+            readings,
+            window,
+            limit,
+            sampled: None,
+        })
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let start = now - self.window;
+        while self
+            .readings
+            .front()
+            .is_some_and(|r| r.time < start || self.readings.len() > self.limit)
+        {
+            self.readings.pop_front();
+        }
+    }
+
+    fn push(&mut self, reading: Reading, now: DateTime<Utc>) -> Result<(), Error> {
+        // Readings with an explicit time may arrive out of order.
+        let at = self.readings.partition_point(|r| r.time <= reading.time);
+        self.readings.insert(at, reading);
+        self.prune(now);
+        Ok(state::save(self.path.as_deref(), &self.readings)?)
+    }
+
+    fn window(&self, now: DateTime<Utc>) -> impl Iterator<Item = &Reading> {
+        let start = now - self.window;
+        self.readings
+            .iter()
+            .filter(move |r| r.time >= start && r.time <= now)
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "sensor.stpl")]
+struct SensorTemplate<'a> {
+    label: &'a str,
+    unit: &'a str,
+    current: Option<String>,
+    min: String,
+    max: String,
+    updated: String,
+    chart: Chart,
+    compact: bool,
+}
+
+pub struct SensorPlugin {
+    label: String,
+    unit: String,
+    token: Option<String>,
+    path: Option<PathBuf>,
+    history: Mutex<History>,
+}
+
+impl SensorPlugin {
+    pub fn new(
+        label: Option<String>,
+        unit: String,
+        token: Option<String>,
+        path: Option<PathBuf>,
+        window: Option<Duration>,
+        limit: Option<usize>,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        if token.is_none() && path.is_none() {
+            error!("A sensor plugin needs a token to receive readings or a path to read them from");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        if token.as_deref() == Some("") {
+            error!("A sensor plugin's token must not be empty");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let window = window.unwrap_or(DEFAULT_WINDOW);
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        if window.is_zero() || limit == 0 {
+            error!("A sensor plugin has to keep some history");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let window = TimeDelta::from_std(window).map_err(|_| {
+            error!("The sensor window is too long");
+            storage::LoadError::InvalidConfig
+        })?;
+        let history = History::open(state, window, limit)
+            .inspect_err(|e| error!("Loading sensor readings: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            label: label.unwrap_or_default(),
+            unit,
+            token,
+            path,
+            history: Mutex::new(history),
+        })
+    }
+
+    // Whether the request carries `Authorization: Bearer <token>`.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        self.token
+            .as_deref()
+            .is_some_and(|token| auth::bearer(authorization, token))
+    }
+
+    // Parses a sent reading without storing it.
+    pub fn accept(body: &[u8], now: DateTime<Utc>) -> Result<Reading, Error> {
+        let reading = parse(&String::from_utf8_lossy(body), now)?;
+        debug!("Received sensor reading {}", reading.value);
+        Ok(reading)
+    }
+
+    pub fn store(&self, reading: Reading, now: DateTime<Utc>) -> Result<(), Error> {
+        self.history
+            .lock()
+            .expect("sensor history lock poisoned")
+            .push(reading, now)
+    }
+
+    // Takes a new reading from the file whenever it was modified since it
+    // was last read.
+    fn sample(&self, now: DateTime<Utc>) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+        let modified: DateTime<Utc> = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_err(io_error)?
+            .into();
+        let mut history = self.history.lock().expect("sensor history lock poisoned");
+        if history.sampled == Some(modified) {
+            return Ok(());
+        }
+        history.sampled = Some(modified);
+        let reading = parse(&fs::read_to_string(path).map_err(io_error)?, modified)?;
+        // The reading may already be in the saved history from before a restart.
+        if history.readings.contains(&reading) {
+            return Ok(());
+        }
+        history.push(reading, now)
+    }
+
+    fn chart(&self, history: &History, ctx: &RenderContext, now: DateTime<Utc>) -> Chart {
+        let (width, _) = ctx.view.size();
+        let (kind, height, buckets) = match ctx.view {
+            View::Full => (Kind::Area, 220, 48),
+            View::HalfVertical => (Kind::Area, 200, 24),
+            View::Quadrant => (Kind::Sparkline, 50, 24),
+        };
+        let tz = ctx.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        let start = now - history.window;
+        let step = history.window / buckets;
+        let mut sums = vec![(0.0, 0); usize::try_from(buckets).unwrap_or_default()];
+        for reading in history.window(now) {
+            let bucket = (reading.time - start).num_seconds() / step.num_seconds().max(1);
+            if let Some((sum, count)) = usize::try_from(bucket).ok().and_then(|b| sums.get_mut(b)) {
+                *sum += reading.value;
+                *count += 1;
+            }
+        }
+        let format = if history.window > TimeDelta::days(1) {
+            "%a %H:%M"
+        } else {
+            "%H:%M"
+        };
+        let chart = Chart::new(kind, width - 40, height)
+            .with_series(sums.iter().map(|&(sum, count)| {
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    sum / f64::from(count)
+                }
+            }))
+            .with_unit(self.unit.clone());
+        if kind == Kind::Sparkline {
+            return chart;
+        }
+        chart.with_labels((0..buckets).map(|i| {
+            (start + step * i)
+                .with_timezone(&tz)
+                .format(format)
+                .to_string()
+        }))
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        if let Err(e) = self.sample(now) {
+            warn!("Reading sensor file: {e}");
+        }
+        let history = self.history.lock().expect("sensor history lock poisoned");
+        let latest = history.readings.back().copied();
+        let (min, max) = history
+            .window(now)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), r| {
+                (min.min(r.value), max.max(r.value))
+            });
+        let chart = self.chart(&history, ctx, now);
+        drop(history);
+        let format = |value: f64| {
+            if value.is_finite() {
+                format!("{value:.1}")
+            } else {
+                "–".into()
+            }
+        };
+        SensorTemplate {
+            label: &self.label,
+            unit: &self.unit,
+            current: latest.map(|r| format(r.value)),
+            min: format(min),
+            max: format(max),
+            updated: latest.map_or_else(String::new, |r| format::age(r.time, now)),
+            chart,
+            compact: ctx.view == View::Quadrant,
+        }
+        .render_once()
+        .expect("sensor template render failed")
+    }
+}
+
+impl generator::Content for SensorPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { Ok(self.render(ctx, Utc::now())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const TOKEN: &str = "Bearer s3cret";
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, h, m, 0)
+            .single()
+            .expect("Valid time")
+    }
+
+    fn receive(sensor: &SensorPlugin, body: &[u8], now: DateTime<Utc>) -> Result<(), Error> {
+        sensor.store(SensorPlugin::accept(body, now)?, now)
+    }
+
+    fn sensor(window: Option<Duration>, limit: Option<usize>) -> SensorPlugin {
+        SensorPlugin::new(
+            Some("Living room".into()),
+            "°C".into(),
+            Some("s3cret".into()),
+            None,
+            window,
+            limit,
+            None,
+        )
+        .expect("Sensor is configured")
+    }
+
+    #[test]
+    fn parse_accepts_numbers_and_objects() {
+        let now = at(8, 0);
+        let value = |text| parse(text, now).expect("Reading parses").value;
+        assert!((value("21.5\n") - 21.5).abs() < f64::EPSILON);
+        assert!((value(r#"{"value": 40}"#) - 40.0).abs() < f64::EPSILON);
+        assert!((value(r#"{"value": "19.25"}"#) - 19.25).abs() < f64::EPSILON);
+        let reading =
+            parse(r#"{"value": 1, "time": "2024-05-06T07:30:00Z"}"#, now).expect("Reading parses");
+        assert_eq!(reading.time, at(7, 30));
+        assert!(matches!(parse(r#"{"temp": 1}"#, now), Err(Error::NoValue)));
+        assert!(matches!(parse("NaN", now), Err(Error::NoValue)));
+        assert!(matches!(parse("{", now), Err(Error::NotJson(_))));
+    }
+
+    #[test]
+    fn authorized_requires_the_token() {
+        let sensor = sensor(None, None);
+        for authorization in [None, Some("Bearer wrong"), Some("s3cret")] {
+            assert!(!sensor.authorized(authorization));
+        }
+        assert!(sensor.authorized(Some(TOKEN)));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let sensor = sensor(Some(Duration::from_hours(2)), Some(3));
+        for (value, minute) in [(1, 0), (2, 10), (3, 20), (4, 30)] {
+            receive(&sensor, value.to_string().as_bytes(), at(8, minute))
+                .expect("Reading is stored");
+        }
+        let values = |sensor: &SensorPlugin| {
+            let history = sensor.history.lock().expect("Lock is not poisoned");
+            history.readings.iter().map(|r| r.value).collect::<Vec<_>>()
+        };
+        assert_eq!(values(&sensor), [2.0, 3.0, 4.0]);
+        receive(&sensor, b"5", at(10, 25)).expect("Reading is stored");
+        assert_eq!(values(&sensor), [4.0, 5.0]);
+    }
+
+    #[test]
+    fn render_shows_current_and_range() {
+        let sensor = sensor(None, None);
+        for (value, hour) in [("18.5", 2), ("23.5", 5), ("21", 7)] {
+            receive(&sensor, value.as_bytes(), at(hour, 0)).expect("Reading is stored");
+        }
+        let ctx = RenderContext::new("d");
+        let html = sensor.render(&ctx, at(8, 0));
+        assert!(html.contains("Living room"));
+        assert!(html.contains("21.0"));
+        assert!(html.contains("18.5"));
+        assert!(html.contains("23.5"));
+        assert!(html.contains("<svg"));
+        assert!(html.contains("1 h ago"));
+
+        let quadrant = sensor.render(&ctx.with_view(View::Quadrant), at(8, 0));
+        assert!(quadrant.contains("<circle"));
+    }
+
+    #[test]
+    fn sample_reads_the_file_once_per_change() {
+        let path = std::env::temp_dir().join(format!("atrmnl_sensor_{}", std::process::id()));
+        fs::write(&path, "22.5").expect("Failed to write reading");
+        let sensor = SensorPlugin::new(
+            None,
+            String::new(),
+            None,
+            Some(path.clone()),
+            None,
+            None,
+            None,
+        )
+        .expect("Sensor is configured");
+        let now = Utc::now();
+        sensor.sample(now).expect("File is sampled");
+        sensor.sample(now).expect("File is sampled");
+        fs::remove_file(&path).expect("Failed to remove reading");
+        let readings = sensor
+            .history
+            .lock()
+            .expect("Lock is not poisoned")
+            .readings
+            .clone();
+        assert_eq!(readings.len(), 1);
+        assert!((readings[0].value - 22.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn sample_is_not_hidden_by_newer_sent_readings() {
+        let path = std::env::temp_dir().join(format!("atrmnl_sensor_mixed_{}", std::process::id()));
+        fs::write(&path, "1").expect("Failed to write reading");
+        let sensor = SensorPlugin::new(
+            None,
+            String::new(),
+            Some("s3cret".into()),
+            Some(path.clone()),
+            None,
+            None,
+            None,
+        )
+        .expect("Sensor is configured");
+        let now = Utc::now();
+        receive(&sensor, b"2", now + TimeDelta::minutes(10)).expect("Reading is stored");
+        sensor.sample(now).expect("File is sampled");
+        let modified = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .expect("File has a modification time");
+        fs::write(&path, "3").expect("Failed to write reading");
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(modified + Duration::from_secs(5)))
+            .expect("Failed to touch reading");
+        sensor.sample(now).expect("File is sampled");
+        fs::remove_file(&path).expect("Failed to remove reading");
+        let mut values: Vec<_> = sensor
+            .history
+            .lock()
+            .expect("Lock is not poisoned")
+            .readings
+            .iter()
+            .map(|r| r.value)
+            .collect();
+        values.sort_by(f64::total_cmp);
+        assert_eq!(values, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        let new = |token: Option<&str>, path: Option<&str>, limit| {
+            SensorPlugin::new(
+                None,
+                String::new(),
+                token.map(Into::into),
+                path.map(Into::into),
+                None,
+                limit,
+                None,
+            )
+        };
+        assert!(new(None, None, None).is_err());
+        assert!(new(Some(""), None, None).is_err());
+        assert!(new(Some("s3cret"), None, Some(0)).is_err());
+        assert!(new(None, Some("/tmp/reading"), None).is_ok());
+    }
+}
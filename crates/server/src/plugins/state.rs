@@ -0,0 +1,94 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to access the plugin state at {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("the plugin state at {path} is not valid JSON")]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+// Reads a plugin's JSON state file, starting empty when there is none yet.
+pub fn load<T: DeserializeOwned + Default>(path: Option<&Path>) -> Result<T, Error> {
+    let Some(path) = path else {
+        return Ok(T::default());
+    };
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).map_err(|source| Error::Invalid {
+            path: path.to_path_buf(),
+            source,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(source) => Err(Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+// Replaces the state file through a temporary sibling, so a crash mid-write
+// leaves the previous state rather than a truncated file.
+pub fn save<T: Serialize + ?Sized>(path: Option<&Path>, state: &T) -> Result<(), Error> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let data = serde_json::to_string(state).expect("Plugin state serializes");
+    fs::write(&temporary, data)
+        .and_then(|()| fs::rename(&temporary, path))
+        .map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn save_replaces_the_file_and_load_reads_it_back() {
+        let path = std::env::temp_dir().join(format!("atrmnl_state_{}.json", std::process::id()));
+        let missing: HashMap<String, u32> = load(Some(&path)).expect("Missing state is empty");
+        assert!(missing.is_empty());
+
+        save(Some(&path), &HashMap::from([("a", 1)])).expect("State is saved");
+        save(Some(&path), &HashMap::from([("b", 2)])).expect("State is saved");
+        let loaded: HashMap<String, u32> = load(Some(&path)).expect("State loads");
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let leftover = Path::new(&temporary).exists();
+        fs::write(&path, "{").expect("Failed to corrupt state");
+        let invalid = load::<HashMap<String, u32>>(Some(&path));
+        fs::remove_file(&path).expect("Failed to remove state file");
+
+        assert_eq!(loaded, HashMap::from([("b".to_owned(), 2)]));
+        assert!(!leftover);
+        assert!(matches!(invalid, Err(Error::Invalid { .. })));
+    }
+
+    #[test]
+    fn without_a_path_nothing_is_stored() {
+        save(None, &[1, 2, 3]).expect("Nothing to save");
+        assert_eq!(
+            load::<Vec<u32>>(None).expect("Nothing to load"),
+            Vec::<u32>::new()
+        );
+    }
+}
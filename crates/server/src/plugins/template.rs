@@ -0,0 +1,460 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, try_join_all};
+use log::{debug, error};
+use reqwest::header;
+use serde_json::{Map, Value, json};
+use serde_json_path::JsonPath;
+use url::Url;
+
+use crate::{
+    generator::{self, RenderContext, View},
+    net, storage,
+};
+
+pub mod archive;
+mod filters;
+
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    #[default]
+    Get,
+    Post,
+}
+
+impl From<Method> for reqwest::Method {
+    fn from(value: Method) -> Self {
+        match value {
+            Method::Get => Self::GET,
+            Method::Post => Self::POST,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Polling {
+    pub url: Url,
+    #[serde(default)]
+    pub method: Method,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    pub json_path: Option<JsonPath>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Markup {
+    pub full: PathBuf,
+    pub half: Option<PathBuf>,
+    pub quadrant: Option<PathBuf>,
+    pub shared: Option<PathBuf>,
+}
+
+impl Markup {
+    pub fn read(&self) -> Result<Sources, storage::LoadError> {
+        let read = |path: &PathBuf| {
+            fs::read_to_string(path).map_err(|source| storage::LoadError::Read {
+                path: path.clone(),
+                source,
+            })
+        };
+        Ok(Sources {
+            full: read(&self.full)?,
+            half: self.half.as_ref().map(read).transpose()?,
+            quadrant: self.quadrant.as_ref().map(read).transpose()?,
+            shared: self.shared.as_ref().map(read).transpose()?,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Sources {
+    pub full: String,
+    pub half: Option<String>,
+    pub quadrant: Option<String>,
+    pub shared: Option<String>,
+}
+
+pub struct Layouts {
+    full: liquid::Template,
+    half: Option<liquid::Template>,
+    quadrant: Option<liquid::Template>,
+}
+
+impl Layouts {
+    pub fn parse(sources: &Sources) -> Result<Self, liquid::Error> {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(filters::Qr)
+            .build()?;
+        let shared = sources.shared.as_deref().unwrap_or_default();
+        let parse = |markup: &str| parser.parse(&format!("{shared}{markup}"));
+        Ok(Self {
+            full: parse(&sources.full)?,
+            half: sources.half.as_deref().map(parse).transpose()?,
+            quadrant: sources.quadrant.as_deref().map(parse).transpose()?,
+        })
+    }
+
+    pub fn for_view(&self, view: View) -> &liquid::Template {
+        match view {
+            View::Full => &self.full,
+            View::HalfVertical => self.half.as_ref().unwrap_or(&self.full),
+            View::Quadrant => self
+                .quadrant
+                .as_ref()
+                .or(self.half.as_ref())
+                .unwrap_or(&self.full),
+        }
+    }
+}
+
+struct Source {
+    url: Url,
+    method: reqwest::Method,
+    headers: header::HeaderMap,
+    body: Option<String>,
+    json_path: Option<JsonPath>,
+}
+
+impl TryFrom<Polling> for Source {
+    type Error = storage::LoadError;
+
+    fn try_from(polling: Polling) -> Result<Self, Self::Error> {
+        let headers = header::HeaderMap::try_from(&polling.headers)
+            .inspect_err(|e| error!("Invalid polling headers for {}: {e}", polling.url))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            url: polling.url,
+            method: polling.method.into(),
+            headers,
+            body: polling.body,
+            json_path: polling.json_path,
+        })
+    }
+}
+
+pub struct TemplatePlugin {
+    client: reqwest::Client,
+    sources: Vec<Source>,
+    layouts: Layouts,
+    fields: Map<String, Value>,
+}
+
+impl TemplatePlugin {
+    pub fn new(
+        polling: Vec<Polling>,
+        markup: &Markup,
+        fields: Map<String, Value>,
+    ) -> Result<Self, storage::LoadError> {
+        Self::from_sources(polling, &markup.read()?, fields)
+    }
+
+    pub fn from_sources(
+        polling: Vec<Polling>,
+        sources: &Sources,
+        fields: Map<String, Value>,
+    ) -> Result<Self, storage::LoadError> {
+        let layouts = Layouts::parse(sources)
+            .inspect_err(|e| error!("Failed to parse liquid template: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(Self {
+            client: net::client().clone(),
+            sources: polling
+                .into_iter()
+                .map(Source::try_from)
+                .collect::<Result<_, _>>()?,
+            layouts,
+            fields,
+        })
+    }
+
+    async fn fetch(&self, source: &Source) -> Result<Value, generator::Error> {
+        debug!("Polling {} {}", source.method, source.url);
+        let mut request = self
+            .client
+            .request(source.method.clone(), source.url.clone())
+            .headers(source.headers.clone())
+            .timeout(Duration::from_secs(30));
+        if let Some(body) = &source.body {
+            request = request.body(body.clone());
+        }
+        let data: Value = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .inspect_err(|e| error!("Decoding polled data: {e}"))?;
+        Ok(match &source.json_path {
+            Some(path) => select(path, &data),
+            None => data,
+        })
+    }
+
+    fn render(
+        &self,
+        data: Vec<Value>,
+        ctx: &RenderContext,
+        now: DateTime<Utc>,
+    ) -> Result<String, generator::Error> {
+        let globals = liquid::to_object(&Value::Object(variables(data, &self.fields, ctx, now)))
+            .map_err(|e| generator::Error::Template(e.to_string()))?;
+        self.layouts
+            .for_view(ctx.view)
+            .render(&globals)
+            .map_err(|e| generator::Error::Template(e.to_string()))
+    }
+}
+
+impl generator::Content for TemplatePlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            let data = try_join_all(self.sources.iter().map(|s| self.fetch(s))).await?;
+            self.render(data, ctx, Utc::now())
+        })
+    }
+}
+
+fn select(path: &JsonPath, data: &Value) -> Value {
+    let nodes = path.query(data);
+    nodes.exactly_one().map_or_else(
+        |_| Value::Array(nodes.all().into_iter().cloned().collect()),
+        Clone::clone,
+    )
+}
+
+// Hosted TRMNL exposes the fields of a single response at the top level and
+// multiple responses as `IDX_n`, so both are provided next to `data`.
+pub fn variables(
+    data: Vec<Value>,
+    fields: &Map<String, Value>,
+    ctx: &RenderContext,
+    now: DateTime<Utc>,
+) -> Map<String, Value> {
+    let mut vars = Map::new();
+    let data = match <[Value; 1]>::try_from(data) {
+        Ok([single]) => {
+            if let Value::Object(fields) = &single {
+                vars.extend(fields.clone());
+            }
+            single
+        }
+        Err(many) => {
+            for (i, value) in many.iter().enumerate() {
+                vars.insert(format!("IDX_{i}"), value.clone());
+            }
+            Value::Array(many)
+        }
+    };
+    vars.insert("data".into(), data);
+    vars.insert(
+        "trmnl".into(),
+        json!({
+            "device": { "id": ctx.device, "view": ctx.view.as_str() },
+            "now": now.to_rfc3339(),
+            "plugin_settings": { "custom_fields_values": fields },
+        }),
+    );
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, routing::get};
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::{generator::Content, testing};
+
+    fn plugin(polling: Vec<Polling>, sources: &Sources) -> TemplatePlugin {
+        TemplatePlugin::from_sources(polling, sources, Map::new()).expect("Valid template plugin")
+    }
+
+    fn polling(url: Url) -> Polling {
+        Polling {
+            url,
+            method: Method::Get,
+            headers: HashMap::new(),
+            body: None,
+            json_path: None,
+        }
+    }
+
+    #[test]
+    fn select_single_match() {
+        let data = json!({ "current": { "temp": 21 } });
+        let path = JsonPath::parse("$.current.temp").expect("Valid JSON path");
+        assert_eq!(select(&path, &data), json!(21));
+    }
+
+    #[test]
+    fn select_many_matches() {
+        let data = json!({ "items": [{ "n": 1 }, { "n": 2 }] });
+        let path = JsonPath::parse("$.items[*].n").expect("Valid JSON path");
+        assert_eq!(select(&path, &data), json!([1, 2]));
+    }
+
+    #[test]
+    fn variables_single_source_merged_at_top_level() {
+        let ctx = RenderContext::new("kitchen");
+        let vars = variables(vec![json!({ "temp": 21 })], &Map::new(), &ctx, Utc::now());
+        assert_eq!(vars["temp"], json!(21));
+        assert_eq!(vars["data"], json!({ "temp": 21 }));
+        assert_eq!(vars["trmnl"]["device"]["id"], json!("kitchen"));
+        assert_eq!(vars["trmnl"]["device"]["view"], json!("full"));
+    }
+
+    #[test]
+    fn variables_expose_custom_fields() {
+        let ctx = RenderContext::new("kitchen");
+        let mut fields = Map::new();
+        fields.insert("city".into(), json!("Zurich"));
+        let vars = variables(vec![], &fields, &ctx, Utc::now());
+        assert_eq!(
+            vars["trmnl"]["plugin_settings"]["custom_fields_values"]["city"],
+            json!("Zurich")
+        );
+    }
+
+    #[test]
+    fn variables_multiple_sources_indexed() {
+        let ctx = RenderContext::new("kitchen");
+        let vars = variables(vec![json!(1), json!(2)], &Map::new(), &ctx, Utc::now());
+        assert_eq!(vars["data"], json!([1, 2]));
+        assert_eq!(vars["IDX_0"], json!(1));
+        assert_eq!(vars["IDX_1"], json!(2));
+    }
+
+    #[test]
+    fn layout_falls_back_to_full() {
+        let sources = Sources {
+            full: "full".into(),
+            shared: Some("shared ".into()),
+            ..Sources::default()
+        };
+        let plugin = plugin(vec![], &sources);
+        let ctx = RenderContext::new("d").with_view(View::Quadrant);
+        let html = plugin
+            .render(vec![], &ctx, Utc::now())
+            .expect("Template renders");
+        assert_eq!(html, "shared full");
+    }
+
+    #[test]
+    fn layout_quadrant_falls_back_to_half() {
+        let sources = Sources {
+            full: "full".into(),
+            half: Some("half".into()),
+            ..Sources::default()
+        };
+        let plugin = plugin(vec![], &sources);
+        let ctx = RenderContext::new("d").with_view(View::Quadrant);
+        let html = plugin
+            .render(vec![], &ctx, Utc::now())
+            .expect("Template renders");
+        assert_eq!(html, "half");
+    }
+
+    #[test]
+    fn qr_filter_renders_svg() {
+        let sources = Sources {
+            full: "{{ \"https://example.com\" | qr: 120 }}".into(),
+            ..Sources::default()
+        };
+        let plugin = plugin(vec![], &sources);
+        let html = plugin
+            .render(vec![], &RenderContext::new("d"), Utc::now())
+            .expect("Template renders");
+        assert!(html.starts_with("<svg"));
+        // A version 2 code of 33 modules with the quiet zone, at 3px each.
+        assert!(html.contains(r#"width="99""#));
+        let sources = Sources {
+            full: "{{ \"https://example.com\" | qr: 10 }}".into(),
+            ..Sources::default()
+        };
+        let plugin = self::plugin(vec![], &sources);
+        assert!(
+            plugin
+                .render(vec![], &RenderContext::new("d"), Utc::now())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_liquid_is_rejected() {
+        let sources = Sources {
+            full: "{% if %}".into(),
+            ..Sources::default()
+        };
+        assert!(TemplatePlugin::from_sources(vec![], &sources, Map::new()).is_err());
+    }
+
+    #[test]
+    fn invalid_header_is_rejected() {
+        let mut source = polling("http://localhost/".parse().expect("Valid URL"));
+        source.headers.insert("bad header".into(), "x".into());
+        assert!(
+            TemplatePlugin::from_sources(vec![source], &Sources::default(), Map::new()).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_polls_and_renders() {
+        let base = testing::mock_server(Router::new().route(
+            "/weather",
+            get(|headers: HeaderMap| async move {
+                let key = headers
+                    .get("x-api-key")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned();
+                Json(json!({ "current": { "temp": 21, "key": key } }))
+            }),
+        ))
+        .await;
+        let mut source = polling(base.join("weather").expect("Valid path"));
+        source.headers.insert("x-api-key".into(), "secret".into());
+        source.json_path = Some(JsonPath::parse("$.current").expect("Valid JSON path"));
+        let sources = Sources {
+            full: "{{ temp }}° {{ data.key }} on {{ trmnl.device.id }}".into(),
+            half: Some("{{ temp }}°".into()),
+            ..Sources::default()
+        };
+        let plugin = plugin(vec![source], &sources);
+
+        let ctx = RenderContext::new("kitchen");
+        let html = plugin.generate(&ctx).await.expect("Generation succeeds");
+        assert_eq!(html, "21° secret on kitchen");
+
+        let html = plugin
+            .generate(&ctx.with_view(View::HalfVertical))
+            .await
+            .expect("Generation succeeds");
+        assert_eq!(html, "21°");
+    }
+
+    #[tokio::test]
+    async fn generate_reports_upstream_status() {
+        let base = testing::mock_server(Router::new()).await;
+        let plugin = plugin(
+            vec![polling(base.join("missing").expect("Valid path"))],
+            &Sources::default(),
+        );
+        let err = plugin
+            .generate(&RenderContext::new("d"))
+            .await
+            .expect_err("Missing route fails");
+        assert!(matches!(
+            err,
+            generator::Error::Fetch {
+                kind: generator::FetchErrorKind::Request(http::StatusCode::NOT_FOUND),
+                ..
+            }
+        ));
+    }
+}
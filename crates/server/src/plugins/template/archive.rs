@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use log::warn;
+use serde_json::{Map, Value, json};
+
+use super::{Method, Polling, Sources, TemplatePlugin};
+use crate::storage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read the archive")]
+    Read(#[from] std::io::Error),
+    #[error("the archive is not a valid zip file")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("the archive does not contain {0}")]
+    Missing(&'static str),
+    #[error("settings.yml is invalid")]
+    Settings(#[from] serde_yaml_ng::Error),
+    #[error("the {0:?} strategy is not supported")]
+    UnsupportedStrategy(Strategy),
+    #[error("the custom field {0} requires a value")]
+    MissingField(String),
+    #[error("the polling settings are invalid: {0}")]
+    Polling(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    #[default]
+    Polling,
+    Webhook,
+    Static,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CustomField {
+    #[serde(default)]
+    pub keyname: String,
+    #[serde(default)]
+    pub field_type: String,
+    pub name: Option<String>,
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl CustomField {
+    fn is_input(&self) -> bool {
+        !self.keyname.is_empty() && self.field_type != "author_bio"
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Settings {
+    pub name: String,
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    polling_url: String,
+    #[serde(default)]
+    polling_verb: String,
+    #[serde(default)]
+    polling_headers: String,
+    #[serde(default)]
+    polling_body: String,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+}
+
+pub struct Archive {
+    pub settings: Settings,
+    sources: Sources,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::read(fs::File::open(path)?)
+    }
+
+    pub fn read(reader: impl Read + Seek) -> Result<Self, Error> {
+        const FILES: [&str; 6] = [
+            "settings.yml",
+            "full.liquid",
+            "half_vertical.liquid",
+            "half_horizontal.liquid",
+            "quadrant.liquid",
+            "shared.liquid",
+        ];
+
+        let mut zip = zip::ZipArchive::new(reader)?;
+        let mut files = HashMap::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            // Exports nest their files in a `src/` directory, which is ignored.
+            let name = file.name().rsplit('/').next().unwrap_or_default();
+            if let Some(&name) = FILES.iter().find(|&&n| n == name)
+                && file.is_file()
+            {
+                let mut contents = String::new();
+                file.read_to_string(&mut contents)?;
+                files.insert(name, contents);
+            }
+        }
+
+        let settings = serde_yaml_ng::from_str(
+            &files
+                .remove("settings.yml")
+                .ok_or(Error::Missing("settings.yml"))?,
+        )?;
+        let sources = Sources {
+            full: files
+                .remove("full.liquid")
+                .ok_or(Error::Missing("full.liquid"))?,
+            half: files
+                .remove("half_vertical.liquid")
+                .or_else(|| files.remove("half_horizontal.liquid")),
+            quadrant: files.remove("quadrant.liquid"),
+            shared: files.remove("shared.liquid"),
+        };
+        Ok(Self { settings, sources })
+    }
+
+    pub fn default_name(&self) -> String {
+        self.settings
+            .name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    pub fn fields(&self, provided: Map<String, Value>) -> Result<Map<String, Value>, Error> {
+        let mut fields = Map::new();
+        for field in self.settings.custom_fields.iter().filter(|f| f.is_input()) {
+            match (&field.default, field.optional) {
+                (Some(default), _) => {
+                    fields.insert(field.keyname.clone(), default.clone());
+                }
+                (None, false) if !provided.contains_key(&field.keyname) => {
+                    return Err(Error::MissingField(field.keyname.clone()));
+                }
+                (None, _) => {}
+            }
+        }
+        fields.extend(provided);
+        Ok(fields)
+    }
+
+    pub fn polling(&self, fields: &Map<String, Value>) -> Result<Vec<Polling>, Error> {
+        if self.settings.strategy != Strategy::Polling {
+            return Err(Error::UnsupportedStrategy(self.settings.strategy));
+        }
+        let method = match self.settings.polling_verb.to_ascii_lowercase().as_str() {
+            "" | "get" => Method::Get,
+            "post" => Method::Post,
+            other => return Err(Error::Polling(format!("unknown verb {other}"))),
+        };
+
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .build()
+            .map_err(|e| Error::Polling(e.to_string()))?;
+        let globals = liquid::to_object(fields).map_err(|e| Error::Polling(e.to_string()))?;
+        let interpolate = |text: &str| {
+            parser
+                .parse(text)
+                .and_then(|t| t.render(&globals))
+                .map_err(|e| Error::Polling(e.to_string()))
+        };
+
+        let headers = parse_headers(&interpolate(&self.settings.polling_headers)?)?;
+        let body = Some(interpolate(&self.settings.polling_body)?).filter(|b| !b.is_empty());
+        self.settings
+            .polling_url
+            .lines()
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                Ok(Polling {
+                    url: interpolate(url)?
+                        .parse()
+                        .map_err(|e: url::ParseError| Error::Polling(e.to_string()))?,
+                    method,
+                    headers: headers.clone(),
+                    body: body.clone(),
+                    json_path: None,
+                })
+            })
+            .collect()
+    }
+
+    pub fn into_plugin(
+        self,
+        provided: Map<String, Value>,
+    ) -> Result<TemplatePlugin, storage::LoadError> {
+        let fields = self.fields(provided)?;
+        let polling = self.polling(&fields)?;
+        TemplatePlugin::from_sources(polling, &self.sources, fields)
+    }
+
+    pub fn config_entry(&self, device: &str, name: &str, path: &Path) -> String {
+        let mut fields = Map::new();
+        for field in self.settings.custom_fields.iter().filter(|f| f.is_input()) {
+            if let Some(default) = &field.default {
+                fields.insert(field.keyname.clone(), default.clone());
+            } else if !field.optional {
+                warn!(
+                    "Custom field {} ({}) requires a value",
+                    field.keyname,
+                    field.name.as_deref().unwrap_or("unnamed")
+                );
+                fields.insert(field.keyname.clone(), Value::String(String::new()));
+            }
+        }
+        let config = json!({
+            device: {
+                "plugins": [{
+                    "trmnl_archive": {
+                        "name": name,
+                        "path": path,
+                        "fields": fields,
+                    }
+                }]
+            }
+        });
+        toml::to_string(&config).expect("JSON built from strings always serializes to TOML")
+    }
+}
+
+// Hosted TRMNL stores headers as `key=value&key=value`. Values are taken
+// verbatim, as tokens routinely contain characters a form decoder would mangle.
+fn parse_headers(raw: &str) -> Result<HashMap<String, String>, Error> {
+    raw.split('&')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+                .ok_or_else(|| Error::Polling(format!("malformed header {pair}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    const SETTINGS: &str = r"---
+strategy: polling
+polling_verb: get
+polling_url: |-
+  https://example.com/{{ city }}/now
+  https://example.com/{{ city }}/later
+polling_headers: authorization=bearer {{ api_key }}&accept=application/json
+name: Bin Collection Days
+custom_fields:
+- keyname: about
+  field_type: author_bio
+- keyname: api_key
+  field_type: string
+  name: API key
+- keyname: city
+  field_type: string
+  default: Zurich
+- keyname: units
+  field_type: string
+  optional: true
+";
+
+    fn zip(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .expect("Failed to start zip entry");
+            writer
+                .write_all(contents.as_bytes())
+                .expect("Failed to write zip entry");
+        }
+        let mut cursor = writer.finish().expect("Failed to finish zip");
+        cursor.set_position(0);
+        cursor
+    }
+
+    fn archive() -> Archive {
+        Archive::read(zip(&[
+            ("src/settings.yml", SETTINGS),
+            ("src/full.liquid", "full"),
+            ("src/half_horizontal.liquid", "half"),
+            ("src/shared.liquid", "shared"),
+        ]))
+        .expect("Valid archive")
+    }
+
+    fn api_key() -> Map<String, Value> {
+        let mut provided = Map::new();
+        provided.insert("api_key".into(), json!("secret"));
+        provided
+    }
+
+    #[test]
+    fn read_settings_and_layouts() {
+        let archive = archive();
+        assert_eq!(archive.settings.name, "Bin Collection Days");
+        assert_eq!(archive.settings.strategy, Strategy::Polling);
+        assert_eq!(archive.sources.full, "full");
+        assert_eq!(archive.sources.half.as_deref(), Some("half"));
+        assert_eq!(archive.sources.quadrant, None);
+        assert_eq!(archive.sources.shared.as_deref(), Some("shared"));
+    }
+
+    #[test]
+    fn read_requires_settings() {
+        let result = Archive::read(zip(&[("full.liquid", "full")]));
+        assert!(matches!(result, Err(Error::Missing("settings.yml"))));
+    }
+
+    #[test]
+    fn read_requires_full_layout() {
+        let result = Archive::read(zip(&[("settings.yml", SETTINGS)]));
+        assert!(matches!(result, Err(Error::Missing("full.liquid"))));
+    }
+
+    #[test]
+    fn default_name_is_slug() {
+        assert_eq!(archive().default_name(), "bin-collection-days");
+    }
+
+    #[test]
+    fn fields_merge_defaults() {
+        let fields = archive().fields(api_key()).expect("Fields are complete");
+        assert_eq!(fields["api_key"], json!("secret"));
+        assert_eq!(fields["city"], json!("Zurich"));
+        assert!(!fields.contains_key("units"));
+        assert!(!fields.contains_key("about"));
+    }
+
+    #[test]
+    fn fields_require_values() {
+        let result = archive().fields(Map::new());
+        assert!(matches!(result, Err(Error::MissingField(f)) if f == "api_key"));
+    }
+
+    #[test]
+    fn polling_interpolates_fields() {
+        let archive = archive();
+        let fields = archive.fields(api_key()).expect("Fields are complete");
+        let polling = archive.polling(&fields).expect("Valid polling settings");
+        assert_eq!(polling.len(), 2);
+        assert_eq!(polling[0].url.as_str(), "https://example.com/Zurich/now");
+        assert_eq!(polling[1].url.as_str(), "https://example.com/Zurich/later");
+        assert_eq!(polling[0].headers["authorization"], "bearer secret");
+        assert_eq!(polling[0].headers["accept"], "application/json");
+        assert!(polling[0].body.is_none());
+    }
+
+    #[test]
+    fn polling_rejects_webhooks() {
+        let archive = Archive::read(zip(&[
+            ("settings.yml", "name: Hook\nstrategy: webhook\n"),
+            ("full.liquid", "full"),
+        ]))
+        .expect("Valid archive");
+        assert!(matches!(
+            archive.polling(&Map::new()),
+            Err(Error::UnsupportedStrategy(Strategy::Webhook))
+        ));
+    }
+
+    #[test]
+    fn parse_headers_keeps_values_verbatim() {
+        let headers = parse_headers("authorization=Basic a+b==&x-empty=").expect("Valid headers");
+        assert_eq!(headers["authorization"], "Basic a+b==");
+        assert_eq!(headers["x-empty"], "");
+        assert!(parse_headers("nonsense").is_err());
+    }
+
+    #[test]
+    fn config_entry_is_valid_toml() {
+        let entry = archive().config_entry("kitchen", "bins", Path::new("/plugins/bins.zip"));
+        let table: toml::Table = toml::from_str(&entry).expect("Entry is valid TOML");
+        let plugin = &table["kitchen"]["plugins"][0]["trmnl_archive"];
+        assert_eq!(plugin["name"].as_str(), Some("bins"));
+        assert_eq!(plugin["path"].as_str(), Some("/plugins/bins.zip"));
+        assert_eq!(plugin["fields"]["city"].as_str(), Some("Zurich"));
+        assert_eq!(plugin["fields"]["api_key"].as_str(), Some(""));
+    }
+
+    #[test]
+    fn into_plugin_builds_template() {
+        assert!(archive().into_plugin(api_key()).is_ok());
+    }
+}
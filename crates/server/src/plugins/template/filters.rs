@@ -0,0 +1,50 @@
+use liquid_core::{
+    Display_filter, Error, Expression, Filter, FilterParameters, FilterReflection,
+    FromFilterParameters, ParseFilter, Result, Runtime, Value, ValueView,
+};
+
+use crate::qr;
+
+const DEFAULT_QR_SIZE: u32 = 200;
+
+#[derive(Debug, FilterParameters)]
+struct QrArgs {
+    #[parameter(
+        description = "The largest width and height of the code in pixels.",
+        arg_type = "integer"
+    )]
+    size: Option<Expression>,
+}
+
+// `{{ url | qr: 240 }}` renders the input as a QR code in inline SVG.
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "qr",
+    description = "Renders the input as a QR code in inline SVG.",
+    parameters(QrArgs),
+    parsed(QrFilter)
+)]
+pub struct Qr;
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "qr"]
+struct QrFilter {
+    #[parameters]
+    args: QrArgs,
+}
+
+impl Filter for QrFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &dyn Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let size = args
+            .size
+            .map_or(Ok(DEFAULT_QR_SIZE), u32::try_from)
+            .map_err(|_| {
+                Error::with_msg("invalid argument").context("size", "must be a positive number")
+            })?;
+        qr::Qr::new(input.to_kstr().as_str(), size)
+            .svg()
+            .map(Value::scalar)
+            .map_err(|e| Error::with_msg(e.to_string()))
+    }
+}
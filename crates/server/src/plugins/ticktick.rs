@@ -15,7 +15,7 @@ struct ContentTemplate<'a> {
     now: DateTime<Utc>,
 }
 
-fn format_relative(deadline: DateTime<Utc>, now: DateTime<Utc>) -> String {
+pub fn format_relative(deadline: DateTime<Utc>, now: DateTime<Utc>) -> String {
     use std::cmp::Ordering;
     let days = (deadline - now).num_days();
     match days.cmp(&0) {
@@ -292,6 +292,24 @@ pub struct Task {
     priority: Priority,
 }
 
+impl Task {
+    pub const fn new(
+        title: String,
+        content: String,
+        start_date: Option<DateTime<Utc>>,
+        due_date: Option<DateTime<Utc>>,
+        priority: Priority,
+    ) -> Self {
+        Self {
+            title,
+            content,
+            due_date,
+            start_date,
+            priority,
+        }
+    }
+}
+
 pub fn content(tasks: &[Task], now: DateTime<Utc>) -> String {
     ContentTemplate { tasks, now }
         .render_once()
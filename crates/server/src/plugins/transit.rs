@@ -0,0 +1,362 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::future::BoxFuture;
+use log::{error, warn};
+use sailfish::TemplateOnce;
+use url::Url;
+
+use crate::{
+    generator::{self, RenderContext},
+    net, storage,
+};
+
+pub mod gtfs;
+pub mod realtime;
+
+use realtime::{Estimate, Updates};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_REFRESH: Duration = Duration::from_mins(5);
+// How late a trip may run and still be listed.
+const MAX_DELAY: TimeDelta = TimeDelta::hours(1);
+const HORIZON: TimeDelta = TimeDelta::hours(12);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+pub enum StopConfig {
+    Stop(String),
+    Detailed {
+        stop: String,
+        label: Option<String>,
+        #[serde(default)]
+        lines: Vec<String>,
+        walk_mins: Option<u32>,
+    },
+}
+
+struct Board {
+    label: String,
+    lines: Vec<String>,
+    walk: TimeDelta,
+}
+
+struct Row {
+    line: String,
+    headsign: String,
+    time: String,
+    // Minutes behind the schedule.
+    delay: i64,
+    leave: String,
+    cancelled: bool,
+}
+
+struct BoardRows<'a> {
+    label: &'a str,
+    rows: Vec<Row>,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "transit.stpl")]
+struct TransitTemplate<'a> {
+    boards: &'a [BoardRows<'a>],
+}
+
+pub struct TransitPlugin {
+    feed: gtfs::Feed,
+    boards: Vec<Board>,
+    realtime: Option<Url>,
+    limit: Option<usize>,
+    refresh: Duration,
+}
+
+// "leave in 4 min" when the walk to the stop is known, "in 4 min" otherwise.
+fn leave(minutes: i64, walk: bool) -> String {
+    match (minutes, walk) {
+        (..=0, true) => "leave now".into(),
+        (..=0, false) => "now".into(),
+        (m, true) => format!("leave in {m} min"),
+        (m, false) => format!("in {m} min"),
+    }
+}
+
+impl TransitPlugin {
+    pub fn new(
+        feed: &Path,
+        stops: Vec<StopConfig>,
+        realtime: Option<Url>,
+        limit: Option<usize>,
+        refresh_mins: Option<u64>,
+    ) -> Result<Self, storage::LoadError> {
+        if stops.is_empty() {
+            error!("A transit plugin needs at least one stop");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        File::open(feed)
+            .map_err(gtfs::Error::from)
+            .and_then(|file| Self::read(BufReader::new(file), stops, realtime, limit, refresh_mins))
+            .inspect_err(|e| error!("Loading the GTFS feed {}: {e}", feed.display()))
+            .map_err(|_| storage::LoadError::InvalidConfig)
+    }
+
+    pub fn read(
+        feed: impl Read + Seek,
+        stops: Vec<StopConfig>,
+        realtime: Option<Url>,
+        limit: Option<usize>,
+        refresh_mins: Option<u64>,
+    ) -> Result<Self, gtfs::Error> {
+        let (queries, boards): (Vec<_>, Vec<_>) = stops
+            .into_iter()
+            .map(|stop| match stop {
+                StopConfig::Stop(stop) => (
+                    stop.clone(),
+                    Board {
+                        label: stop,
+                        lines: Vec::new(),
+                        walk: TimeDelta::zero(),
+                    },
+                ),
+                StopConfig::Detailed {
+                    stop,
+                    label,
+                    lines,
+                    walk_mins,
+                } => (
+                    stop.clone(),
+                    Board {
+                        label: label.unwrap_or(stop),
+                        lines,
+                        walk: TimeDelta::minutes(walk_mins.map_or(0, i64::from)),
+                    },
+                ),
+            })
+            .unzip();
+        let queries: Vec<_> = queries.iter().map(String::as_str).collect();
+        Ok(Self {
+            feed: gtfs::Feed::read(feed, &queries)?,
+            boards,
+            realtime,
+            limit,
+            refresh: refresh_mins.map_or(DEFAULT_REFRESH, Duration::from_mins),
+        })
+    }
+
+    async fn updates(&self) -> Result<Option<Updates>, generator::Error> {
+        let Some(url) = &self.realtime else {
+            return Ok(None);
+        };
+        let bytes = net::retry(
+            || async {
+                net::client()
+                    .get(url.clone())
+                    .timeout(FETCH_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+            },
+            RETRY_BACKOFF,
+            RETRY_TIMEOUT,
+        )
+        .await?;
+        Updates::decode(&bytes)
+            .map(Some)
+            .map_err(|_| generator::Error::Fetch {
+                kind: generator::FetchErrorKind::InvalidData,
+                target: url.to_string(),
+            })
+    }
+
+    fn rows(
+        &self,
+        index: usize,
+        board: &Board,
+        now: DateTime<Utc>,
+        updates: Option<&Updates>,
+        limit: usize,
+    ) -> Vec<Row> {
+        let tz = self.feed.timezone;
+        let mut rows: Vec<_> = self
+            .feed
+            .departures(index, now - MAX_DELAY, now + HORIZON)
+            .into_iter()
+            .filter(|d| board.lines.is_empty() || board.lines.iter().any(|l| l == d.line))
+            .filter_map(|d| {
+                let estimate = updates.map_or(Estimate::Scheduled, |u| {
+                    u.estimate(d.trip_id, d.stop_id, d.sequence)
+                });
+                let expected = match estimate {
+                    Estimate::Skipped => return None,
+                    Estimate::Scheduled | Estimate::Cancelled => d.time,
+                    Estimate::Delayed(delay) => d.time + delay,
+                    Estimate::At(time) => time,
+                };
+                let leave_at = expected - board.walk;
+                (leave_at >= now - TimeDelta::seconds(30)).then(|| {
+                    (
+                        expected,
+                        Row {
+                            line: d.line.to_owned(),
+                            headsign: d.headsign.to_owned(),
+                            time: d.time.with_timezone(&tz).format("%H:%M").to_string(),
+                            delay: (expected - d.time).num_minutes(),
+                            leave: leave((leave_at - now).num_minutes(), !board.walk.is_zero()),
+                            cancelled: estimate == Estimate::Cancelled,
+                        },
+                    )
+                })
+            })
+            .collect();
+        rows.sort_by_key(|(expected, _)| *expected);
+        rows.into_iter().take(limit).map(|(_, row)| row).collect()
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>, updates: Option<&Updates>) -> String {
+        let fitted = ctx.view.rows(36).saturating_sub(self.boards.len()) / self.boards.len();
+        let limit = self.limit.unwrap_or(fitted).max(1);
+        let boards: Vec<_> = self
+            .boards
+            .iter()
+            .enumerate()
+            .map(|(i, board)| BoardRows {
+                label: &board.label,
+                rows: self.rows(i, board, now, updates, limit),
+            })
+            .collect();
+        TransitTemplate { boards: &boards }
+            .render_once()
+            .expect("transit template render failed")
+    }
+}
+
+impl generator::Content for TransitPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move {
+            // Without live data the board falls back to the timetable.
+            let updates = self
+                .updates()
+                .await
+                .inspect_err(|e| warn!("Fetching GTFS-Realtime updates: {e}"))
+                .ok()
+                .flatten();
+            Ok(self.render(ctx, Utc::now(), updates.as_ref()))
+        })
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Some(self.refresh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        gtfs::tests::{fixture, zurich},
+        realtime::tests::{encode, stop, trip},
+        *,
+    };
+
+    fn plugin(stops: Vec<StopConfig>) -> TransitPlugin {
+        TransitPlugin::read(fixture(), stops, None, None, None).expect("Fixture feed loads")
+    }
+
+    fn walk(stop: &str, lines: &[&str], walk_mins: u32) -> StopConfig {
+        StopConfig::Detailed {
+            stop: stop.into(),
+            label: None,
+            lines: lines.iter().map(|&l| l.into()).collect(),
+            walk_mins: Some(walk_mins),
+        }
+    }
+
+    fn leaves(
+        plugin: &TransitPlugin,
+        now: DateTime<Utc>,
+        updates: Option<&Updates>,
+    ) -> Vec<String> {
+        plugin
+            .rows(0, &plugin.boards[0], now, updates, 10)
+            .into_iter()
+            .map(|row| format!("{} {} {}", row.line, row.time, row.leave))
+            .collect()
+    }
+
+    #[test]
+    fn leave_reads_naturally() {
+        assert_eq!(leave(0, false), "now");
+        assert_eq!(leave(-1, true), "leave now");
+        assert_eq!(leave(4, true), "leave in 4 min");
+        assert_eq!(leave(12, false), "in 12 min");
+    }
+
+    #[test]
+    fn rows_account_for_the_walk() {
+        let plugin = plugin(vec![walk("Central", &[], 4)]);
+        assert_eq!(
+            leaves(&plugin, zurich(6, 7, 57), None),
+            ["13 08:12 leave in 11 min", "4 08:20 leave in 19 min"]
+        );
+        let plugin = self::plugin(vec![walk("Central", &["4"], 0)]);
+        assert_eq!(
+            leaves(&plugin, zurich(6, 7, 57), None),
+            ["4 08:00 in 3 min", "4 08:20 in 23 min"]
+        );
+    }
+
+    #[test]
+    fn rows_merge_realtime_updates() {
+        let plugin = plugin(vec![StopConfig::Stop("Central".into())]);
+        let updates = Updates::decode(&encode(vec![
+            trip("t4a", false, vec![stop(1, Some(180), false)]),
+            trip("t13a", true, vec![]),
+            trip("t4b", false, vec![stop(1, None, true)]),
+        ]))
+        .expect("Feed decodes");
+        let rows = plugin.rows(0, &plugin.boards[0], zurich(6, 8, 1), Some(&updates), 10);
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].time.as_str(), rows[0].delay), ("08:00", 3));
+        assert_eq!(rows[0].leave, "in 2 min");
+        assert!(rows[1].cancelled);
+    }
+
+    #[test]
+    fn render_lists_each_board() {
+        let plugin = plugin(vec![
+            StopConfig::Stop("Central".into()),
+            walk("Zoo", &[], 2),
+        ]);
+        let html = plugin.render(&RenderContext::new("d"), zurich(6, 7, 50), None);
+        assert!(html.contains("Central"));
+        assert!(html.contains("Zoo"));
+        assert!(html.contains("08:12"));
+        assert!(html.contains("in 10 min"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        let feed = Path::new("/nonexistent/gtfs.zip");
+        assert!(TransitPlugin::new(feed, vec![], None, None, None).is_err());
+        assert!(
+            TransitPlugin::new(
+                feed,
+                vec![StopConfig::Stop("Central".into())],
+                None,
+                None,
+                None
+            )
+            .is_err()
+        );
+    }
+}
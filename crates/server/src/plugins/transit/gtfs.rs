@@ -0,0 +1,546 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Seek},
+};
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, de::DeserializeOwned};
+use zip::ZipArchive;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to open the GTFS feed")]
+    Io(#[from] std::io::Error),
+    #[error("failed to read the GTFS feed")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("the GTFS feed lacks {0}")]
+    Missing(&'static str),
+    #[error("{file} is not valid CSV")]
+    Csv {
+        file: &'static str,
+        #[source]
+        source: csv::Error,
+    },
+    #[error("{file} holds the invalid value {value:?}")]
+    Invalid { file: &'static str, value: String },
+    #[error("no stop is called or has the id {0:?}")]
+    UnknownStop(String),
+}
+
+#[derive(Deserialize)]
+struct AgencyRow {
+    agency_timezone: String,
+}
+
+#[derive(Deserialize)]
+struct StopRow {
+    stop_id: String,
+    #[serde(default)]
+    stop_name: String,
+    #[serde(default)]
+    parent_station: String,
+}
+
+#[derive(Deserialize)]
+struct RouteRow {
+    #[serde(rename = "route_id")]
+    id: String,
+    #[serde(default, rename = "route_short_name")]
+    short_name: String,
+    #[serde(default, rename = "route_long_name")]
+    long_name: String,
+}
+
+#[derive(Deserialize)]
+struct TripRow {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    #[serde(default)]
+    trip_headsign: String,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRow {
+    trip_id: String,
+    #[serde(default)]
+    arrival_time: String,
+    #[serde(default)]
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+    pickup_type: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct CalendarRow {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRow {
+    service_id: String,
+    date: String,
+    exception_type: u8,
+}
+
+// Calls `f` with every row of `file`. Returns false if the feed lacks it.
+fn each<R: Read + Seek, T: DeserializeOwned>(
+    zip: &mut ZipArchive<R>,
+    file: &'static str,
+    mut f: impl FnMut(T) -> Result<(), Error>,
+) -> Result<bool, Error> {
+    let reader = match zip.by_name(file) {
+        Ok(reader) => reader,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    for row in csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_deserialize()
+    {
+        f(row.map_err(|source| Error::Csv { file, source })?)?;
+    }
+    Ok(true)
+}
+
+fn date(file: &'static str, value: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| Error::Invalid {
+        file,
+        value: value.to_owned(),
+    })
+}
+
+// Seconds since the start of the service day. Trips running past midnight
+// use hours beyond 24.
+fn seconds(value: &str) -> Option<u32> {
+    let mut parts = value.split(':').map(str::parse::<u32>);
+    let (Some(Ok(h)), Some(Ok(m)), Some(Ok(s)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    (m < 60 && s < 60).then_some(h * 3600 + m * 60 + s)
+}
+
+struct StopTime {
+    trip_id: String,
+    stop_id: String,
+    sequence: u32,
+    departure: u32,
+}
+
+struct Trip {
+    route_id: String,
+    service_id: String,
+    headsign: String,
+}
+
+struct Service {
+    // Monday first.
+    days: [bool; 7],
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+type Calendar = (
+    HashMap<String, Service>,
+    HashMap<String, HashMap<NaiveDate, bool>>,
+);
+
+fn read_calendar<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<Calendar, Error> {
+    let mut services = HashMap::new();
+    let has_calendar = each(zip, "calendar.txt", |row: CalendarRow| {
+        let days = [
+            row.monday,
+            row.tuesday,
+            row.wednesday,
+            row.thursday,
+            row.friday,
+            row.saturday,
+            row.sunday,
+        ]
+        .map(|day| day == 1);
+        services.insert(
+            row.service_id,
+            Service {
+                days,
+                start: date("calendar.txt", &row.start_date)?,
+                end: date("calendar.txt", &row.end_date)?,
+            },
+        );
+        Ok(())
+    })?;
+    let mut exceptions: HashMap<String, HashMap<NaiveDate, bool>> = HashMap::new();
+    let has_dates = each(zip, "calendar_dates.txt", |row: CalendarDateRow| {
+        exceptions.entry(row.service_id).or_default().insert(
+            date("calendar_dates.txt", &row.date)?,
+            row.exception_type == 1,
+        );
+        Ok(())
+    })?;
+    if !has_calendar && !has_dates {
+        return Err(Error::Missing("calendar.txt"));
+    }
+    Ok((services, exceptions))
+}
+
+#[derive(Debug)]
+pub struct Departure<'a> {
+    pub trip_id: &'a str,
+    pub stop_id: &'a str,
+    pub sequence: u32,
+    pub line: &'a str,
+    pub headsign: &'a str,
+    pub time: DateTime<Utc>,
+}
+
+// The parts of a static GTFS feed needed to list departures at a few stops.
+// Only the stop times of those stops are kept in memory.
+pub struct Feed {
+    pub timezone: Tz,
+    // Stop times per requested stop, in the order the stops were requested.
+    stop_times: Vec<Vec<StopTime>>,
+    trips: HashMap<String, Trip>,
+    lines: HashMap<String, String>,
+    services: HashMap<String, Service>,
+    // Dates a service runs (`true`) or does not run in addition to its
+    // calendar.
+    exceptions: HashMap<String, HashMap<NaiveDate, bool>>,
+}
+
+// Maps a stop id or name to the ids of all stops it covers, including the
+// platforms of a station.
+struct StopIndex {
+    by_name: HashMap<String, Vec<String>>,
+    children: HashMap<String, Vec<String>>,
+    ids: HashSet<String>,
+}
+
+impl StopIndex {
+    fn read<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<Self, Error> {
+        let mut index = Self {
+            by_name: HashMap::new(),
+            children: HashMap::new(),
+            ids: HashSet::new(),
+        };
+        let found = each(zip, "stops.txt", |row: StopRow| {
+            if !row.parent_station.is_empty() {
+                index
+                    .children
+                    .entry(row.parent_station)
+                    .or_default()
+                    .push(row.stop_id.clone());
+            }
+            index
+                .by_name
+                .entry(row.stop_name.to_lowercase())
+                .or_default()
+                .push(row.stop_id.clone());
+            index.ids.insert(row.stop_id);
+            Ok(())
+        })?;
+        if found {
+            Ok(index)
+        } else {
+            Err(Error::Missing("stops.txt"))
+        }
+    }
+
+    fn resolve_all(&self, queries: &[&str]) -> Result<Vec<HashSet<String>>, Error> {
+        queries.iter().map(|query| self.resolve(query)).collect()
+    }
+
+    fn resolve(&self, query: &str) -> Result<HashSet<String>, Error> {
+        let roots = if self.ids.contains(query) {
+            vec![query.to_owned()]
+        } else {
+            self.by_name
+                .get(&query.to_lowercase())
+                .cloned()
+                .ok_or_else(|| Error::UnknownStop(query.to_owned()))?
+        };
+        let mut ids = HashSet::new();
+        let mut pending = roots;
+        while let Some(id) = pending.pop() {
+            if let Some(children) = self.children.get(&id) {
+                pending.extend(children.iter().cloned());
+            }
+            ids.insert(id);
+        }
+        Ok(ids)
+    }
+}
+
+impl Feed {
+    // Reads a GTFS zip, keeping the stop times of the stops named or
+    // identified by `stops`.
+    pub fn read(reader: impl Read + Seek, stops: &[&str]) -> Result<Self, Error> {
+        let mut zip = ZipArchive::new(reader)?;
+
+        let mut timezone = None;
+        each(&mut zip, "agency.txt", |row: AgencyRow| {
+            timezone.get_or_insert(row.agency_timezone);
+            Ok(())
+        })?;
+        let timezone = timezone.ok_or(Error::Missing("agency.txt"))?;
+        let timezone = timezone.parse().map_err(|_| Error::Invalid {
+            file: "agency.txt",
+            value: timezone,
+        })?;
+
+        let resolved = StopIndex::read(&mut zip)?.resolve_all(stops)?;
+
+        let mut stop_times: Vec<Vec<StopTime>> = resolved.iter().map(|_| Vec::new()).collect();
+        let mut trip_ids = HashSet::new();
+        let found = each(&mut zip, "stop_times.txt", |row: StopTimeRow| {
+            // Nobody can board here, as at the end of a line.
+            if row.pickup_type == Some(1) {
+                return Ok(());
+            }
+            let matching: Vec<_> = resolved
+                .iter()
+                .enumerate()
+                .filter(|(_, ids)| ids.contains(&row.stop_id))
+                .map(|(i, _)| i)
+                .collect();
+            if matching.is_empty() {
+                return Ok(());
+            }
+            let time = if row.departure_time.is_empty() {
+                &row.arrival_time
+            } else {
+                &row.departure_time
+            };
+            // Stops between timepoints may leave their time empty.
+            if time.is_empty() {
+                return Ok(());
+            }
+            let departure = seconds(time).ok_or_else(|| Error::Invalid {
+                file: "stop_times.txt",
+                value: time.clone(),
+            })?;
+            trip_ids.insert(row.trip_id.clone());
+            for i in matching {
+                stop_times[i].push(StopTime {
+                    trip_id: row.trip_id.clone(),
+                    stop_id: row.stop_id.clone(),
+                    sequence: row.stop_sequence,
+                    departure,
+                });
+            }
+            Ok(())
+        })?;
+        if !found {
+            return Err(Error::Missing("stop_times.txt"));
+        }
+
+        let mut trips = HashMap::new();
+        each(&mut zip, "trips.txt", |row: TripRow| {
+            if trip_ids.contains(&row.trip_id) {
+                trips.insert(
+                    row.trip_id,
+                    Trip {
+                        route_id: row.route_id,
+                        service_id: row.service_id,
+                        headsign: row.trip_headsign,
+                    },
+                );
+            }
+            Ok(())
+        })?;
+
+        let mut lines = HashMap::new();
+        each(&mut zip, "routes.txt", |row: RouteRow| {
+            let line = if row.short_name.is_empty() {
+                row.long_name
+            } else {
+                row.short_name
+            };
+            lines.insert(row.id, line);
+            Ok(())
+        })?;
+
+        let (services, exceptions) = read_calendar(&mut zip)?;
+
+        Ok(Self {
+            timezone,
+            stop_times,
+            trips,
+            lines,
+            services,
+            exceptions,
+        })
+    }
+
+    fn runs(&self, service_id: &str, date: NaiveDate) -> bool {
+        if let Some(&runs) = self.exceptions.get(service_id).and_then(|d| d.get(&date)) {
+            return runs;
+        }
+        self.services.get(service_id).is_some_and(|service| {
+            service.days[date.weekday().num_days_from_monday() as usize]
+                && (service.start..=service.end).contains(&date)
+        })
+    }
+
+    // Times are counted from noon minus 12 hours, which is midnight except
+    // on days the clocks change.
+    fn service_start(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        date.and_hms_opt(12, 0, 0)?
+            .and_local_timezone(self.timezone)
+            .earliest()
+            .map(|noon| noon.to_utc() - TimeDelta::hours(12))
+    }
+
+    // Scheduled departures at the `stop`-th requested stop between `from`
+    // and `until`, earliest first.
+    pub fn departures(
+        &self,
+        stop: usize,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<Departure<'_>> {
+        let Some(stop_times) = self.stop_times.get(stop) else {
+            return Vec::new();
+        };
+        let first = from.with_timezone(&self.timezone).date_naive() - Days::new(1);
+        let last = until.with_timezone(&self.timezone).date_naive();
+        let mut departures = Vec::new();
+        for date in first.iter_days().take_while(|d| *d <= last) {
+            let Some(start) = self.service_start(date) else {
+                continue;
+            };
+            for stop_time in stop_times {
+                let time = start + TimeDelta::seconds(i64::from(stop_time.departure));
+                if time < from || time > until {
+                    continue;
+                }
+                let Some(trip) = self.trips.get(&stop_time.trip_id) else {
+                    continue;
+                };
+                if !self.runs(&trip.service_id, date) {
+                    continue;
+                }
+                departures.push(Departure {
+                    trip_id: &stop_time.trip_id,
+                    stop_id: &stop_time.stop_id,
+                    sequence: stop_time.sequence,
+                    line: self.lines.get(&trip.route_id).map_or("", String::as_str),
+                    headsign: &trip.headsign,
+                    time,
+                });
+            }
+        }
+        departures.sort_by_key(|d| d.time);
+        departures
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Write},
+        path::PathBuf,
+    };
+
+    use chrono::TimeZone;
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    // The trimmed feed in `tests/transit`, zipped.
+    pub fn fixture() -> Cursor<Vec<u8>> {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "transit"]
+            .iter()
+            .collect();
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for entry in fs::read_dir(dir).expect("Fixture directory exists") {
+            let path = entry.expect("Fixture entry is readable").path();
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .expect("Failed to start zip entry");
+            writer
+                .write_all(&fs::read(&path).expect("Fixture is readable"))
+                .expect("Failed to write zip entry");
+        }
+        let mut cursor = writer.finish().expect("Failed to finish zip");
+        cursor.set_position(0);
+        cursor
+    }
+
+    pub fn zurich(day: u32, h: u32, m: u32) -> DateTime<Utc> {
+        Tz::Europe__Zurich
+            .with_ymd_and_hms(2024, 5, day, h, m, 0)
+            .single()
+            .expect("Valid time")
+            .to_utc()
+    }
+
+    fn trips(feed: &Feed, stop: usize, from: DateTime<Utc>) -> Vec<&str> {
+        feed.departures(stop, from, from + TimeDelta::hours(1))
+            .iter()
+            .map(|d| d.trip_id)
+            .collect()
+    }
+
+    #[test]
+    fn seconds_allow_times_past_midnight() {
+        assert_eq!(seconds("08:05:30"), Some(29130));
+        assert_eq!(seconds("7:00:00"), Some(25200));
+        assert_eq!(seconds("25:10:00"), Some(90600));
+        assert_eq!(seconds("08:65:00"), None);
+        assert_eq!(seconds("08:00"), None);
+    }
+
+    #[test]
+    fn stops_resolve_by_name_or_id() {
+        let feed = Feed::read(fixture(), &["central", "Central", "central:2"])
+            .expect("Fixture feed loads");
+        assert_eq!(feed.timezone, Tz::Europe__Zurich);
+        // The station covers both of its platforms.
+        let from = zurich(6, 7, 55);
+        assert_eq!(trips(&feed, 0, from), ["t4a", "t13a", "t4b"]);
+        assert_eq!(trips(&feed, 1, from), ["t4a", "t13a", "t4b"]);
+        assert_eq!(trips(&feed, 2, from), ["t13a"]);
+        assert!(matches!(
+            Feed::read(fixture(), &["Nowhere"]),
+            Err(Error::UnknownStop(_))
+        ));
+    }
+
+    #[test]
+    fn departures_follow_the_calendar() {
+        let feed = Feed::read(fixture(), &["Central"]).expect("Fixture feed loads");
+        // A Saturday and a public holiday on a Wednesday run the weekend service.
+        assert_eq!(trips(&feed, 0, zurich(4, 7, 55)), ["t4w"]);
+        assert_eq!(trips(&feed, 0, zurich(1, 7, 55)), ["t4w"]);
+        // Monday's late trip leaves after midnight.
+        assert_eq!(trips(&feed, 0, zurich(7, 0, 5)), ["t4late"]);
+        let departure = &feed.departures(0, zurich(6, 7, 55), zurich(6, 8, 0))[0];
+        assert_eq!(departure.line, "4");
+        assert_eq!(departure.headsign, "Zoo");
+        assert_eq!(departure.time, zurich(6, 8, 0));
+    }
+
+    #[test]
+    fn terminus_has_no_departures() {
+        let feed = Feed::read(fixture(), &["Park"]).expect("Fixture feed loads");
+        assert!(trips(&feed, 0, zurich(6, 8, 0)).is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use prost::Message;
+
+// The subset of the GTFS-Realtime schema needed for trip updates. Other
+// fields are skipped while decoding.
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct FeedMessage {
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<StopTimeUpdate>,
+    #[prost(int32, optional, tag = "5")]
+    pub delay: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    #[prost(uint32, optional, tag = "4")]
+    pub schedule_relationship: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    pub stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub arrival: Option<StopTimeEvent>,
+    #[prost(message, optional, tag = "3")]
+    pub departure: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    pub stop_id: Option<String>,
+    #[prost(uint32, optional, tag = "5")]
+    pub schedule_relationship: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct StopTimeEvent {
+    #[prost(int32, optional, tag = "1")]
+    pub delay: Option<i32>,
+    #[prost(int64, optional, tag = "2")]
+    pub time: Option<i64>,
+}
+
+const TRIP_CANCELED: u32 = 3;
+const STOP_SKIPPED: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Estimate {
+    Scheduled,
+    Delayed(TimeDelta),
+    At(DateTime<Utc>),
+    Skipped,
+    Cancelled,
+}
+
+impl StopTimeUpdate {
+    fn estimate(&self, exact: bool) -> Option<Estimate> {
+        if self.schedule_relationship == Some(STOP_SKIPPED) {
+            // Skipping a stop says nothing about the stops after it.
+            return exact.then_some(Estimate::Skipped);
+        }
+        let event = self.departure.as_ref().or(self.arrival.as_ref())?;
+        if exact && let Some(time) = event.time.and_then(|t| DateTime::from_timestamp(t, 0)) {
+            return Some(Estimate::At(time));
+        }
+        event
+            .delay
+            .map(|delay| Estimate::Delayed(TimeDelta::seconds(i64::from(delay))))
+    }
+}
+
+// Trip updates by trip id.
+pub struct Updates(HashMap<String, TripUpdate>);
+
+impl Updates {
+    pub fn decode(bytes: &[u8]) -> Result<Self, prost::DecodeError> {
+        let feed = FeedMessage::decode(bytes)?;
+        Ok(Self(
+            feed.entity
+                .into_iter()
+                .filter_map(|entity| entity.trip_update)
+                .filter_map(|update| Some((update.trip.trip_id.clone()?, update)))
+                .collect(),
+        ))
+    }
+
+    // How the departure of `trip_id` at the stop deviates from the schedule.
+    // Without an update for the stop itself, the delay of the last earlier
+    // stop carries over, as GTFS-Realtime prescribes.
+    pub fn estimate(&self, trip_id: &str, stop_id: &str, sequence: u32) -> Estimate {
+        let Some(update) = self.0.get(trip_id) else {
+            return Estimate::Scheduled;
+        };
+        if update.trip.schedule_relationship == Some(TRIP_CANCELED) {
+            return Estimate::Cancelled;
+        }
+        let exact = update.stop_time_update.iter().find(|u| {
+            u.stop_sequence
+                .map_or_else(|| u.stop_id.as_deref() == Some(stop_id), |s| s == sequence)
+        });
+        if let Some(estimate) = exact.and_then(|u| u.estimate(true)) {
+            return estimate;
+        }
+        update
+            .stop_time_update
+            .iter()
+            .filter(|u| u.stop_sequence.is_some_and(|s| s < sequence))
+            .max_by_key(|u| u.stop_sequence)
+            .and_then(|u| u.estimate(false))
+            .or_else(|| {
+                update
+                    .delay
+                    .map(|delay| Estimate::Delayed(TimeDelta::seconds(i64::from(delay))))
+            })
+            .unwrap_or(Estimate::Scheduled)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn stop(sequence: u32, delay: Option<i32>, skipped: bool) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_sequence: Some(sequence),
+            departure: delay.map(|delay| StopTimeEvent {
+                delay: Some(delay),
+                time: None,
+            }),
+            schedule_relationship: skipped.then_some(STOP_SKIPPED),
+            ..StopTimeUpdate::default()
+        }
+    }
+
+    pub fn trip(
+        trip_id: &str,
+        cancelled: bool,
+        stop_time_update: Vec<StopTimeUpdate>,
+    ) -> FeedEntity {
+        FeedEntity {
+            id: trip_id.into(),
+            trip_update: Some(TripUpdate {
+                trip: TripDescriptor {
+                    trip_id: Some(trip_id.into()),
+                    schedule_relationship: cancelled.then_some(TRIP_CANCELED),
+                },
+                stop_time_update,
+                delay: None,
+            }),
+        }
+    }
+
+    pub fn encode(entity: Vec<FeedEntity>) -> Vec<u8> {
+        FeedMessage { entity }.encode_to_vec()
+    }
+
+    #[test]
+    fn estimate_reads_trip_updates() {
+        let updates = Updates::decode(&encode(vec![
+            trip("a", false, vec![stop(1, Some(120), false)]),
+            trip("b", true, vec![]),
+            trip("c", false, vec![stop(2, None, true)]),
+        ]))
+        .expect("Feed decodes");
+        let minutes = |m| Estimate::Delayed(TimeDelta::minutes(m));
+        assert_eq!(updates.estimate("a", "x", 1), minutes(2));
+        // Delays carry over to later stops.
+        assert_eq!(updates.estimate("a", "y", 3), minutes(2));
+        assert_eq!(updates.estimate("b", "x", 1), Estimate::Cancelled);
+        assert_eq!(updates.estimate("c", "x", 2), Estimate::Skipped);
+        assert_eq!(updates.estimate("c", "x", 3), Estimate::Scheduled);
+        assert_eq!(updates.estimate("d", "x", 1), Estimate::Scheduled);
+    }
+
+    #[test]
+    fn estimate_prefers_absolute_times() {
+        let mut update = stop(1, Some(60), false);
+        update.stop_sequence = None;
+        update.stop_id = Some("x".into());
+        update.departure = Some(StopTimeEvent {
+            delay: Some(60),
+            time: Some(1_714_975_320),
+        });
+        let updates =
+            Updates::decode(&encode(vec![trip("a", false, vec![update])])).expect("Feed decodes");
+        assert_eq!(
+            updates.estimate("a", "x", 4),
+            Estimate::At(DateTime::from_timestamp(1_714_975_320, 0).expect("Valid time"))
+        );
+        assert_eq!(updates.estimate("a", "y", 4), Estimate::Scheduled);
+        assert!(Updates::decode(b"\xff\xff").is_err());
+    }
+}
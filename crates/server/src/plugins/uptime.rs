@@ -0,0 +1,440 @@
+use std::{collections::VecDeque, fmt::Write, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::future::{BoxFuture, join_all};
+use log::{error, info, warn};
+use sailfish::TemplateOnce;
+use tokio::net::TcpStream;
+use url::Url;
+
+use super::format;
+use crate::{
+    generator::{self, RenderContext, View},
+    net, storage,
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_mins(1);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_HISTORY: usize = 60;
+// Size of one sample in the history strip.
+const TICK_WIDTH: usize = 4;
+const TICK_HEIGHT: usize = 12;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TargetConfig {
+    pub name: Option<String>,
+    // `host:port` to open a TCP connection to.
+    pub tcp: Option<String>,
+    pub http: Option<Url>,
+    // Status the HTTP check expects, any success status otherwise.
+    pub expect: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Check {
+    Tcp(String),
+    Http { url: Url, expect: Option<u16> },
+}
+
+struct Target {
+    name: String,
+    check: Check,
+}
+
+impl TryFrom<TargetConfig> for Target {
+    type Error = String;
+
+    fn try_from(config: TargetConfig) -> Result<Self, Self::Error> {
+        let (check, fallback) = match (config.tcp, config.http) {
+            (Some(addr), None) => (Check::Tcp(addr.clone()), addr),
+            (None, Some(url)) => (
+                Check::Http {
+                    expect: config.expect,
+                    url: url.clone(),
+                },
+                url.host_str().unwrap_or(url.as_str()).to_owned(),
+            ),
+            _ => return Err(config.name.unwrap_or_default()),
+        };
+        Ok(Self {
+            name: config.name.unwrap_or(fallback),
+            check,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sample {
+    time: DateTime<Utc>,
+    // How long the check took, or why it failed.
+    result: Result<Duration, String>,
+}
+
+#[derive(Debug, Default)]
+struct Status {
+    samples: VecDeque<Sample>,
+    // The first failed check of the current outage.
+    down_since: Option<DateTime<Utc>>,
+}
+
+impl Status {
+    fn record(&mut self, sample: Sample, limit: usize) {
+        match (&sample.result, self.down_since) {
+            (Ok(_), _) => self.down_since = None,
+            (Err(_), None) => self.down_since = Some(sample.time),
+            (Err(_), Some(_)) => {}
+        }
+        self.samples.push_back(sample);
+        while self.samples.len() > limit {
+            self.samples.pop_front();
+        }
+    }
+
+    // Share of successful checks in the history, in percent.
+    fn uptime(&self) -> Option<usize> {
+        let up = self.samples.iter().filter(|s| s.result.is_ok()).count();
+        (!self.samples.is_empty()).then(|| up * 100 / self.samples.len())
+    }
+
+    // A strip of filled (up) and hollow (down) ticks, oldest first.
+    fn strip(&self, limit: usize) -> String {
+        let width = limit * TICK_WIDTH;
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{TICK_HEIGHT}" viewBox="0 0 {width} {TICK_HEIGHT}" shape-rendering="crispEdges">"#
+        );
+        // Recent samples are right-aligned, so the strip fills up from the right.
+        let offset = limit - self.samples.len().min(limit);
+        for (i, sample) in self.samples.iter().enumerate() {
+            let x = (offset + i) * TICK_WIDTH;
+            let w = TICK_WIDTH - 1;
+            let _ = if sample.result.is_ok() {
+                write!(
+                    svg,
+                    r#"<rect x="{x}" width="{w}" height="{TICK_HEIGHT}" fill="black"/>"#
+                )
+            } else {
+                write!(
+                    svg,
+                    r#"<rect x="{x}.5" y="0.5" width="{}" height="{}" fill="white" stroke="black"/>"#,
+                    w - 1,
+                    TICK_HEIGHT - 1,
+                )
+            };
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+struct Row {
+    name: String,
+    up: Option<bool>,
+    detail: String,
+    uptime: Option<usize>,
+    strip: String,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "uptime.stpl")]
+struct UptimeTemplate<'a> {
+    rows: &'a [Row],
+    summary: String,
+    columns: usize,
+    compact: bool,
+}
+
+pub struct UptimePlugin {
+    targets: Vec<Target>,
+    interval: Duration,
+    timeout: Duration,
+    history: usize,
+    status: Mutex<Vec<Status>>,
+}
+
+fn millis(latency: Duration) -> String {
+    format!("{} ms", latency.as_millis())
+}
+
+impl UptimePlugin {
+    pub fn new(
+        targets: Vec<TargetConfig>,
+        interval_secs: Option<u64>,
+        timeout_secs: Option<u64>,
+        history: Option<usize>,
+    ) -> Result<Self, storage::LoadError> {
+        if targets.is_empty() {
+            error!("An uptime monitor needs at least one target");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        let targets: Vec<Target> = targets
+            .into_iter()
+            .map(Target::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|name| {
+                error!("The uptime target {name:?} needs either `tcp` or `http`");
+                storage::LoadError::InvalidConfig
+            })?;
+        let interval = interval_secs.map_or(DEFAULT_INTERVAL, Duration::from_secs);
+        if interval.is_zero() || history == Some(0) {
+            error!("The uptime interval and history must not be zero");
+            return Err(storage::LoadError::InvalidConfig);
+        }
+        Ok(Self {
+            status: Mutex::new(targets.iter().map(|_| Status::default()).collect()),
+            targets,
+            interval,
+            timeout: timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs),
+            history: history.unwrap_or(DEFAULT_HISTORY),
+        })
+    }
+
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn probe(&self, check: &Check) -> Result<Duration, String> {
+        let start = tokio::time::Instant::now();
+        match check {
+            Check::Tcp(addr) => {
+                tokio::time::timeout(self.timeout, TcpStream::connect(addr))
+                    .await
+                    .map_err(|_| "timed out".to_owned())?
+                    .map_err(|e| e.to_string())?;
+            }
+            Check::Http { url, expect } => {
+                let status = net::client()
+                    .get(url.clone())
+                    .timeout(self.timeout)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        if e.is_timeout() {
+                            "timed out".into()
+                        } else {
+                            e.to_string()
+                        }
+                    })?
+                    .status();
+                let ok = expect.map_or(status.is_success(), |expect| status.as_u16() == expect);
+                if !ok {
+                    return Err(format!("HTTP {}", status.as_u16()));
+                }
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    // Runs every check once, at the same time, and records the results.
+    pub async fn check(&self, now: DateTime<Utc>) {
+        let results = join_all(self.targets.iter().map(|t| self.probe(&t.check))).await;
+        let mut status = self.status.lock().expect("uptime status lock poisoned");
+        for ((target, status), result) in self.targets.iter().zip(status.iter_mut()).zip(results) {
+            match (&result, status.down_since) {
+                (Err(e), None) => warn!("{} is down: {e}", target.name),
+                (Ok(_), Some(_)) => info!("{} is up again", target.name),
+                _ => {}
+            }
+            status.record(Sample { time: now, result }, self.history);
+        }
+    }
+
+    fn render(&self, ctx: &RenderContext, now: DateTime<Utc>) -> String {
+        let tz = ctx.timezone.parse::<Tz>().unwrap_or_else(|_| {
+            warn!("Unknown timezone {}, showing times in UTC", ctx.timezone);
+            Tz::UTC
+        });
+        let status = self.status.lock().expect("uptime status lock poisoned");
+        let rows: Vec<_> = self
+            .targets
+            .iter()
+            .zip(status.iter())
+            .map(|(target, status)| {
+                let last = status.samples.back();
+                let detail = match (last.map(|s| &s.result), status.down_since) {
+                    (None, _) => "not checked yet".into(),
+                    (Some(Ok(latency)), _) => millis(*latency),
+                    (Some(Err(e)), Some(since)) => {
+                        let local = since.with_timezone(&tz);
+                        let format = if local.date_naive() == now.with_timezone(&tz).date_naive() {
+                            "%H:%M"
+                        } else {
+                            "%-d %b %H:%M"
+                        };
+                        format!(
+                            "down since {} ({}), {e}",
+                            local.format(format),
+                            format::age(since, now)
+                        )
+                    }
+                    (Some(Err(e)), None) => e.clone(),
+                };
+                Row {
+                    name: target.name.clone(),
+                    up: last.map(|s| s.result.is_ok()),
+                    detail,
+                    uptime: status.uptime(),
+                    strip: status.strip(self.history),
+                }
+            })
+            .collect();
+        drop(status);
+        let down = rows.iter().filter(|r| r.up == Some(false)).count();
+        UptimeTemplate {
+            summary: match down {
+                0 if rows.iter().any(|r| r.up.is_none()) => "Checking…".into(),
+                0 => "All systems up".into(),
+                n => format!("{n} of {} down", rows.len()),
+            },
+            rows: &rows,
+            columns: if ctx.view == View::Full { 2 } else { 1 },
+            compact: ctx.view == View::Quadrant,
+        }
+        .render_once()
+        .expect("uptime template render failed")
+    }
+}
+
+impl generator::Content for UptimePlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(async move { Ok(self.render(ctx, Utc::now())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::get};
+    use chrono::{TimeDelta, TimeZone};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::testing;
+
+    fn tcp(name: &str, addr: &impl ToString) -> TargetConfig {
+        TargetConfig {
+            name: Some(name.into()),
+            tcp: Some(addr.to_string()),
+            http: None,
+            expect: None,
+        }
+    }
+
+    fn http(name: &str, url: &str, expect: Option<u16>) -> TargetConfig {
+        TargetConfig {
+            name: Some(name.into()),
+            tcp: None,
+            http: Some(url.parse().expect("Valid URL")),
+            expect,
+        }
+    }
+
+    // A local HTTP server answering `/ok` and `/teapot`.
+    async fn listener() -> std::net::SocketAddr {
+        let router = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/teapot", get(|| async { StatusCode::IM_A_TEAPOT }));
+        testing::serve(router).await
+    }
+
+    // An address nothing listens on.
+    async fn closed() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Binds a free port");
+        listener.local_addr().expect("Bound address")
+    }
+
+    fn results(plugin: &UptimePlugin) -> Vec<bool> {
+        plugin
+            .status
+            .lock()
+            .expect("Lock is not poisoned")
+            .iter()
+            .map(|s| s.samples.back().is_some_and(|s| s.result.is_ok()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn check_probes_local_listeners() {
+        let up = listener().await;
+        let plugin = UptimePlugin::new(
+            vec![
+                tcp("open", &up),
+                tcp("closed", &closed().await),
+                http("ok", &format!("http://{up}/ok"), None),
+                http("teapot", &format!("http://{up}/teapot"), None),
+                http("expected", &format!("http://{up}/teapot"), Some(418)),
+            ],
+            None,
+            Some(2),
+            None,
+        )
+        .expect("Valid config");
+        plugin.check(Utc::now()).await;
+        assert_eq!(results(&plugin), [true, false, true, false, true]);
+    }
+
+    #[tokio::test]
+    async fn render_shows_outages() {
+        let plugin = UptimePlugin::new(vec![tcp("db", &closed().await)], None, Some(2), Some(3))
+            .expect("Valid config");
+        let html = plugin.render(&RenderContext::new("d"), Utc::now());
+        assert!(html.contains("not checked yet"));
+        assert!(html.contains("Checking…"));
+        let start = Utc
+            .with_ymd_and_hms(2024, 6, 1, 12, 0, 0)
+            .single()
+            .expect("Valid time");
+        for i in 0..4 {
+            plugin.check(start + TimeDelta::minutes(i * 10)).await;
+        }
+        let html = plugin.render(&RenderContext::new("d"), start + TimeDelta::minutes(30));
+        assert!(html.contains("1 of 1 down"));
+        assert!(html.contains(&format!(
+            "down since {} (30 min ago)",
+            start.format("%H:%M")
+        )));
+        let status = plugin.status.lock().expect("Lock is not poisoned");
+        assert_eq!((status[0].samples.len(), status[0].uptime()), (3, Some(0)));
+        drop(status);
+    }
+
+    #[test]
+    fn status_tracks_outages() {
+        let start = Utc::now();
+        let mut status = Status::default();
+        let sample = |minutes, ok: bool| Sample {
+            time: start + TimeDelta::minutes(minutes),
+            result: if ok {
+                Ok(Duration::from_millis(12))
+            } else {
+                Err("refused".into())
+            },
+        };
+        status.record(sample(0, true), 10);
+        status.record(sample(1, false), 10);
+        status.record(sample(2, false), 10);
+        assert_eq!(status.down_since, Some(start + TimeDelta::minutes(1)));
+        status.record(sample(3, true), 10);
+        assert_eq!(status.down_since, None);
+        assert_eq!(status.uptime(), Some(50));
+        let strip = status.strip(10);
+        assert_eq!(strip.matches(r#"fill="black""#).count(), 2);
+        assert_eq!(strip.matches(r#"fill="white""#).count(), 2);
+    }
+
+    #[test]
+    fn new_rejects_invalid_configs() {
+        assert!(UptimePlugin::new(vec![], None, None, None).is_err());
+        let both = TargetConfig {
+            http: Some("http://example.com".parse().expect("Valid URL")),
+            ..tcp("both", &"example.com:80")
+        };
+        assert!(UptimePlugin::new(vec![both], None, None, None).is_err());
+        assert!(UptimePlugin::new(vec![tcp("a", &"a:1")], Some(0), None, None).is_err());
+        assert!(UptimePlugin::new(vec![tcp("a", &"a:1")], None, None, Some(0)).is_err());
+    }
+}
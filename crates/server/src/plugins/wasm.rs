@@ -1,40 +1,555 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 
 use futures::future::BoxFuture;
+use log::error;
 use serde_json::Value;
+use tokio::runtime::Handle;
 
-use crate::{generator, storage};
+use crate::{
+    generator::{self, RenderContext},
+    storage,
+};
+
+pub mod component;
+mod host;
+
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_mins(15);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub allowed_hosts: Vec<String>,
+    pub allowed_paths: HashMap<String, PathBuf>,
+    pub max_memory_pages: Option<u32>,
+    pub timeout_ms: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            // Plugins reach no hosts unless the config lists them.
+            allowed_hosts: Vec::new(),
+            allowed_paths: HashMap::new(),
+            max_memory_pages: None,
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+impl Limits {
+    fn apply(self, manifest: extism::Manifest) -> extism::Manifest {
+        let manifest = manifest
+            .with_allowed_hosts(self.allowed_hosts.into_iter())
+            .with_allowed_paths(self.allowed_paths.into_iter())
+            .with_timeout(Duration::from_millis(self.timeout_ms));
+        match self.max_memory_pages {
+            Some(pages) => manifest.with_memory_max(pages),
+            None => manifest,
+        }
+    }
+}
+
+struct Module {
+    compiled: extism::CompiledPlugin,
+    // Whether the module exports the optional `refresh` entry point.
+    refreshes: bool,
+    timeout: Duration,
+}
+
+impl Module {
+    fn compile(
+        path: &Path,
+        limits: &Limits,
+        functions: &[extism::Function],
+    ) -> Result<Self, storage::LoadError> {
+        let manifest = limits
+            .clone()
+            .apply(extism::Manifest::new([extism::Wasm::file(path)]));
+        let compiled = extism::PluginBuilder::new(manifest)
+            .with_wasi(true)
+            .with_functions(functions.iter().cloned())
+            .compile()
+            .inspect_err(|e| error!("Compiling WASM plugin {}: {e}", path.display()))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        // Exports can only be inspected on an instance.
+        let instance = extism::Plugin::new_from_compiled(&compiled)
+            .inspect_err(|e| error!("Instantiating WASM plugin {}: {e}", path.display()))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        let refreshes = instance.function_exists("refresh");
+        Ok(Self {
+            compiled,
+            refreshes,
+            timeout: Duration::from_millis(limits.timeout_ms),
+        })
+    }
+}
 
 pub struct WasmPlugin {
-    manifest: extism::Manifest,
+    path: PathBuf,
+    limits: Limits,
+    // Shared with reloaded modules so the KV store and fetch cache survive.
+    functions: Vec<extism::Function>,
+    module: RwLock<Arc<Module>>,
     config: Value,
+    refresh_interval: Duration,
 }
 
 impl WasmPlugin {
-    pub fn new(path: PathBuf, config: Value) -> Result<Self, storage::LoadError> {
+    pub fn new(
+        name: &str,
+        path: &Path,
+        config: Value,
+        limits: Limits,
+        state: Option<PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
         if !path.exists() {
-            log::error!("WASM plugin not found at {}", path.display());
+            error!("WASM plugin not found at {}", path.display());
             return Err(storage::LoadError::InvalidConfig);
         }
-        let manifest = extism::Manifest::new([extism::Wasm::file(path)]).with_allowed_host("*");
-        Ok(Self { manifest, config })
+        let kv = host::KvStore::open(state)
+            .inspect_err(|e| error!("Loading state of WASM plugin {name}: {e}"))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        let host = host::Host::new(name.to_owned(), limits.allowed_hosts.clone(), kv);
+        let functions = host::functions(host);
+        let module = Module::compile(path, &limits, &functions)?;
+        Ok(Self {
+            path: path.to_owned(),
+            limits,
+            functions,
+            module: RwLock::new(Arc::new(module)),
+            config,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+        })
+    }
+
+    #[must_use]
+    pub const fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // How often `refresh` should run, if the current module exports it.
+    pub fn refresh_schedule(&self) -> Option<Duration> {
+        self.module().refreshes.then_some(self.refresh_interval)
+    }
+
+    // Lets the plugin fetch and store data ahead of the renders that use it.
+    pub async fn refresh(&self, ctx: &RenderContext) -> Result<(), generator::Error> {
+        self.invoke("refresh", self.config.to_string(), ctx)
+            .await
+            .map(drop)
+    }
+
+    // Renders already in flight finish with the module they started with.
+    pub fn reload(&self) -> Result<(), storage::LoadError> {
+        let module = Module::compile(&self.path, &self.limits, &self.functions)?;
+        *self.module.write().expect("WASM module lock poisoned") = Arc::new(module);
+        Ok(())
+    }
+
+    fn module(&self) -> Arc<Module> {
+        Arc::clone(&self.module.read().expect("WASM module lock poisoned"))
+    }
+}
+
+// Prefers typed errors: wasmtime's interrupt trap, the host's own denials and
+// whether the call ran into its time limit. Extism turns some violations into
+// bare messages, which are matched only as a fallback.
+fn classify(err: &extism::Error, timed_out: bool) -> generator::Error {
+    let interrupted = err
+        .chain()
+        .any(|e| matches!(e.downcast_ref(), Some(wasmtime::Trap::Interrupt)));
+    if timed_out || interrupted {
+        return generator::Error::WasmTimeout;
+    }
+    if let Some(host::Denied(target)) = err.chain().find_map(|e| e.downcast_ref()) {
+        return generator::Error::WasmHostDenied(target.clone());
+    }
+    let cause = err.root_cause().to_string();
+    match cause.as_str() {
+        "timeout" => generator::Error::WasmTimeout,
+        "oom" => generator::Error::WasmOutOfMemory,
+        _ => cause
+            .strip_prefix("HTTP request to ")
+            .and_then(|rest| rest.strip_suffix(" is not allowed"))
+            .map_or_else(
+                || generator::Error::Wasm(err.to_string()),
+                |target| generator::Error::WasmHostDenied(target.to_owned()),
+            ),
+    }
+}
+
+// Every call gets a fresh instance of the compiled module, so no guest memory
+// or globals carry over from one call to the next. Only the host KV store and
+// fetch cache outlive a call.
+fn call(
+    module: &Module,
+    export: &str,
+    input: &str,
+    ctx: host::CallContext,
+) -> Result<String, generator::Error> {
+    let mut plugin = extism::Plugin::new_from_compiled(&module.compiled)
+        .map_err(|e| generator::Error::Wasm(e.to_string()))?;
+    let start = Instant::now();
+    plugin
+        .call_with_host_context::<&str, String, _>(export, input, ctx)
+        .map_err(|e| classify(&e, start.elapsed() >= module.timeout))
+}
+
+impl WasmPlugin {
+    async fn invoke(
+        &self,
+        export: &'static str,
+        input: String,
+        ctx: &RenderContext,
+    ) -> Result<String, generator::Error> {
+        let module = self.module();
+        let ctx = host::CallContext {
+            render: ctx.clone(),
+            runtime: Handle::current(),
+        };
+        tokio::task::spawn_blocking(move || call(&module, export, &input, ctx))
+            .await
+            .map_err(|_| generator::Error::Unknown)?
     }
 }
 
 impl generator::Content for WasmPlugin {
-    fn generate(&self) -> BoxFuture<'_, Result<String, generator::Error>> {
-        let manifest = self.manifest.clone();
-        let config = self.config.to_string();
-        Box::pin(async move {
-            tokio::task::spawn_blocking(move || {
-                let mut plugin = extism::Plugin::new(&manifest, [], true)
-                    .map_err(|e| generator::Error::Wasm(e.to_string()))?;
-                plugin
-                    .call::<&str, String>("generate", &config)
-                    .map_err(|e| generator::Error::Wasm(e.to_string()))
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        Box::pin(self.invoke("generate", self.config.to_string(), ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{Router, routing::get};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        generator::{Content, View},
+        testing,
+    };
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "plugins", name]
+            .iter()
+            .collect()
+    }
+
+    fn load(name: &str, config: Value, limits: Limits) -> WasmPlugin {
+        WasmPlugin::new("test", &fixture(name), config, limits, None).expect("Plugin loads")
+    }
+
+    fn sample(config: Value) -> WasmPlugin {
+        load("echo.wat", config, Limits::default())
+    }
+
+    async fn misbehave(mode: &str, limits: Limits) -> generator::Error {
+        load("misbehave.wat", json!(mode), limits)
+            .generate(&RenderContext::new("d"))
+            .await
+            .expect_err("Plugin violates its limits")
+    }
+
+    #[test]
+    fn new_rejects_missing_file() {
+        assert!(
+            WasmPlugin::new(
+                "test",
+                Path::new("does/not/exist.wasm"),
+                Value::Null,
+                Limits::default(),
+                None
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn new_rejects_invalid_module() {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "Cargo.toml"].iter().collect();
+        assert!(WasmPlugin::new("test", &path, Value::Null, Limits::default(), None).is_err());
+    }
+
+    #[test]
+    fn classify_prefers_typed_errors() {
+        let trap = extism::Error::new(wasmtime::Trap::Interrupt).context("calling generate");
+        assert!(matches!(
+            classify(&trap, false),
+            generator::Error::WasmTimeout
+        ));
+        let denied = extism::Error::new(host::Denied("http://192.0.2.1/".into()));
+        assert!(matches!(
+            classify(&denied, false),
+            generator::Error::WasmHostDenied(target) if target == "http://192.0.2.1/"
+        ));
+        let failed = extism::Error::msg("unreachable");
+        assert!(matches!(
+            classify(&failed, true),
+            generator::Error::WasmTimeout
+        ));
+        assert!(matches!(
+            classify(&failed, false),
+            generator::Error::Wasm(_)
+        ));
+    }
+
+    #[test]
+    fn limits_deserialize_with_defaults() {
+        let limits: Limits =
+            toml::from_str(r#"allowed_hosts = ["api.example.com"]"#).expect("Valid limits");
+        assert_eq!(limits.allowed_hosts, ["api.example.com"]);
+        assert!(limits.allowed_paths.is_empty());
+        assert_eq!(limits.max_memory_pages, None);
+        assert_eq!(limits.timeout_ms, 30_000);
+    }
+
+    #[tokio::test]
+    async fn timeout_cancels_call() {
+        let limits = Limits {
+            timeout_ms: 100,
+            ..Limits::default()
+        };
+        let err = misbehave("spin", limits).await;
+        assert!(matches!(err, generator::Error::WasmTimeout));
+    }
+
+    #[tokio::test]
+    async fn memory_cap_enforced() {
+        let limits = Limits {
+            max_memory_pages: Some(32),
+            ..Limits::default()
+        };
+        let err = misbehave("grow", limits).await;
+        assert!(matches!(err, generator::Error::WasmOutOfMemory));
+    }
+
+    #[tokio::test]
+    async fn host_allow_list_enforced() {
+        let limits = Limits {
+            allowed_hosts: vec!["api.example.com".into()],
+            ..Limits::default()
+        };
+        let err = misbehave("fetch", limits).await;
+        assert!(
+            matches!(err, generator::Error::WasmHostDenied(target) if target == "http://192.0.2.1/")
+        );
+
+        let denied = load("host.wat", Value::Null, Limits::default())
+            .invoke(
+                "fetch",
+                json!({ "url": "http://api.example.com/" }).to_string(),
+                &RenderContext::new("d"),
+            )
+            .await;
+        assert!(matches!(denied, Err(generator::Error::WasmHostDenied(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn host_fetch_checks_redirects_against_allowed_hosts() {
+        static HITS: AtomicUsize = AtomicUsize::new(0);
+        let inner = testing::mock_server(Router::new().route(
+            "/",
+            get(|| async {
+                HITS.fetch_add(1, Ordering::SeqCst);
+                "internal"
+            }),
+        ))
+        .await;
+        // The same server under a name the plugin may not reach.
+        let target = format!(
+            "http://localhost:{}/",
+            inner.port().expect("Mock has a port")
+        );
+        let base = testing::mock_server(Router::new().route(
+            "/",
+            get(move || async move { axum::response::Redirect::to(&target) }),
+        ))
+        .await;
+        let limits = Limits {
+            allowed_hosts: vec!["127.0.0.1".into()],
+            ..Limits::default()
+        };
+        let err = load("host.wat", Value::Null, limits)
+            .invoke(
+                "fetch",
+                json!({ "url": base.as_str() }).to_string(),
+                &RenderContext::new("d"),
+            )
+            .await
+            .expect_err("Redirect is not allowed");
+        assert!(
+            matches!(err, generator::Error::WasmHostDenied(target) if target.contains("localhost"))
+        );
+        assert_eq!(HITS.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn host_kv_round_trips() {
+        let plugin = load("host.wat", Value::Null, Limits::default());
+        let ctx = RenderContext::new("d");
+        let get = || plugin.invoke("kv_get", "count".into(), &ctx);
+        assert_eq!(get().await.expect("Lookup succeeds"), "null");
+        plugin
+            .invoke(
+                "kv_set",
+                json!({ "key": "count", "value": "1" }).to_string(),
+                &ctx,
+            )
+            .await
+            .expect("Store succeeds");
+        assert_eq!(get().await.expect("Lookup succeeds"), r#""1""#);
+        plugin
+            .invoke("kv_delete", "count".into(), &ctx)
+            .await
+            .expect("Delete succeeds");
+        assert_eq!(get().await.expect("Lookup succeeds"), "null");
+    }
+
+    #[tokio::test]
+    async fn host_context_describes_render() {
+        let plugin = load("host.wat", Value::Null, Limits::default());
+        let ctx = RenderContext::new("kitchen")
+            .with_region(Some("Europe/Zurich"), Some("de-CH"))
+            .with_view(View::Quadrant);
+        let context = plugin
+            .invoke("context", String::new(), &ctx)
+            .await
+            .expect("Context lookup succeeds");
+        assert_eq!(
+            serde_json::from_str::<Value>(&context).expect("Context is JSON"),
+            json!({
+                "device": "kitchen",
+                "view": "quadrant",
+                "width": 400,
+                "height": 240,
+                "timezone": "Europe/Zurich",
+                "locale": "de-CH",
             })
+        );
+    }
+
+    #[tokio::test]
+    async fn host_log_and_secret() {
+        let plugin = load("host.wat", Value::Null, Limits::default());
+        let ctx = RenderContext::new("d");
+        let record = json!({ "level": "debug", "message": "hello" }).to_string();
+        plugin
+            .invoke("log", record, &ctx)
             .await
-            .map_err(|_| generator::Error::Unknown)?
-        })
+            .expect("Logging succeeds");
+        let secret = plugin
+            .invoke("secret", "not_configured".into(), &ctx)
+            .await
+            .expect("Secret lookup succeeds");
+        assert_eq!(secret, "null");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn host_fetch_caches_responses() {
+        static HITS: AtomicUsize = AtomicUsize::new(0);
+        let base = testing::mock_server(Router::new().route(
+            "/",
+            get(|| async {
+                HITS.fetch_add(1, Ordering::SeqCst);
+                "hello"
+            }),
+        ))
+        .await;
+        let limits = Limits {
+            allowed_hosts: vec!["127.0.0.1".into()],
+            ..Limits::default()
+        };
+        let plugin = load("host.wat", Value::Null, limits);
+        let ctx = RenderContext::new("d");
+        let request = json!({ "url": base.as_str() }).to_string();
+        for _ in 0..2 {
+            let resp = plugin
+                .invoke("fetch", request.clone(), &ctx)
+                .await
+                .expect("Fetch succeeds");
+            assert_eq!(
+                serde_json::from_str::<Value>(&resp).expect("Response is JSON"),
+                json!({ "status": 200, "body": "hello" })
+            );
+        }
+        assert_eq!(HITS.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn host_fetch_respects_allowed_hosts() {
+        let limits = Limits {
+            allowed_hosts: vec!["api.example.com".into()],
+            ..Limits::default()
+        };
+        let plugin = load("host.wat", Value::Null, limits);
+        let err = plugin
+            .invoke(
+                "fetch",
+                json!({ "url": "http://192.0.2.1/" }).to_string(),
+                &RenderContext::new("d"),
+            )
+            .await
+            .expect_err("Host is not allowed");
+        assert!(
+            matches!(err, generator::Error::WasmHostDenied(target) if target == "http://192.0.2.1/")
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_passes_config() {
+        let plugin = sample(json!({ "city": "Zurich" }));
+        let html = plugin
+            .generate(&RenderContext::new("d"))
+            .await
+            .expect("Generation succeeds");
+        assert_eq!(html, r#"<p>{"city":"Zurich"}</p>"#);
+    }
+
+    #[tokio::test]
+    async fn refresh_feeds_generate() {
+        let plugin = load(
+            "refresh.wat",
+            json!({"key": "data", "value": "fresh"}),
+            Limits::default(),
+        );
+        let ctx = RenderContext::new("d");
+        assert_eq!(plugin.refresh_schedule(), Some(DEFAULT_REFRESH_INTERVAL));
+        assert_eq!(plugin.generate(&ctx).await.expect("Renders"), "null");
+        plugin.refresh(&ctx).await.expect("Refresh succeeds");
+        assert_eq!(plugin.generate(&ctx).await.expect("Renders"), r#""fresh""#);
+    }
+
+    #[test]
+    fn refresh_is_optional() {
+        assert_eq!(sample(json!("x")).refresh_schedule(), None);
+    }
+
+    #[tokio::test]
+    async fn generate_starts_from_a_fresh_instance() {
+        let plugin = load("counter.wat", Value::Null, Limits::default());
+        let ctx = RenderContext::new("d");
+        for _ in 0..3 {
+            let counts = plugin.generate(&ctx).await.expect("Generation succeeds");
+            assert_eq!(counts, "11");
+        }
     }
 }
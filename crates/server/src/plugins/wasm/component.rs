@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, RwLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use log::error;
+use serde_json::Value;
+use wasmtime::{
+    Engine, ResourceLimiter, Store, Trap,
+    component::{Component, Linker, ResourceTable},
+};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtx, WasiCtxView, WasiView};
+
+use super::Limits;
+use crate::{
+    generator::{self, FetchErrorKind, RenderContext, View},
+    storage,
+};
+
+mod bindings {
+    wasmtime::component::bindgen!({ path: "wit", world: "plugin" });
+}
+
+use bindings::{PluginPre, atrmnl::plugin::types};
+
+const TICK_MS: u64 = 10;
+const PAGE_SIZE: usize = 64 * 1024;
+
+// One engine for all components, its epoch advances every `TICK_MS` so calls can
+// be interrupted once their deadline has passed.
+static ENGINE: LazyLock<Engine> = LazyLock::new(|| {
+    let mut config = wasmtime::Config::new();
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config).expect("WASM engine configuration is valid");
+    let ticker = engine.clone();
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_millis(TICK_MS));
+            ticker.increment_epoch();
+        }
+    });
+    engine
+});
+
+// Components are distinguished from core modules by the layer field of the
+// binary header; text (.wat) files are always treated as core modules.
+pub fn is_component(path: &Path) -> bool {
+    let mut header = [0; 8];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|()| header.starts_with(b"\0asm") && header[6..] == [1, 0])
+}
+
+struct MemoryCap {
+    max_bytes: Option<usize>,
+    exceeded: bool,
+}
+
+impl ResourceLimiter for MemoryCap {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let allow = self.max_bytes.is_none_or(|max| desired <= max);
+        self.exceeded |= !allow;
+        Ok(allow)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+struct State {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    memory: MemoryCap,
+}
+
+impl WasiView for State {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+struct Module {
+    pre: PluginPre<State>,
+    allowed_paths: HashMap<String, PathBuf>,
+    max_memory_pages: Option<u32>,
+    timeout_ms: u64,
+}
+
+impl Module {
+    fn load(path: &Path, limits: &Limits) -> Result<Self, storage::LoadError> {
+        let compile = || {
+            let component = Component::from_file(&ENGINE, path)?;
+            let mut linker = Linker::new(&ENGINE);
+            wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+            PluginPre::new(linker.instantiate_pre(&component)?)
+        };
+        let pre = compile()
+            .inspect_err(|e| error!("Compiling WASM component {}: {e:#}", path.display()))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        let module = Self {
+            pre,
+            allowed_paths: limits.allowed_paths.clone(),
+            max_memory_pages: limits.max_memory_pages,
+            timeout_ms: limits.timeout_ms,
+        };
+        module
+            .wasi()
+            .inspect_err(|e| error!("Preparing WASI for {}: {e:#}", path.display()))
+            .map_err(|_| storage::LoadError::InvalidConfig)?;
+        Ok(module)
+    }
+
+    fn wasi(&self) -> wasmtime::Result<WasiCtx> {
+        let mut wasi = WasiCtx::builder();
+        for (host, guest) in &self.allowed_paths {
+            wasi.preopened_dir(
+                host,
+                guest.to_string_lossy(),
+                DirPerms::all(),
+                FilePerms::all(),
+            )?;
+        }
+        Ok(wasi.build())
+    }
+
+    fn call(
+        &self,
+        config: &str,
+        ctx: &types::RenderContext,
+    ) -> Result<types::Output, generator::Error> {
+        let wasi = self
+            .wasi()
+            .map_err(|e| generator::Error::Wasm(e.to_string()))?;
+        let mut store = Store::new(
+            &ENGINE,
+            State {
+                wasi,
+                table: ResourceTable::new(),
+                memory: MemoryCap {
+                    max_bytes: self.max_memory_pages.map(|p| p as usize * PAGE_SIZE),
+                    exceeded: false,
+                },
+            },
+        );
+        store.limiter(|state| &mut state.memory);
+        store.set_epoch_deadline(self.timeout_ms.div_ceil(TICK_MS));
+        let result = self
+            .pre
+            .instantiate(&mut store)
+            .and_then(|plugin| plugin.call_generate(&mut store, config, ctx));
+        match result {
+            Ok(output) => output.map_err(Into::into),
+            Err(_) if store.data().memory.exceeded => Err(generator::Error::WasmOutOfMemory),
+            Err(e) => Err(classify(&e)),
+        }
+    }
+}
+
+fn classify(err: &wasmtime::Error) -> generator::Error {
+    match err.downcast_ref::<Trap>() {
+        Some(Trap::Interrupt) => generator::Error::WasmTimeout,
+        _ => generator::Error::Wasm(format!("{err:#}")),
+    }
+}
+
+impl From<types::Error> for generator::Error {
+    fn from(err: types::Error) -> Self {
+        match err {
+            types::Error::Misconfigured(msg) => {
+                error!("WASM component is misconfigured: {msg}");
+                Self::Misconfigured
+            }
+            types::Error::Fetch(types::FetchError { target, failure }) => Self::Fetch {
+                kind: match failure {
+                    types::FetchFailure::Status(status) => FetchErrorKind::Request(
+                        http::StatusCode::from_u16(status).unwrap_or(http::StatusCode::BAD_GATEWAY),
+                    ),
+                    types::FetchFailure::Network => FetchErrorKind::Network,
+                    types::FetchFailure::Timeout => FetchErrorKind::Timeout,
+                    types::FetchFailure::InvalidData => FetchErrorKind::InvalidData,
+                },
+                target,
+            },
+            types::Error::Other(msg) => Self::Wasm(msg),
+        }
+    }
+}
+
+impl From<&RenderContext> for types::RenderContext {
+    fn from(ctx: &RenderContext) -> Self {
+        let (width, height) = ctx.view.size();
+        Self {
+            device: ctx.device.clone(),
+            view: match ctx.view {
+                View::Full => types::View::Full,
+                View::HalfVertical => types::View::HalfVertical,
+                View::Quadrant => types::View::Quadrant,
+            },
+            width,
+            height,
+            timezone: ctx.timezone.clone(),
+            locale: ctx.locale.clone(),
+        }
+    }
+}
+
+pub struct ComponentPlugin {
+    path: PathBuf,
+    limits: Limits,
+    module: RwLock<Arc<Module>>,
+    config: String,
+    templates: HashMap<String, liquid::Template>,
+    // Seconds, zero while the component has not asked for an interval.
+    refresh_interval: AtomicU32,
+}
+
+impl ComponentPlugin {
+    pub fn new(
+        path: &Path,
+        config: &Value,
+        limits: Limits,
+        templates: HashMap<String, PathBuf>,
+    ) -> Result<Self, storage::LoadError> {
+        let module = Module::load(path, &limits)?;
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .build()
+            .expect("Liquid stdlib parser builds");
+        let templates = templates
+            .into_iter()
+            .map(|(name, path)| {
+                let source = fs::read_to_string(&path)
+                    .map_err(|source| storage::LoadError::Read { path, source })?;
+                let template = parser
+                    .parse(&source)
+                    .inspect_err(|e| error!("Parsing template {name}: {e}"))
+                    .map_err(|_| storage::LoadError::InvalidConfig)?;
+                Ok((name, template))
+            })
+            .collect::<Result<_, storage::LoadError>>()?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            limits,
+            module: RwLock::new(Arc::new(module)),
+            config: config.to_string(),
+            templates,
+            refresh_interval: AtomicU32::new(0),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn reload(&self) -> Result<(), storage::LoadError> {
+        let module = Module::load(&self.path, &self.limits)?;
+        *self.module.write().expect("WASM module lock poisoned") = Arc::new(module);
+        Ok(())
+    }
+
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        match self.refresh_interval.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs.into())),
+        }
+    }
+
+    fn render(&self, content: types::Content) -> Result<String, generator::Error> {
+        match content {
+            types::Content::Html(html) => Ok(html),
+            types::Content::Templated(types::Templated { template, data }) => {
+                let template = self.templates.get(&template).ok_or_else(|| {
+                    generator::Error::Template(format!("unknown template {template}"))
+                })?;
+                let data: Value = serde_json::from_str(&data)
+                    .map_err(|e| generator::Error::Template(e.to_string()))?;
+                let globals = liquid::to_object(&data)
+                    .map_err(|e| generator::Error::Template(e.to_string()))?;
+                template
+                    .render(&globals)
+                    .map_err(|e| generator::Error::Template(e.to_string()))
+            }
+        }
+    }
+}
+
+impl generator::Content for ComponentPlugin {
+    fn generate<'a>(
+        &'a self,
+        ctx: &'a RenderContext,
+    ) -> BoxFuture<'a, Result<String, generator::Error>> {
+        let module = Arc::clone(&self.module.read().expect("WASM module lock poisoned"));
+        let config = self.config.clone();
+        let ctx = types::RenderContext::from(ctx);
+        Box::pin(async move {
+            let output = tokio::task::spawn_blocking(move || module.call(&config, &ctx))
+                .await
+                .map_err(|_| generator::Error::Unknown)??;
+            self.refresh_interval
+                .store(output.refresh_interval.unwrap_or(0), Ordering::Relaxed);
+            self.render(output.content)
+        })
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Self::refresh_interval(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::generator::Content;
+
+    // A release build of `crates/example-plugin`, checked in so the tests need
+    // neither the wasm32-wasip2 target nor the network. Rebuild it with
+    // `cargo build --release -p example-plugin --target wasm32-wasip2` after
+    // changing the plugin or the WIT world.
+    fn example_path() -> PathBuf {
+        [
+            env!("CARGO_MANIFEST_DIR"),
+            "tests",
+            "plugins",
+            "example_plugin.wasm",
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn example(config: &Value, templates: HashMap<String, PathBuf>) -> ComponentPlugin {
+        ComponentPlugin::new(&example_path(), config, Limits::default(), templates)
+            .expect("Example plugin loads")
+    }
+
+    #[test]
+    fn detects_components() {
+        assert!(is_component(&example_path()));
+        let module: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "plugins", "echo.wat"]
+            .iter()
+            .collect();
+        assert!(!is_component(&module));
+        assert!(!is_component(Path::new("does/not/exist.wasm")));
+    }
+
+    #[tokio::test]
+    async fn example_renders_html() {
+        let plugin = example(&json!({ "name": "Zurich" }), HashMap::new());
+        let ctx = RenderContext::new("kitchen").with_view(View::Quadrant);
+        let html = plugin.generate(&ctx).await.expect("Generation succeeds");
+        assert_eq!(html, "<p>Hello Zurich from kitchen (400x240)</p>");
+        assert_eq!(plugin.refresh_interval(), Some(Duration::from_mins(15)));
+    }
+
+    #[tokio::test]
+    async fn example_renders_template() {
+        let template =
+            std::env::temp_dir().join(format!("atrmnl_greeting_{}.liquid", std::process::id()));
+        fs::write(&template, "<h1>{{ name }}</h1><p>{{ device }}</p>")
+            .expect("Failed to write template");
+        let plugin = example(
+            &json!({ "name": "Zurich", "template": "greeting" }),
+            HashMap::from([("greeting".into(), template.clone())]),
+        );
+        fs::remove_file(&template).expect("Failed to remove template");
+        let html = plugin
+            .generate(&RenderContext::new("kitchen"))
+            .await
+            .expect("Generation succeeds");
+        assert_eq!(html, "<h1>Zurich</h1><p>kitchen</p>");
+    }
+
+    #[tokio::test]
+    async fn example_reports_misconfiguration() {
+        let plugin = example(&json!({}), HashMap::new());
+        let err = plugin
+            .generate(&RenderContext::new("kitchen"))
+            .await
+            .expect_err("Name is required");
+        assert!(matches!(err, generator::Error::Misconfigured));
+    }
+
+    #[tokio::test]
+    async fn unknown_template_is_an_error() {
+        let plugin = example(&json!({ "name": "x", "template": "nope" }), HashMap::new());
+        let err = plugin
+            .generate(&RenderContext::new("kitchen"))
+            .await
+            .expect_err("Template is unknown");
+        assert!(matches!(err, generator::Error::Template(_)));
+    }
+
+    #[test]
+    fn interrupt_is_a_timeout() {
+        let err = wasmtime::Error::from(Trap::Interrupt);
+        assert!(matches!(classify(&err), generator::Error::WasmTimeout));
+    }
+
+    #[test]
+    fn fetch_errors_keep_their_kind() {
+        let err = generator::Error::from(types::Error::Fetch(types::FetchError {
+            target: "https://example.com".into(),
+            failure: types::FetchFailure::Status(404),
+        }));
+        assert!(matches!(
+            err,
+            generator::Error::Fetch {
+                kind: FetchErrorKind::Request(http::StatusCode::NOT_FOUND),
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use extism::{CurrentPlugin, Function, PTR, ToBytes, UserData, Val, convert::Json};
+use log::{Level, log};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use crate::{chart::Chart, generator::RenderContext, plugins::state, qr::Qr};
+
+const SECRET_PREFIX: &str = "ATRMNL_SECRET_";
+const DEFAULT_TTL: Duration = Duration::from_mins(5);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 10;
+
+// Handed to the host functions of a single call through Extism's host context.
+pub struct CallContext {
+    pub render: RenderContext,
+    pub runtime: Handle,
+}
+
+// Raised by `atrmnl_fetch` for hosts outside the plugin's allow-list.
+#[derive(Debug, thiserror::Error)]
+#[error("HTTP request to {0} is not allowed")]
+pub struct Denied(pub String);
+
+#[derive(Debug, Default)]
+pub struct KvStore {
+    path: Option<PathBuf>,
+    entries: HashMap<String, String>,
+}
+
+impl KvStore {
+    pub fn open(path: Option<PathBuf>) -> Result<Self, state::Error> {
+        let entries = state::load(path.as_deref())?;
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.entries.get(key)
+    }
+
+    pub fn set(&mut self, key: String, value: String) -> Result<(), state::Error> {
+        self.entries.insert(key, value);
+        self.persist()
+    }
+
+    pub fn delete(&mut self, key: &str) -> Result<(), state::Error> {
+        if self.entries.remove(key).is_some() {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    fn persist(&self) -> Result<(), state::Error> {
+        state::save(self.path.as_deref(), &self.entries)
+    }
+}
+
+#[derive(Deserialize)]
+struct KvEntry {
+    key: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct Context<'a> {
+    device: &'a str,
+    view: &'a str,
+    width: u32,
+    height: u32,
+    timezone: &'a str,
+    locale: &'a str,
+}
+
+#[derive(Deserialize)]
+struct LogRecord {
+    level: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct FetchRequest {
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    ttl_secs: Option<u64>,
+}
+
+impl FetchRequest {
+    // The cache lives in the host of one plugin instance, and each device
+    // loads its own instance, so responses are never shared between devices.
+    fn cache_key(&self) -> String {
+        let mut headers: Vec<_> = self.headers.iter().collect();
+        headers.sort();
+        format!("{} {headers:?}", self.url)
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct FetchResponse {
+    status: u16,
+    body: String,
+}
+
+// Mirrors the matching Extism applies to its own HTTP host function.
+fn allows(allowed_hosts: &[String], host: &str) -> bool {
+    allowed_hosts
+        .iter()
+        .any(|pattern| glob::Pattern::new(pattern).map_or(pattern == host, |p| p.matches(host)))
+}
+
+// State shared by every instance of one plugin.
+pub struct Host {
+    name: String,
+    allowed_hosts: Arc<[String]>,
+    // Follows only redirects to allowed hosts, so an allowed host cannot
+    // forward the plugin to the local network.
+    client: reqwest::Client,
+    kv: KvStore,
+    cache: HashMap<String, (Instant, FetchResponse)>,
+}
+
+impl Host {
+    pub fn new(name: String, allowed_hosts: Vec<String>, kv: KvStore) -> Self {
+        let allowed_hosts: Arc<[String]> = allowed_hosts.into();
+        let redirects = allowed_hosts.clone();
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if allows(&redirects, attempt.url().host_str().unwrap_or_default()) {
+                    attempt.follow()
+                } else {
+                    let url = attempt.url().to_string();
+                    attempt.error(Denied(url))
+                }
+            }))
+            .build()
+            .expect("HTTP client configuration is valid");
+        Self {
+            name,
+            allowed_hosts,
+            client,
+            kv,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        allows(&self.allowed_hosts, host)
+    }
+}
+
+pub fn functions(host: Host) -> Vec<Function> {
+    let host = UserData::new(host);
+    vec![
+        Function::new("atrmnl_kv_get", [PTR], [PTR], host.clone(), kv_get),
+        Function::new("atrmnl_kv_set", [PTR], [], host.clone(), kv_set),
+        Function::new("atrmnl_kv_delete", [PTR], [], host.clone(), kv_delete),
+        Function::new("atrmnl_context", [], [PTR], host.clone(), context),
+        Function::new("atrmnl_log", [PTR], [], host.clone(), log_record),
+        Function::new("atrmnl_fetch", [PTR], [PTR], host.clone(), fetch),
+        Function::new("atrmnl_secret", [PTR], [PTR], host.clone(), secret),
+        Function::new("atrmnl_chart", [PTR], [PTR], host.clone(), chart),
+        Function::new("atrmnl_qr", [PTR], [PTR], host, qr),
+    ]
+}
+
+fn output<'a>(
+    plugin: &mut CurrentPlugin,
+    outputs: &mut [Val],
+    value: impl ToBytes<'a>,
+) -> Result<(), extism::Error> {
+    let handle = plugin.memory_new(value)?;
+    outputs[0] = plugin.memory_to_val(handle);
+    Ok(())
+}
+
+fn with_host<R>(host: &UserData<Host>, f: impl FnOnce(&mut Host) -> R) -> Result<R, extism::Error> {
+    let host = host.get()?;
+    let mut host = host.lock().expect("WASM host lock poisoned");
+    Ok(f(&mut host))
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn kv_get(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let key: String = plugin.memory_get_val(&inputs[0])?;
+    let value = with_host(&host, |h| h.kv.get(&key).cloned())?;
+    output(plugin, outputs, Json(value))
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn kv_set(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    _outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let Json(KvEntry { key, value }) = plugin.memory_get_val(&inputs[0])?;
+    with_host(&host, |h| h.kv.set(key, value))??;
+    Ok(())
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn kv_delete(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    _outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let key: String = plugin.memory_get_val(&inputs[0])?;
+    with_host(&host, |h| h.kv.delete(&key))??;
+    Ok(())
+}
+
+fn context(
+    plugin: &mut CurrentPlugin,
+    _inputs: &[Val],
+    outputs: &mut [Val],
+    _host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let ctx = &plugin.host_context::<CallContext>()?.render;
+    let (width, height) = ctx.view.size();
+    let context = serde_json::to_vec(&Context {
+        device: &ctx.device,
+        view: ctx.view.as_str(),
+        width,
+        height,
+        timezone: &ctx.timezone,
+        locale: &ctx.locale,
+    })?;
+    output(plugin, outputs, context)
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn log_record(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    _outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let Json(LogRecord { level, message }) = plugin.memory_get_val(&inputs[0])?;
+    let level = level.parse().unwrap_or(Level::Info);
+    with_host(&host, |h| log!(level, "[{}] {message}", h.name))
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn fetch(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let Json(request): Json<FetchRequest> = plugin.memory_get_val(&inputs[0])?;
+    let url = url::Url::parse(&request.url)?;
+    let key = request.cache_key();
+    let (cached, client) = with_host(&host, |h| {
+        if !h.allows(url.host_str().unwrap_or_default()) {
+            return Err(extism::Error::new(Denied(url.to_string())));
+        }
+        h.cache.retain(|_, (expires, _)| *expires > Instant::now());
+        Ok((
+            h.cache.get(&key).map(|(_, resp)| resp.clone()),
+            h.client.clone(),
+        ))
+    })??;
+    if let Some(resp) = cached {
+        return output(plugin, outputs, Json(resp));
+    }
+
+    // The call runs on a blocking thread, so waiting for the request here is
+    // fine. The host lock is released meanwhile, and the plugin's timeout only
+    // interrupts guest code: a slow request holds the render for up to `FETCH_TIMEOUT`.
+    let runtime = plugin.host_context::<CallContext>()?.runtime.clone();
+    let resp = runtime.block_on(async {
+        let mut req = client.get(url).timeout(FETCH_TIMEOUT);
+        for (name, value) in &request.headers {
+            req = req.header(name, value);
+        }
+        let resp = req.send().await?;
+        let status = resp.status();
+        Ok::<_, reqwest::Error>(FetchResponse {
+            status: status.as_u16(),
+            body: resp.text().await?,
+        })
+    })?;
+    if (200..300).contains(&resp.status) {
+        let ttl = request.ttl_secs.map_or(DEFAULT_TTL, Duration::from_secs);
+        with_host(&host, |h| {
+            h.cache.insert(key, (Instant::now() + ttl, resp.clone()));
+        })?;
+    }
+    output(plugin, outputs, Json(resp))
+}
+
+// Secrets are scoped to the plugin: `secret("api_key")` in the plugin named
+// `weather` reads `ATRMNL_SECRET_weather__api_key`. Names are used as they
+// are and may not contain `__`, so no two plugins share a variable.
+fn secret_var(plugin: &str, name: &str) -> Option<String> {
+    let valid = |s: &str| {
+        !s.is_empty()
+            && !s.contains("__")
+            && !s.starts_with('_')
+            && !s.ends_with('_')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    (valid(plugin) && valid(name)).then(|| format!("{SECRET_PREFIX}{plugin}__{name}"))
+}
+
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "extism::Function dictates the signature"
+)]
+fn secret(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let name: String = plugin.memory_get_val(&inputs[0])?;
+    let var = with_host(&host, |h| {
+        secret_var(&h.name, &name).ok_or_else(|| {
+            extism::Error::msg(format!(
+                "secret {name:?} of plugin {:?}: names may only contain letters, digits and single inner underscores",
+                h.name
+            ))
+        })
+    })??;
+    output(plugin, outputs, Json(std::env::var(var).ok()))
+}
+
+fn chart(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    _host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let Json(chart): Json<Chart> = plugin.memory_get_val(&inputs[0])?;
+    output(plugin, outputs, chart.svg())
+}
+
+fn qr(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    _host: UserData<Host>,
+) -> Result<(), extism::Error> {
+    let Json(qr): Json<Qr> = plugin.memory_get_val(&inputs[0])?;
+    output(plugin, outputs, qr.svg()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_state() -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("atrmnl_kv_{}.json", std::process::id()));
+        path
+    }
+
+    #[test]
+    fn kv_store_persists_to_disk() {
+        let path = temp_state();
+        let mut kv = KvStore::open(Some(path.clone())).expect("Fresh state opens");
+        kv.set("count".into(), "1".into()).expect("Value stored");
+        kv.set("gone".into(), "x".into()).expect("Value stored");
+        kv.delete("gone").expect("Value deleted");
+
+        let kv = KvStore::open(Some(path.clone())).expect("Saved state opens");
+        std::fs::remove_file(&path).expect("Failed to remove state file");
+        assert_eq!(kv.get("count").map(String::as_str), Some("1"));
+        assert_eq!(kv.get("gone"), None);
+    }
+
+    #[test]
+    fn secrets_are_scoped_to_the_plugin() {
+        assert_eq!(
+            secret_var("weather", "api_key").as_deref(),
+            Some("ATRMNL_SECRET_weather__api_key")
+        );
+        assert_ne!(secret_var("a", "b_c"), secret_var("a_b", "c"));
+        assert_ne!(secret_var("my_data", "key"), secret_var("MY_DATA", "key"));
+        for (plugin, name) in [
+            ("my-data", "key"),
+            ("a", "b__c"),
+            ("a_", "b"),
+            ("a", "_b"),
+            ("a", ""),
+        ] {
+            assert_eq!(secret_var(plugin, name), None, "{plugin} {name}");
+        }
+    }
+
+    #[test]
+    fn host_allow_list_matches_globs() {
+        let host = Host::new(
+            "test".into(),
+            vec!["api.example.com".into(), "*.example.org".into()],
+            KvStore::default(),
+        );
+        assert!(host.allows("api.example.com"));
+        assert!(host.allows("www.example.org"));
+        assert!(!host.allows("example.com"));
+        assert!(!host.allows("192.0.2.1"));
+    }
+}
@@ -6,9 +6,14 @@ use log::{debug, error};
 use sailfish::TemplateOnce;
 use url::Url;
 
-use crate::generator;
+use super::astronomy::{Observer, Sun};
+use crate::{
+    chart::{Chart, Kind},
+    generator,
+};
 
 const ICON_SUNRISE: &str = iconify::svg!("wi:sunrise", width = "24px");
+const ICON_SUNSET: &str = iconify::svg!("wi:sunset", width = "24px");
 const ICON_STRONG_WIND: &str = iconify::svg!("wi:strong-wind", width = "24px");
 const ICON_THERMOMETER: &str = iconify::svg!("wi:thermometer");
 const ICON_THERMOMETER_24: &str = iconify::svg!("wi:thermometer", width = "24px");
@@ -291,17 +296,47 @@ pub struct DailyForecast {
     pub temperatures: TemperatureRange,
     pub weather_code: WeatherCode,
     pub sunrise: NaiveDateTime,
+    // Open-Meteo only reports the sunrise, the sunset is computed locally.
+    pub sunset: Option<NaiveDateTime>,
     pub uv_index: f64,
     pub wind_speed: f64,
     pub wind_gusts: f64,
     pub wind_dir: WindDirection,
 }
 
+#[derive(Debug)]
+pub struct HourlyForecast {
+    pub time: NaiveDateTime,
+    pub temperature: Temperature,
+}
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(try_from = "intermediate::Weather")]
 pub struct Weather {
     pub current: CurrentForecast,
     pub daily: Vec<DailyForecast>,
+    pub hourly: Vec<HourlyForecast>,
+}
+
+impl Weather {
+    pub fn temperature_chart(&self, width: u32, height: u32) -> Chart {
+        Chart::new(Kind::Area, width, height)
+            .with_series(self.hourly.iter().map(|h| h.temperature.0))
+            .with_labels(self.hourly.iter().map(|h| h.time.format("%H").to_string()))
+            .with_unit("°")
+    }
+
+    // Fills in the sunset of each day as seen from `observer`.
+    pub fn with_sunsets(mut self, observer: Observer) -> Self {
+        let offset = *self.current.time.offset();
+        for day in &mut self.daily {
+            day.sunset = Sun::on(observer, day.date)
+                .daylight
+                .set
+                .map(|set| set.with_timezone(&offset).naive_local());
+        }
+        self
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -312,7 +347,7 @@ impl TryFrom<intermediate::Weather> for Weather {
     type Error = ConvertError;
 
     fn try_from(weather: intermediate::Weather) -> Result<Self, Self::Error> {
-        let (current, daily) = (weather.current, weather.daily);
+        let (current, daily, hourly) = (weather.current, weather.daily, weather.hourly);
         Ok(Self {
             current: CurrentForecast {
                 time: current
@@ -344,6 +379,7 @@ impl TryFrom<intermediate::Weather> for Weather {
                         temperatures: TemperatureRange::new(tmin.into(), tmax.into()),
                         weather_code: wc,
                         sunrise: sunrise.into_inner(),
+                        sunset: None,
                         uv_index,
                         wind_speed,
                         wind_gusts,
@@ -352,6 +388,15 @@ impl TryFrom<intermediate::Weather> for Weather {
                 },
             )
             .collect(),
+            hourly: hourly
+                .time
+                .into_iter()
+                .zip(hourly.temperature)
+                .map(|(time, temperature)| HourlyForecast {
+                    time: time.into_inner(),
+                    temperature: temperature.into(),
+                })
+                .collect(),
         })
     }
 }
@@ -402,17 +447,27 @@ mod intermediate {
         pub weather_code: Vec<WeatherCode>,
     }
 
+    #[derive(serde::Deserialize, Default)]
+    pub struct HourlyForecast {
+        pub time: Vec<DayAndTime>,
+        #[serde(rename = "temperature_2m")]
+        pub temperature: Vec<f64>,
+    }
+
     #[derive(serde::Deserialize)]
     pub struct Weather {
         #[serde(rename = "utc_offset_seconds", with = "super::utc_offset")]
         pub utc_offset: FixedOffset,
         pub current: CurrentForecast,
         pub daily: DailyForecast,
+        #[serde(default)]
+        pub hourly: HourlyForecast,
     }
 }
 
 pub struct Client {
     url: Url,
+    observer: Observer,
     detail: Detail,
 }
 
@@ -425,7 +480,11 @@ impl Client {
             .clear()
             .append_pair("longitude", &format!("{:.2}", coords.longitude))
             .append_pair("latitude", &format!("{:.2}", coords.latitude));
-        Ok(Self { url, detail })
+        Ok(Self {
+            url,
+            observer: Observer::new(coords.latitude, coords.longitude),
+            detail,
+        })
     }
 
     pub async fn fetch(&self) -> Result<Weather, reqwest::Error> {
@@ -439,7 +498,9 @@ impl Client {
                 "daily",
                 "weather_code,temperature_2m_max,temperature_2m_min,sunrise,uv_index_max,wind_speed_10m_max,wind_gusts_10m_max,wind_direction_10m_dominant",
             )
-            .append_pair("forecast_days", "3");
+            .append_pair("hourly", "temperature_2m")
+            .append_pair("forecast_days", "3")
+            .append_pair("forecast_hours", "24");
         reqwest::get(url)
             .await
             .inspect(|d| debug!("Got weather response: {d:#?}"))?
@@ -451,7 +512,8 @@ impl Client {
         let weather = self
             .fetch()
             .await
-            .inspect_err(|e| error!("In weather data body: {e}"))?;
+            .inspect_err(|e| error!("In weather data body: {e}"))?
+            .with_sunsets(self.observer);
         Ok(self.detail.produce(&weather))
     }
 }
@@ -555,6 +617,47 @@ mod tests {
             weather.daily[0].weather_code,
             WeatherCode::MostlyClear
         ));
+        assert!(weather.hourly.is_empty());
+        assert_eq!(weather.daily[0].sunset, None);
+        // Berlin's sunset, in the forecast's UTC offset.
+        let weather = weather.with_sunsets(Observer::new(52.52, 13.405));
+        let sunset = weather.daily[0].sunset.expect("Sunset");
+        assert_eq!(sunset.format("%Y-%m-%d %H").to_string(), "2024-01-01 16");
+    }
+
+    #[test]
+    fn hourly_temperatures_become_a_chart() {
+        let json = r#"{
+            "utc_offset_seconds": 0,
+            "current": {
+                "time": "2024-01-01T12:00",
+                "temperature_2m": 15.5,
+                "apparent_temperature": 14.0,
+                "relative_humidity_2m": 60,
+                "weather_code": 1
+            },
+            "daily": {
+                "time": [],
+                "temperature_2m_max": [],
+                "temperature_2m_min": [],
+                "sunrise": [],
+                "uv_index_max": [],
+                "wind_speed_10m_max": [],
+                "wind_gusts_10m_max": [],
+                "wind_direction_10m_dominant": [],
+                "weather_code": []
+            },
+            "hourly": {
+                "time": ["2024-01-01T12:00", "2024-01-01T13:00", "2024-01-01T14:00"],
+                "temperature_2m": [15.5, 16.2, 14.8]
+            }
+        }"#;
+        let weather: Weather = serde_json::from_str(json).expect("Valid test JSON");
+        assert_eq!(weather.hourly.len(), 3);
+        let chart = weather.temperature_chart(400, 100);
+        assert_eq!(chart.series, [vec![15.5, 16.2, 14.8]]);
+        assert_eq!(chart.labels, ["12", "13", "14"]);
+        assert!(chart.svg().contains("16°"));
     }
 
     #[test]
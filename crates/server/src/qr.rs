@@ -0,0 +1,226 @@
+use std::fmt::Write;
+
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+
+// Light modules around the code, as the standard asks for.
+const QUIET_ZONE: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Correction {
+    Low,
+    #[default]
+    Medium,
+    Quartile,
+    High,
+}
+
+impl From<Correction> for EcLevel {
+    fn from(value: Correction) -> Self {
+        match value {
+            Correction::Low => Self::L,
+            Correction::Medium => Self::M,
+            Correction::Quartile => Self::Q,
+            Correction::High => Self::H,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the data does not fit in a QR code")]
+    TooLong,
+    #[error("{size}px is too small for a code of {modules} modules")]
+    TooSmall { size: u32, modules: usize },
+}
+
+// A QR code rendered as inline SVG. Each module covers a whole number of
+// pixels, so the code stays sharp when the page is dithered for e-ink.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Qr {
+    pub data: String,
+    // Upper bound for the width and height, the code is shrunk to the
+    // largest multiple of its module count that fits.
+    pub size: u32,
+    #[serde(default)]
+    pub correction: Correction,
+}
+
+impl Qr {
+    pub fn new(data: impl Into<String>, size: u32) -> Self {
+        Self {
+            data: data.into(),
+            size,
+            correction: Correction::default(),
+        }
+    }
+
+    pub const fn with_correction(mut self, correction: Correction) -> Self {
+        self.correction = correction;
+        self
+    }
+
+    fn encode(&self) -> Result<QrCode, Error> {
+        QrCode::with_error_correction_level(&self.data, self.correction.into())
+            .map_err(|_| Error::TooLong)
+    }
+
+    // Checks that the data fits, without rendering.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.encode().map(|_| ())
+    }
+
+    pub fn svg(&self) -> Result<String, Error> {
+        let code = self.encode()?;
+        let modules = code.width() + 2 * QUIET_ZONE;
+        let scale = usize::try_from(self.size).unwrap_or(usize::MAX) / modules;
+        if scale == 0 {
+            return Err(Error::TooSmall {
+                size: self.size,
+                modules,
+            });
+        }
+        let side = modules * scale;
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{side}" height="{side}" viewBox="0 0 {side} {side}" shape-rendering="crispEdges"><rect width="{side}" height="{side}" fill="white"/><path fill="black" d=""#
+        );
+        // Dark modules are merged into runs along each row.
+        for (y, row) in code.to_colors().chunks(code.width()).enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                let run = row[x..].iter().take_while(|&&c| c == Color::Dark).count();
+                if run > 0 {
+                    let _ = write!(
+                        svg,
+                        "M{},{}h{w}v{scale}h-{w}z",
+                        (x + QUIET_ZONE) * scale,
+                        (y + QUIET_ZONE) * scale,
+                        w = run * scale,
+                    );
+                }
+                x += run.max(1);
+            }
+        }
+        svg.push_str(r#""/></svg>"#);
+        Ok(svg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Security {
+    // Also covers WPA2 and WPA3.
+    #[default]
+    Wpa,
+    Wep,
+    Open,
+}
+
+// Network details in the `WIFI:` format phone cameras understand.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Wifi {
+    pub ssid: String,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .fold(String::with_capacity(value.len()), |mut escaped, c| {
+            if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+}
+
+impl Wifi {
+    pub fn payload(&self) -> String {
+        let mut payload = String::from("WIFI:");
+        let security = match self.security {
+            Security::Wpa => "WPA",
+            Security::Wep => "WEP",
+            Security::Open => "nopass",
+        };
+        let _ = write!(payload, "T:{security};S:{};", escape(&self.ssid));
+        if let Some(password) = self
+            .password
+            .as_deref()
+            .filter(|_| self.security != Security::Open)
+        {
+            let _ = write!(payload, "P:{};", escape(password));
+        }
+        if self.hidden {
+            payload.push_str("H:true;");
+        }
+        payload.push(';');
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_snaps_modules_to_pixels() {
+        // "hello" fits a version 1 code of 21 modules, 29 with the quiet zone.
+        let svg = Qr::new("hello", 100).svg().expect("Data fits");
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="87" height="87""#)
+        );
+        assert!(svg.contains(r#"shape-rendering="crispEdges""#));
+        // The top-left finder pattern starts at the quiet zone.
+        assert!(svg.contains("M12,12h21v3h-21z"));
+        let path = svg.split(r#"d=""#).nth(1).expect("Path");
+        assert!(!path.contains('.'), "Coordinates are whole pixels");
+    }
+
+    #[test]
+    fn svg_rejects_impossible_codes() {
+        assert!(matches!(
+            Qr::new("hello", 20).svg(),
+            Err(Error::TooSmall {
+                size: 20,
+                modules: 29
+            })
+        ));
+        let long = "x".repeat(3000);
+        assert!(matches!(Qr::new(long, 800).validate(), Err(Error::TooLong)));
+    }
+
+    #[test]
+    fn correction_grows_the_code() {
+        let low = Qr::new("https://example.com/guest", 800).with_correction(Correction::Low);
+        let high = low.clone().with_correction(Correction::High);
+        let width = |qr: &Qr| qr.encode().expect("Data fits").width();
+        assert!(width(&low) < width(&high));
+    }
+
+    #[test]
+    fn wifi_payload_escapes_special_characters() {
+        let wifi = Wifi {
+            ssid: "Guest;Net".into(),
+            password: Some(r#"p:a"ss\"#.into()),
+            security: Security::Wpa,
+            hidden: true,
+        };
+        assert_eq!(
+            wifi.payload(),
+            r#"WIFI:T:WPA;S:Guest\;Net;P:p\:a\"ss\\;H:true;;"#
+        );
+        let open = Wifi {
+            ssid: "Cafe".into(),
+            password: Some("ignored".into()),
+            security: Security::Open,
+            hidden: false,
+        };
+        assert_eq!(open.payload(), "WIFI:T:nopass;S:Cafe;;");
+    }
+}
@@ -1,16 +1,17 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use axum::response::Html;
 use axum::{
     Json, Router,
-    extract::{FromRef, Path, State},
-    response::{IntoResponse, Response},
-    routing::get,
+    body::Bytes,
+    extract::{Form, FromRef, Path, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
 };
 use axum_server::tls_rustls::RustlsConfig;
 use http::{StatusCode, header};
 use itertools::Itertools;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use rust_embed::Embed;
 use sailfish::TemplateOnce;
 use serde::Serialize;
@@ -22,10 +23,14 @@ use crate::{
     error::Canonical,
     generator::Content,
     pages,
+    plugins::{self, reload::Reloads},
     resource::{self, Resource},
     storage,
 };
 
+const DEFAULT_REFRESH_RATE: u64 = 1800;
+const DEV_RELOADS_WAIT: Duration = Duration::from_secs(30);
+
 enum ImageType {
     Png,
     Qoi,
@@ -66,8 +71,12 @@ pub async fn serve(
         .route("/content/{id}", get(screen_content))
         .route("/screen/{id}", get(render_screen_img))
         .route("/preview/{id}", get(preview))
+        .route("/plugins", get(installed_plugins))
+        .route("/dev/reloads/{after}", get(dev_reloads))
         .route("/assets/{*file}", get(embedded_assets))
         .route("/api/display", get(api_display))
+        .route("/api/plugins/{name}/data", post(push_data))
+        .route("/board/{name}", get(board_page).post(board_update))
         .with_state(state);
     let app = if log_requests {
         app.layer(
@@ -169,6 +178,315 @@ mod tests {
         assert!(matches!(determine_image_type(&headers), ImageType::Qoi));
     }
 
+    #[tokio::test]
+    async fn dev_reloads_requires_dev_mode() {
+        let resp = dev_reloads(State(None), Path(0)).await;
+        assert!(matches!(resp, Err(Canonical::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn dev_reloads_answers_after_reload() {
+        let (tx, rx) = tokio::sync::watch::channel(0);
+        let waiting = tokio::spawn(dev_reloads(State(Some(rx)), Path(0)));
+        tx.send_replace(1);
+        let resp = waiting.await.expect("Handler completes");
+        assert!(matches!(resp, Ok(ref count) if count == "1"));
+    }
+
+    #[tokio::test]
+    async fn push_data_reaches_the_plugin() {
+        let dir = std::env::temp_dir().join(format!("atrmnl_push_serve_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+        std::fs::write(dir.join("full.liquid"), "{{ message }}").expect("Failed to write markup");
+        let config = dir.join("devices.toml");
+        std::fs::write(
+            &config,
+            format!(
+                r#"
+[mydevice]
+mashup = {{ single = "door" }}
+
+[[mydevice.plugins]]
+push = {{ name = "door", token = "t0ken", max_bytes = 64, markup = {{ full = "{}" }} }}
+"#,
+                dir.join("full.liquid").display()
+            ),
+        )
+        .expect("Failed to write config");
+        let storage = Arc::new(
+            storage::Storage::load(Some(config), plugins::catalog::Catalog::default())
+                .await
+                .expect("Failed to load storage"),
+        );
+        std::fs::remove_dir_all(&dir).expect("Failed to remove temp dir");
+
+        let push = |name: &str, token: &str, body: String| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                format!("Bearer {token}")
+                    .parse()
+                    .expect("Header value is valid"),
+            );
+            push_data(
+                State(storage.clone()),
+                Path(name.into()),
+                headers,
+                Bytes::from(body),
+            )
+        };
+        let status = |result: Result<StatusCode, Canonical>| {
+            result.unwrap_or_else(|e| e.into_response().status())
+        };
+        assert_eq!(
+            status(push("door", "t0ken", r#"{"message": "Open"}"#.into()).await),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            status(push("door", "guess", r#"{"message": "Closed"}"#.into()).await),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(
+                push(
+                    "door",
+                    "t0ken",
+                    format!(r#"{{"message": "{}"}}"#, "x".repeat(64))
+                )
+                .await
+            ),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            status(push("window", "t0ken", "{}".into()).await),
+            StatusCode::NOT_FOUND
+        );
+        let html = storage
+            .content_generator("mydevice")
+            .expect("Content generator exists")
+            .generate(&storage.render_context("mydevice"))
+            .await
+            .expect("Plugin renders");
+        assert!(html.contains("Open"));
+    }
+
+    #[tokio::test]
+    async fn board_page_requires_password() {
+        let config =
+            std::env::temp_dir().join(format!("atrmnl_board_serve_{}.toml", std::process::id()));
+        std::fs::write(
+            &config,
+            r#"
+[kitchen]
+mashup = { single = "notes" }
+
+[[kitchen.plugins]]
+board = { name = "notes", password = "fridge" }
+"#,
+        )
+        .expect("Failed to write config");
+        let storage = Arc::new(
+            storage::Storage::load(Some(config.clone()), plugins::catalog::Catalog::default())
+                .await
+                .expect("Failed to load storage"),
+        );
+        std::fs::remove_file(&config).expect("Failed to remove config");
+
+        let response = board_page(
+            State(storage.clone()),
+            Path("notes".into()),
+            header::HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            // "mum:fridge"
+            "Basic bXVtOmZyaWRnZQ=="
+                .parse()
+                .expect("Hardcoded header value is valid"),
+        );
+        let update = plugins::board::Update {
+            action: "post".into(),
+            author: "Mum".into(),
+            text: "Back at six".into(),
+            ..plugins::board::Update::default()
+        };
+        let response = board_update(
+            State(storage.clone()),
+            Path("notes".into()),
+            http::Uri::from_static("/board/notes"),
+            headers.clone(),
+            Form(update),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let page = board_page(
+            State(storage.clone()),
+            Path("notes".into()),
+            headers.clone(),
+        )
+        .await
+        .map(|html| html.0)
+        .unwrap_or_default();
+        assert!(page.contains("Back at six"));
+        let screen = storage
+            .content_generator("kitchen")
+            .expect("Content generator exists")
+            .generate(&storage.render_context("kitchen"))
+            .await
+            .expect("Board renders");
+        assert!(screen.contains("Mum · just now"));
+
+        let mut foreign = headers.clone();
+        foreign.insert(
+            header::ORIGIN,
+            "https://evil.example"
+                .parse()
+                .expect("Hardcoded header value is valid"),
+        );
+        foreign.insert(
+            header::HOST,
+            "trmnl.local:8223"
+                .parse()
+                .expect("Hardcoded header value is valid"),
+        );
+        let update = plugins::board::Update {
+            action: "post".into(),
+            author: "Mallory".into(),
+            text: "Send money".into(),
+            ..plugins::board::Update::default()
+        };
+        let response = board_update(
+            State(storage.clone()),
+            Path("notes".into()),
+            http::Uri::from_static("/board/notes"),
+            foreign,
+            Form(update),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn same_origin_compares_origin_and_host() {
+        let headers = |origin: &'static str, host: &'static str| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(header::ORIGIN, header::HeaderValue::from_static(origin));
+            headers.insert(header::HOST, header::HeaderValue::from_static(host));
+            headers
+        };
+        let path = http::Uri::from_static("/board/notes");
+        assert!(same_origin(&header::HeaderMap::new(), &path));
+        assert!(same_origin(
+            &headers("http://trmnl.local:8223", "trmnl.local:8223"),
+            &path
+        ));
+        assert!(!same_origin(
+            &headers("http://evil.example", "trmnl.local:8223"),
+            &path
+        ));
+        assert!(!same_origin(&headers("null", "trmnl.local:8223"), &path));
+        // HTTP/2 puts the host into the URI.
+        assert!(same_origin(
+            &headers("https://trmnl.local", "ignored"),
+            &http::Uri::from_static("https://trmnl.local/board/notes")
+        ));
+    }
+
+    #[tokio::test]
+    async fn board_update_reaches_only_boards_the_password_opens() {
+        let config =
+            std::env::temp_dir().join(format!("atrmnl_boards_serve_{}.toml", std::process::id()));
+        std::fs::write(
+            &config,
+            r#"
+[kitchen]
+mashup = { single = "notes" }
+
+[[kitchen.plugins]]
+board = { name = "notes", password = "fridge" }
+
+[garage]
+mashup = { single = "notes" }
+
+[[garage.plugins]]
+board = { name = "notes", password = "oven" }
+"#,
+        )
+        .expect("Failed to write config");
+        let storage = Arc::new(
+            storage::Storage::load(Some(config.clone()), plugins::catalog::Catalog::default())
+                .await
+                .expect("Failed to load storage"),
+        );
+        std::fs::remove_file(&config).expect("Failed to remove config");
+
+        let signed_in = |credentials: &'static str| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                credentials
+                    .parse()
+                    .expect("Hardcoded header value is valid"),
+            );
+            headers
+        };
+        let update = plugins::board::Update {
+            action: "post".into(),
+            author: "Mum".into(),
+            text: "Back at six".into(),
+            ..plugins::board::Update::default()
+        };
+        // "mum:fridge"
+        let response = board_update(
+            State(storage.clone()),
+            Path("notes".into()),
+            http::Uri::from_static("/board/notes"),
+            signed_in("Basic bXVtOmZyaWRnZQ=="),
+            Form(update),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        // "dad:oven"
+        let page = board_page(
+            State(storage.clone()),
+            Path("notes".into()),
+            signed_in("Basic ZGFkOm92ZW4="),
+        )
+        .await
+        .map(|html| html.0)
+        .unwrap_or_default();
+        assert!(!page.is_empty());
+        assert!(!page.contains("Back at six"));
+        for (device, posted) in [("kitchen", true), ("garage", false)] {
+            let screen = storage
+                .content_generator(device)
+                .expect("Content generator exists")
+                .generate(&storage.render_context(device))
+                .await
+                .expect("Board renders");
+            assert_eq!(screen.contains("Back at six"), posted);
+        }
+    }
+
+    #[test]
+    fn plugins_page_without_catalog() {
+        let html = PluginsTemplate {
+            catalog: &plugins::catalog::Catalog::default(),
+        }
+        .render_once()
+        .expect("plugins template render failed");
+        assert!(html.contains("No plugins installed"));
+    }
+
     #[test]
     fn embedded_file_known_asset() {
         let response = EmbeddedFile("style.css").into_response();
@@ -190,7 +508,7 @@ pub struct ApiResponse {
 
 #[allow(clippy::unused_async)]
 pub async fn api_display(
-    State(_storage): State<Arc<storage::Storage>>,
+    State(storage): State<Arc<storage::Storage>>,
     headers: http::header::HeaderMap,
     device: device::Info,
 ) -> axum::response::Result<axum::response::Json<ApiResponse>> {
@@ -210,10 +528,208 @@ pub async fn api_display(
             .fully_qualified_url()
             .as_str()
             .to_owned(),
-        refresh_rate: 1800,
+        refresh_rate: storage
+            .content_generator(&device.id)
+            .ok()
+            .and_then(Content::refresh_interval)
+            .map_or(DEFAULT_REFRESH_RATE, |interval| interval.as_secs()),
     }))
 }
 
+// A payload checked by the plugin it was sent to, ready to be stored.
+enum Delivery<'a> {
+    Push(&'a plugins::push::PushPlugin, plugins::push::Payload),
+    Sensor(&'a plugins::sensor::SensorPlugin, plugins::sensor::Reading),
+}
+
+// Hands a payload to every push or sensor plugin configured as `name` whose
+// token the request carries. Nothing is stored unless all of them accept it.
+#[allow(clippy::unused_async)]
+async fn push_data(
+    State(storage): State<Arc<storage::Storage>>,
+    Path(name): Path<String>,
+    headers: http::header::HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, Canonical> {
+    let authorization = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let now = chrono::Utc::now();
+    let mut found = false;
+    let mut deliveries = Vec::new();
+    for plugin in storage.plugins_named(&name) {
+        if let Some(push) = plugin.as_push() {
+            found = true;
+            if push.authorized(authorization) {
+                let payload = push
+                    .accept(&body, now)
+                    .inspect_err(|e| warn!("Rejected data pushed to {name}: {e}"))?;
+                deliveries.push(Delivery::Push(push, payload));
+            }
+        } else if let Some(sensor) = plugin.as_sensor() {
+            found = true;
+            if sensor.authorized(authorization) {
+                let reading = plugins::sensor::SensorPlugin::accept(&body, now)
+                    .inspect_err(|e| warn!("Rejected reading sent to {name}: {e}"))?;
+                deliveries.push(Delivery::Sensor(sensor, reading));
+            }
+        }
+    }
+    if !found {
+        return Err(Canonical::NotFound);
+    }
+    if deliveries.is_empty() {
+        warn!("Rejected data pushed to {name}: the request does not carry a token of the plugin");
+        return Err(Canonical::Unauthenticated);
+    }
+    let mut result = Ok(StatusCode::NO_CONTENT);
+    for delivery in deliveries {
+        let stored = match delivery {
+            Delivery::Push(push, payload) => push
+                .store(payload)
+                .inspect_err(|e| error!("Storing data pushed to {name}: {e}"))
+                .map_err(Canonical::from),
+            Delivery::Sensor(sensor, reading) => sensor
+                .store(reading, now)
+                .inspect_err(|e| error!("Storing reading sent to {name}: {e}"))
+                .map_err(Canonical::from),
+        };
+        if let Err(e) = stored {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+enum BoardRejection {
+    // Asks the browser for the board's password.
+    Challenge,
+    Canonical(Canonical),
+}
+
+impl<E: Into<Canonical>> From<E> for BoardRejection {
+    fn from(value: E) -> Self {
+        Self::Canonical(value.into())
+    }
+}
+
+impl IntoResponse for BoardRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Challenge => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, r#"Basic realm="Message board""#)],
+                pages::error("Who goes there?", "Sign in to use the message board."),
+            )
+                .into_response(),
+            Self::Canonical(e) => e.into_response(),
+        }
+    }
+}
+
+// The board plugins configured as `name` whose password the request carries.
+// Devices may give boards of the same name different passwords.
+fn authorized_boards<'a>(
+    storage: &'a storage::Storage,
+    name: &'a str,
+    headers: &http::header::HeaderMap,
+) -> Result<Vec<&'a plugins::board::BoardPlugin>, BoardRejection> {
+    let authorization = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let boards: Vec<_> = storage
+        .plugins_named(name)
+        .filter_map(|p| p.as_board())
+        .collect();
+    if boards.is_empty() {
+        return Err(Canonical::NotFound.into());
+    }
+    let authorized: Vec<_> = boards
+        .into_iter()
+        .filter(|board| board.authorized(authorization))
+        .collect();
+    if authorized.is_empty() {
+        Err(BoardRejection::Challenge)
+    } else {
+        Ok(authorized)
+    }
+}
+
+// Browsers send `Origin` with every form post. Since they also resend stored
+// Basic credentials, a foreign origin means another site submitted the form.
+fn same_origin(headers: &http::header::HeaderMap, uri: &http::Uri) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    // HTTP/2 requests carry the host in the URI instead of a `Host` header.
+    let host = uri
+        .authority()
+        .map(http::uri::Authority::as_str)
+        .or_else(|| {
+            headers
+                .get(header::HOST)
+                .and_then(|value| value.to_str().ok())
+        });
+    origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .zip(host)
+        .is_some_and(|((_, authority), host)| authority.eq_ignore_ascii_case(host))
+}
+
+// Shows the messages of the first device's board the password opens.
+#[allow(clippy::unused_async)]
+async fn board_page(
+    State(storage): State<Arc<storage::Storage>>,
+    Path(name): Path<String>,
+    headers: http::header::HeaderMap,
+) -> Result<Html<String>, BoardRejection> {
+    let now = chrono::Utc::now();
+    let boards = authorized_boards(&storage, &name, &headers)?;
+    Ok(pages::board(&name, &boards[0].messages(now), now))
+}
+
+// Applies a submission of the board page to every board the password opens.
+// A post gets the same id on all of them; pinning and deleting affect the
+// boards that hold the message. Screens are rendered when a device polls, so
+// there is no cached image to invalidate; the change shows up on the next
+// refresh.
+#[allow(clippy::unused_async)]
+async fn board_update(
+    State(storage): State<Arc<storage::Storage>>,
+    Path(name): Path<String>,
+    uri: http::Uri,
+    headers: http::header::HeaderMap,
+    Form(update): Form<plugins::board::Update>,
+) -> Result<Redirect, BoardRejection> {
+    if !same_origin(&headers, &uri) {
+        warn!("Rejected an update of the message board {name} from another site");
+        return Err(Canonical::PermissionDenied.into());
+    }
+    let boards = authorized_boards(&storage, &name, &headers)?;
+    let now = chrono::Utc::now();
+    let change = update
+        .change(now)
+        .inspect_err(|e| warn!("Updating the message board {name}: {e}"))?;
+    let mut applied = false;
+    for board in boards {
+        match board.apply(&change, now) {
+            Ok(()) => applied = true,
+            Err(plugins::board::Error::NotFound(_)) => {}
+            Err(e) => {
+                warn!("Updating the message board {name}: {e}");
+                return Err(e.into());
+            }
+        }
+    }
+    if applied {
+        Ok(Redirect::to(&format!("/board/{name}")))
+    } else {
+        Err(Canonical::NotFound.into())
+    }
+}
+
 #[axum::debug_handler]
 async fn render_screen_img(
     State(server): State<ServerState>,
@@ -238,25 +754,44 @@ async fn screen_content(
     let content = storage
         .content_generator(&device.id)
         .inspect(|_| debug!("Content found"))?;
-    Ok(pages::screen(&content.generate().await?))
+    let ctx = storage.render_context(&device.id);
+    Ok(pages::screen(&content.generate(&ctx).await?))
 }
 
 #[derive(TemplateOnce)]
 #[template(path = "serve/preview.stpl")]
 struct PreviewTemplate<'a> {
     image_url: &'a str,
+    reloads: Option<u64>,
 }
 
 #[allow(clippy::unused_async)]
-async fn preview(_: State<Arc<storage::Storage>>, device: device::Info) -> Html<String> {
+async fn preview(State(reloads): State<Option<Reloads>>, device: device::Info) -> Html<String> {
     let inner = PreviewTemplate {
         image_url: device.image_url.as_href(),
+        reloads: reloads.map(|r| *r.borrow()),
     }
     .render_once()
     .expect("preview template render failed");
     pages::index(&inner)
 }
 
+#[derive(TemplateOnce)]
+#[template(path = "serve/plugins.stpl")]
+struct PluginsTemplate<'a> {
+    catalog: &'a plugins::catalog::Catalog,
+}
+
+#[allow(clippy::unused_async)]
+async fn installed_plugins(State(storage): State<Arc<storage::Storage>>) -> Html<String> {
+    let inner = PluginsTemplate {
+        catalog: storage.catalog(),
+    }
+    .render_once()
+    .expect("plugins template render failed");
+    pages::index(&inner)
+}
+
 #[derive(Embed)]
 #[folder = "assets/"]
 struct Assets;
@@ -280,8 +815,24 @@ where
     }
 }
 
+// Long poll used by the preview page in dev mode: answers once a plugin module
+// was reloaded after `after`, or with the unchanged count after a while.
+async fn dev_reloads(
+    State(reloads): State<Option<Reloads>>,
+    Path(after): Path<u64>,
+) -> Result<String, Canonical> {
+    let mut reloads = reloads.ok_or(Canonical::NotFound)?;
+    let _ = tokio::time::timeout(
+        DEV_RELOADS_WAIT,
+        reloads.wait_for(|generation| *generation > after),
+    )
+    .await;
+    Ok(reloads.borrow().to_string())
+}
+
 #[derive(FromRef, Clone)]
 pub struct ServerState {
     pub renderer: Arc<blender::Instance>,
     pub storage: Arc<storage::Storage>,
+    pub reloads: Option<Reloads>,
 }
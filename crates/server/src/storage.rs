@@ -1,8 +1,13 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
+use itertools::Itertools;
 use log::debug;
 
-use crate::{generator, plugins, resource::Resource};
+use crate::{
+    generator::{self, RenderContext},
+    plugins,
+    resource::Resource,
+};
 
 #[derive(Clone, Debug)]
 pub struct Device {
@@ -12,16 +17,20 @@ pub struct Device {
 
 pub struct Storage {
     devices: HashMap<String, ondisk::Device>,
+    catalog: plugins::catalog::Catalog,
 }
 
 pub type LoadError = ondisk::Error;
 
 impl Storage {
-    pub async fn load(path: Option<PathBuf>) -> Result<Self, LoadError> {
-        let devices = ondisk::load_local(path).await?;
+    pub async fn load(
+        path: Option<PathBuf>,
+        catalog: plugins::catalog::Catalog,
+    ) -> Result<Self, LoadError> {
+        let devices = ondisk::load_local(path, &catalog).await?;
         debug!("Loaded {} devices", devices.len());
         debug!("Devices: {devices:#?}");
-        Ok(Self { devices })
+        Ok(Self { devices, catalog })
     }
 
     pub fn device_by_id(&self, id: &str) -> Option<Device> {
@@ -49,11 +58,47 @@ impl Storage {
             .and_then(|d| d.content_source.as_local())
             .ok_or(generator::SetupError::Missing)
     }
+
+    pub fn plugins(&self) -> impl Iterator<Item = &Arc<plugins::Plugin>> {
+        self.devices.values().flat_map(|d| d.plugins.values())
+    }
+
+    // The plugins configured as `name`, one per device using it.
+    pub fn plugins_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Arc<plugins::Plugin>> {
+        self.devices
+            .iter()
+            .sorted_by_key(|(id, _)| id.as_str())
+            .filter_map(move |(_, d)| d.plugins.get(name))
+    }
+
+    pub fn device_plugins(&self) -> impl Iterator<Item = (RenderContext, &Arc<plugins::Plugin>)> {
+        self.devices.iter().flat_map(move |(id, d)| {
+            d.plugins
+                .values()
+                .map(move |plugin| (self.render_context(id), plugin))
+        })
+    }
+
+    pub const fn catalog(&self) -> &plugins::catalog::Catalog {
+        &self.catalog
+    }
+
+    pub fn render_context(&self, id: &str) -> RenderContext {
+        let ctx = RenderContext::new(id);
+        match self.devices.get(id) {
+            Some(d) => ctx.with_region(d.timezone.as_deref(), d.locale.as_deref()),
+            None => ctx,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{generator::Content, plugins::catalog::Catalog};
     use std::io::Write;
 
     fn write_temp_config(content: &str) -> std::path::PathBuf {
@@ -79,7 +124,7 @@ mashup = { none = "https://example.com/screen" }
 plugins = []
 "#;
         let path = write_temp_config(cfg);
-        let storage = Storage::load(Some(path.clone()))
+        let storage = Storage::load(Some(path.clone()), Catalog::default())
             .await
             .expect("Failed to load storage");
         std::fs::remove_file(&path).expect("Failed to remove temp file");
@@ -99,7 +144,7 @@ mashup = { single = "test" }
 plugins = ["test_screen"]
 "#;
         let path = write_temp_config(cfg);
-        let storage = Storage::load(Some(path.clone()))
+        let storage = Storage::load(Some(path.clone()), Catalog::default())
             .await
             .expect("Failed to load storage");
         std::fs::remove_file(&path).expect("Failed to remove temp file");
@@ -112,6 +157,125 @@ plugins = ["test_screen"]
         assert!(generator.is_ok());
     }
 
+    #[tokio::test]
+    async fn storage_render_context_uses_device_region() {
+        let cfg = r#"
+[mydevice]
+mashup = { single = "test" }
+plugins = ["test_screen"]
+timezone = "Europe/Zurich"
+locale = "de-CH"
+"#;
+        let path = write_temp_config(cfg);
+        let storage = Storage::load(Some(path.clone()), Catalog::default())
+            .await
+            .expect("Failed to load storage");
+        std::fs::remove_file(&path).expect("Failed to remove temp file");
+
+        let ctx = storage.render_context("mydevice");
+        assert_eq!(ctx.device, "mydevice");
+        assert_eq!(ctx.timezone, "Europe/Zurich");
+        assert_eq!(ctx.locale, "de-CH");
+    }
+
+    #[tokio::test]
+    async fn storage_load_quadrants_mashup() {
+        let cfg = r#"
+[mydevice]
+mashup = { quadrants = ["test", "test", "test", "test"] }
+plugins = ["test_screen"]
+"#;
+        let path = write_temp_config(cfg);
+        let storage = Storage::load(Some(path.clone()), Catalog::default())
+            .await
+            .expect("Failed to load storage");
+        std::fs::remove_file(&path).expect("Failed to remove temp file");
+
+        let generator = storage
+            .content_generator("mydevice")
+            .expect("Content generator exists");
+        assert!(matches!(generator, plugins::mashup::Mashup::Quadrants(_)));
+    }
+
+    #[tokio::test]
+    async fn storage_load_quadrants_unknown_plugin() {
+        let cfg = r#"
+[mydevice]
+mashup = { quadrants = ["test", "test", "test", "nope"] }
+plugins = ["test_screen"]
+"#;
+        let path = write_temp_config(cfg);
+        let result = Storage::load(Some(path.clone()), Catalog::default()).await;
+        std::fs::remove_file(&path).expect("Failed to remove temp file");
+        assert!(matches!(result, Err(LoadError::UnknownPlugin(p)) if p == "nope"));
+    }
+
+    #[tokio::test]
+    async fn storage_load_installed_plugin() {
+        let dir = std::env::temp_dir().join(format!("atrmnl_installed_{}", std::process::id()));
+        let plugin_dir = dir.join("echo");
+        std::fs::create_dir_all(&plugin_dir).expect("Failed to create plugin folder");
+        std::fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/plugins/echo.wat"),
+            plugin_dir.join("echo.wat"),
+        )
+        .expect("Failed to copy module");
+        std::fs::write(
+            plugin_dir.join("plugin.toml"),
+            r#"
+name = "echo"
+version = "0.1.0"
+entry = "echo.wat"
+config.greeting = { type = "string", default = "hi" }
+"#,
+        )
+        .expect("Failed to write manifest");
+        let catalog = Catalog::discover(&dir).expect("Plugins are discovered");
+
+        let path = write_temp_config(
+            r#"
+[mydevice]
+mashup = { single = "echo" }
+
+[[mydevice.plugins]]
+installed = { plugin = "echo" }
+"#,
+        );
+        let storage = Storage::load(Some(path.clone()), catalog)
+            .await
+            .expect("Failed to load storage");
+        std::fs::remove_file(&path).expect("Failed to remove temp file");
+        std::fs::remove_dir_all(&dir).expect("Failed to remove plugins dir");
+
+        let content = storage
+            .content_generator("mydevice")
+            .expect("Content generator exists")
+            .generate(&storage.render_context("mydevice"))
+            .await
+            .expect("Plugin renders");
+        assert!(content.contains(r#"<p>{"greeting":"hi"}</p>"#));
+        assert_eq!(storage.catalog().iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn storage_load_unknown_installed_plugin() {
+        let path = write_temp_config(
+            r#"
+[mydevice]
+mashup = { single = "echo" }
+
+[[mydevice.plugins]]
+installed = { plugin = "echo" }
+"#,
+        );
+        let result = Storage::load(Some(path.clone()), Catalog::default()).await;
+        std::fs::remove_file(&path).expect("Failed to remove temp file");
+        assert!(matches!(
+            result,
+            Err(LoadError::Catalog(plugins::catalog::Error::NotInstalled(p))) if p == "echo"
+        ));
+    }
+
     #[tokio::test]
     async fn storage_device_not_found() {
         let cfg = r#"
@@ -120,7 +284,7 @@ mashup = { none = "https://example.com" }
 plugins = []
 "#;
         let path = write_temp_config(cfg);
-        let storage = Storage::load(Some(path.clone()))
+        let storage = Storage::load(Some(path.clone()), Catalog::default())
             .await
             .expect("Failed to load storage");
         std::fs::remove_file(&path).expect("Failed to remove temp file");
@@ -136,7 +300,7 @@ mod ondisk {
     use log::error;
     use url::Url;
 
-    use crate::plugins::{self, PluginsMap, mashup::Mashup};
+    use crate::plugins::{self, PluginsMap, catalog::Catalog, mashup::Mashup};
 
     #[derive(Debug, thiserror::Error)]
     pub enum Error {
@@ -152,6 +316,10 @@ mod ondisk {
         LoadConfig(#[from] toml::de::Error),
         #[error("unknown plugin: {0}")]
         UnknownPlugin(String),
+        #[error("failed to import the TRMNL plugin archive")]
+        Archive(#[from] plugins::template::archive::Error),
+        #[error("failed to set up an installed plugin")]
+        Catalog(#[from] plugins::catalog::Error),
     }
 
     #[derive(Debug, serde::Deserialize)]
@@ -169,12 +337,15 @@ mod ondisk {
         None(Url),
         Single(Plugin),
         LeftRight { left: Plugin, right: Plugin },
+        Quadrants([Plugin; 4]),
     }
 
     #[derive(serde::Deserialize)]
     struct DeviceConfig {
         mashup: MashupSpec,
         plugins: Vec<plugins::PluginConfig>,
+        timezone: Option<String>,
+        locale: Option<String>,
     }
 
     #[derive(Debug)]
@@ -194,7 +365,9 @@ mod ondisk {
 
     pub struct Device {
         pub content_source: ContentSource,
-        plugins: plugins::PluginsMap,
+        pub timezone: Option<String>,
+        pub locale: Option<String>,
+        pub plugins: plugins::PluginsMap,
     }
 
     impl Debug for Device {
@@ -202,11 +375,16 @@ mod ondisk {
             f.debug_struct("Device")
                 .field("plugins", &self.plugins.keys())
                 .field("content_source", &self.content_source)
+                .field("timezone", &self.timezone)
+                .field("locale", &self.locale)
                 .finish()
         }
     }
 
-    pub async fn load_local(path: Option<PathBuf>) -> Result<HashMap<String, Device>, Error> {
+    pub async fn load_local(
+        path: Option<PathBuf>,
+        catalog: &Catalog,
+    ) -> Result<HashMap<String, Device>, Error> {
         let path = path.unwrap_or_else(|| PathBuf::from("devices.toml"));
         let cfg = fs::read_to_string(&path).map_err(|source| Error::Read { path, source })?;
         let toml: HashMap<String, DeviceConfig> =
@@ -216,6 +394,9 @@ mod ondisk {
             let mut plugins = HashMap::new();
             for pluginspec in dinfo.plugins {
                 let plugin = pluginspec.to_key();
+                let pluginspec = catalog
+                    .resolve(pluginspec)
+                    .inspect_err(|e| error!("Configuring {plugin} for {id}: {e}"))?;
                 plugins.insert(
                     plugin.clone(),
                     Arc::new(
@@ -241,11 +422,27 @@ mod ondisk {
                         .ok_or_else(|| Error::UnknownPlugin(right.0.clone()))?;
                     ContentSource::Local(Mashup::LeftRight { left: l, right: r })
                 }
+                MashupSpec::Quadrants(quadrants) => {
+                    let resolve = |quadrant: Plugin| {
+                        quadrant
+                            .resolve(&plugins)
+                            .ok_or(Error::UnknownPlugin(quadrant.0))
+                    };
+                    let [a, b, c, d] = quadrants;
+                    ContentSource::Local(Mashup::Quadrants([
+                        resolve(a)?,
+                        resolve(b)?,
+                        resolve(c)?,
+                        resolve(d)?,
+                    ]))
+                }
             };
             devices.insert(
                 id,
                 Device {
                     content_source,
+                    timezone: dinfo.timezone,
+                    locale: dinfo.locale,
                     plugins,
                 },
             );
@@ -0,0 +1,23 @@
+// Helpers shared by the unit tests.
+
+use std::net::SocketAddr;
+
+use axum::Router;
+use url::Url;
+
+// Serves `router` on a free local port for the rest of the test.
+pub async fn serve(router: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind mock server");
+    let addr = listener
+        .local_addr()
+        .expect("Bound listener has an address");
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    addr
+}
+
+// The base URL of `router`, served on a free local port.
+pub async fn mock_server(router: Router) -> Url {
+    Url::parse(&format!("http://{}/", serve(router).await)).expect("Mock server URL is valid")
+}
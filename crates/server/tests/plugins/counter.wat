@@ -0,0 +1,22 @@
+;; Extism plugin used by the WASM plugin tests: `generate` counts its calls in
+;; a global and in linear memory and returns both counts as digits, so "11"
+;; means the call started from a fresh instance.
+(module
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+
+  (memory (export "memory") 1)
+  (global $calls (mut i32) (i32.const 0))
+
+  (func (export "generate") (result i32)
+    (local $out i64)
+    (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+    (i32.store8 (i32.const 0) (i32.add (i32.load8_u (i32.const 0)) (i32.const 1)))
+    (local.set $out (call $alloc (i64.const 2)))
+    (call $store_u8 (local.get $out) (i32.add (global.get $calls) (i32.const 48)))
+    (call $store_u8
+      (i64.add (local.get $out) (i64.const 1))
+      (i32.add (i32.load8_u (i32.const 0)) (i32.const 48)))
+    (call $output_set (local.get $out) (i64.const 2))
+    (i32.const 0)))
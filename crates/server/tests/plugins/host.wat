@@ -0,0 +1,66 @@
+;; Extism plugin used by the host function tests: every export forwards its
+;; input to the host function of the same name and returns the result as is.
+(module
+  (import "extism:host/env" "input_length" (func $input_length (result i64)))
+  (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+  (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+  (import "extism:host/user" "atrmnl_kv_get" (func $kv_get (param i64) (result i64)))
+  (import "extism:host/user" "atrmnl_kv_set" (func $kv_set (param i64)))
+  (import "extism:host/user" "atrmnl_kv_delete" (func $kv_delete (param i64)))
+  (import "extism:host/user" "atrmnl_context" (func $context (result i64)))
+  (import "extism:host/user" "atrmnl_log" (func $log (param i64)))
+  (import "extism:host/user" "atrmnl_fetch" (func $fetch (param i64) (result i64)))
+  (import "extism:host/user" "atrmnl_secret" (func $secret (param i64) (result i64)))
+
+  (memory (export "memory") 1)
+
+  ;; Copies the input into a fresh block of kernel memory.
+  (func $input (result i64)
+    (local $len i64)
+    (local $offset i64)
+    (local $i i64)
+    (local.set $len (call $input_length))
+    (local.set $offset (call $alloc (local.get $len)))
+    (block $done
+      (loop $next
+        (br_if $done (i64.ge_u (local.get $i) (local.get $len)))
+        (call $store_u8
+          (i64.add (local.get $offset) (local.get $i))
+          (call $input_load_u8 (local.get $i)))
+        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+        (br $next)))
+    (local.get $offset))
+
+  (func $emit (param $offset i64)
+    (call $output_set (local.get $offset) (call $length (local.get $offset))))
+
+  (func (export "kv_get") (result i32)
+    (call $emit (call $kv_get (call $input)))
+    (i32.const 0))
+
+  (func (export "kv_set") (result i32)
+    (call $kv_set (call $input))
+    (i32.const 0))
+
+  (func (export "kv_delete") (result i32)
+    (call $kv_delete (call $input))
+    (i32.const 0))
+
+  (func (export "context") (result i32)
+    (call $emit (call $context))
+    (i32.const 0))
+
+  (func (export "log") (result i32)
+    (call $log (call $input))
+    (i32.const 0))
+
+  (func (export "fetch") (result i32)
+    (call $emit (call $fetch (call $input)))
+    (i32.const 0))
+
+  (func (export "secret") (result i32)
+    (call $emit (call $secret (call $input)))
+    (i32.const 0)))
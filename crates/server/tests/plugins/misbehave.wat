@@ -0,0 +1,38 @@
+;; Extism plugin that violates resource limits, used by the WASM plugin tests.
+;; The config selects the violation by its first letter: "spin" loops forever,
+;; "grow" grows memory by 16 MiB and "fetch" requests http://192.0.2.1/.
+(module
+  (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/env" "http_request" (func $http_request (param i64 i64) (result i64)))
+
+  (memory 1)
+  (data (i32.const 0) "{\"url\":\"http://192.0.2.1/\"}")
+  (global $request_len i32 (i32.const 27))
+
+  (func $fetch
+    (local $request i64)
+    (local $i i32)
+    (local.set $request (call $alloc (i64.extend_i32_u (global.get $request_len))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (global.get $request_len)))
+        (call $store_u8
+          (i64.add (local.get $request) (i64.extend_i32_u (local.get $i)))
+          (i32.load8_u (local.get $i)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (drop (call $http_request (local.get $request) (i64.const 0))))
+
+  (func (export "generate") (result i32)
+    (local $mode i32)
+    ;; Skip the opening quote of the JSON string.
+    (local.set $mode (call $input_load_u8 (i64.const 1)))
+    (if (i32.eq (local.get $mode) (i32.const 115))
+      (then (loop $forever (br $forever))))
+    (if (i32.eq (local.get $mode) (i32.const 103))
+      (then (drop (memory.grow (i32.const 256)))))
+    (if (i32.eq (local.get $mode) (i32.const 102))
+      (then (call $fetch)))
+    (i32.const 0)))
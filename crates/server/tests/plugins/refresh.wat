@@ -0,0 +1,56 @@
+;; Extism plugin with a background entry point: `refresh` stores its config,
+;; which is a KV entry, and `generate` only returns the stored "data" value.
+(module
+  (import "extism:host/env" "input_length" (func $input_length (result i64)))
+  (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+  (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+  (import "extism:host/user" "atrmnl_kv_get" (func $kv_get (param i64) (result i64)))
+  (import "extism:host/user" "atrmnl_kv_set" (func $kv_set (param i64)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "data")
+
+  ;; Copies the input into a fresh block of kernel memory.
+  (func $input (result i64)
+    (local $len i64)
+    (local $offset i64)
+    (local $i i64)
+    (local.set $len (call $input_length))
+    (local.set $offset (call $alloc (local.get $len)))
+    (block $done
+      (loop $next
+        (br_if $done (i64.ge_u (local.get $i) (local.get $len)))
+        (call $store_u8
+          (i64.add (local.get $offset) (local.get $i))
+          (call $input_load_u8 (local.get $i)))
+        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+        (br $next)))
+    (local.get $offset))
+
+  ;; Copies the 4 byte key at address 0 into kernel memory.
+  (func $key (result i64)
+    (local $offset i64)
+    (local $i i32)
+    (local.set $offset (call $alloc (i64.const 4)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (i32.const 4)))
+        (call $store_u8
+          (i64.add (local.get $offset) (i64.extend_i32_u (local.get $i)))
+          (i32.load8_u (local.get $i)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (local.get $offset))
+
+  (func (export "refresh") (result i32)
+    (call $kv_set (call $input))
+    (i32.const 0))
+
+  (func (export "generate") (result i32)
+    (local $value i64)
+    (local.set $value (call $kv_get (call $key)))
+    (call $output_set (local.get $value) (call $length (local.get $value)))
+    (i32.const 0)))